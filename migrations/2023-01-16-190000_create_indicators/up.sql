@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE indicators (
+  base VARCHAR(20),
+  quote VARCHAR(20),
+  period INTEGER,
+  timestamp TIMESTAMPTZ,
+  ma_short REAL,
+  ma_med REAL,
+  ma_long REAL,
+  base_volume_med REAL,
+  volatility_med REAL,
+  PRIMARY KEY (base, quote, period, timestamp)
+)
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE shortlist DROP COLUMN stop_loss;
+ALTER TABLE trades DROP COLUMN stop_loss
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Your SQL goes here
+ALTER TABLE shortlist ADD COLUMN stop_loss REAL NOT NULL DEFAULT 0.005;
+ALTER TABLE trades ADD COLUMN stop_loss REAL NOT NULL DEFAULT 0.005
\ No newline at end of file
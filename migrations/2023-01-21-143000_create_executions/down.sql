@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE executions;
+ALTER TABLE trades DROP COLUMN remaining;
+ALTER TABLE trades DROP COLUMN take_profit_level;
+ALTER TABLE trades DROP COLUMN profit
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE executions (
+  id SERIAL PRIMARY KEY NOT NULL,
+  trade_id INTEGER NOT NULL REFERENCES trades(id),
+  executed_at TIMESTAMPTZ NOT NULL,
+  price REAL NOT NULL,
+  portion REAL NOT NULL,
+  reason VARCHAR(20) NOT NULL
+);
+ALTER TABLE trades ADD COLUMN remaining REAL NOT NULL DEFAULT 1.0;
+ALTER TABLE trades ADD COLUMN take_profit_level INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE trades ADD COLUMN profit REAL
\ No newline at end of file
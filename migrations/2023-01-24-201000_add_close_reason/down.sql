@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE trades DROP COLUMN close_reason
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE trades ADD COLUMN close_reason VARCHAR(20)
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE cooldowns (
+  quote VARCHAR(20) PRIMARY KEY NOT NULL,
+  losses INTEGER NOT NULL,
+  until TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+)
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE halts;
+ALTER TABLE trades DROP COLUMN size
\ No newline at end of file
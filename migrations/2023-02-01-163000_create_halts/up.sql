@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE halts (
+  id SERIAL PRIMARY KEY NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL,
+  reason VARCHAR NOT NULL,
+  until TIMESTAMPTZ,
+  reset_at TIMESTAMPTZ
+);
+ALTER TABLE trades ADD COLUMN size REAL NOT NULL DEFAULT 100.0
\ No newline at end of file
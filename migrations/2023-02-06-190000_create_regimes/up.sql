@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE regimes (
+  id SERIAL PRIMARY KEY NOT NULL,
+  timestamp TIMESTAMPTZ NOT NULL,
+  trend_average REAL,
+  trend_ma_long REAL,
+  breadth REAL,
+  quotes INTEGER NOT NULL,
+  regime VARCHAR(20) NOT NULL
+)
\ No newline at end of file
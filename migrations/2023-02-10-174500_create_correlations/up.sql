@@ -0,0 +1,9 @@
+-- Your SQL goes here
+CREATE TABLE correlations (
+  quote_a VARCHAR(20) NOT NULL,
+  quote_b VARCHAR(20) NOT NULL,
+  timestamp TIMESTAMPTZ NOT NULL,
+  correlation REAL NOT NULL,
+  samples INTEGER NOT NULL,
+  PRIMARY KEY (quote_a, quote_b)
+)
\ No newline at end of file
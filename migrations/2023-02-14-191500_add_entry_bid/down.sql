@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE trades DROP COLUMN entry_bid;
+ALTER TABLE trades DROP COLUMN entry_bid_at;
+ALTER TABLE trades DROP COLUMN entry_bids;
+ALTER TABLE trades DROP COLUMN entry_type
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+ALTER TABLE trades ADD COLUMN entry_bid REAL;
+ALTER TABLE trades ADD COLUMN entry_bid_at TIMESTAMPTZ;
+ALTER TABLE trades ADD COLUMN entry_bids INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE trades ADD COLUMN entry_type VARCHAR(10)
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE trades DROP COLUMN exit_reason;
+ALTER TABLE trades DROP COLUMN exit_started_at;
+ALTER TABLE trades DROP COLUMN exit_slices
\ No newline at end of file
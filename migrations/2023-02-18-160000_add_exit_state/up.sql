@@ -0,0 +1,4 @@
+-- Your SQL goes here
+ALTER TABLE trades ADD COLUMN exit_reason VARCHAR(20);
+ALTER TABLE trades ADD COLUMN exit_started_at TIMESTAMPTZ;
+ALTER TABLE trades ADD COLUMN exit_slices INTEGER NOT NULL DEFAULT 0
\ No newline at end of file
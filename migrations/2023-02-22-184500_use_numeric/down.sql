@@ -0,0 +1,31 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE candles
+  ALTER COLUMN high TYPE REAL,
+  ALTER COLUMN low TYPE REAL,
+  ALTER COLUMN open TYPE REAL,
+  ALTER COLUMN close TYPE REAL,
+  ALTER COLUMN average TYPE REAL,
+  ALTER COLUMN volume TYPE REAL;
+ALTER TABLE indicators
+  ALTER COLUMN ma_short TYPE REAL,
+  ALTER COLUMN ma_med TYPE REAL,
+  ALTER COLUMN ma_long TYPE REAL,
+  ALTER COLUMN base_volume_med TYPE REAL;
+ALTER TABLE regimes
+  ALTER COLUMN trend_average TYPE REAL,
+  ALTER COLUMN trend_ma_long TYPE REAL;
+ALTER TABLE shortlist
+  ALTER COLUMN average TYPE REAL,
+  ALTER COLUMN target TYPE REAL;
+ALTER TABLE trades
+  ALTER COLUMN open_average TYPE REAL,
+  ALTER COLUMN target TYPE REAL,
+  ALTER COLUMN open TYPE REAL,
+  ALTER COLUMN close TYPE REAL,
+  ALTER COLUMN highest_bid TYPE REAL,
+  ALTER COLUMN remaining TYPE REAL,
+  ALTER COLUMN size TYPE REAL,
+  ALTER COLUMN entry_bid TYPE REAL;
+ALTER TABLE executions
+  ALTER COLUMN price TYPE REAL,
+  ALTER COLUMN portion TYPE REAL
\ No newline at end of file
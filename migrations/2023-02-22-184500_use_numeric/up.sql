@@ -0,0 +1,32 @@
+-- Your SQL goes here
+-- prices and sizes as exact decimals, ratios stay REAL
+ALTER TABLE candles
+  ALTER COLUMN high TYPE NUMERIC,
+  ALTER COLUMN low TYPE NUMERIC,
+  ALTER COLUMN open TYPE NUMERIC,
+  ALTER COLUMN close TYPE NUMERIC,
+  ALTER COLUMN average TYPE NUMERIC,
+  ALTER COLUMN volume TYPE NUMERIC;
+ALTER TABLE indicators
+  ALTER COLUMN ma_short TYPE NUMERIC,
+  ALTER COLUMN ma_med TYPE NUMERIC,
+  ALTER COLUMN ma_long TYPE NUMERIC,
+  ALTER COLUMN base_volume_med TYPE NUMERIC;
+ALTER TABLE regimes
+  ALTER COLUMN trend_average TYPE NUMERIC,
+  ALTER COLUMN trend_ma_long TYPE NUMERIC;
+ALTER TABLE shortlist
+  ALTER COLUMN average TYPE NUMERIC,
+  ALTER COLUMN target TYPE NUMERIC;
+ALTER TABLE trades
+  ALTER COLUMN open_average TYPE NUMERIC,
+  ALTER COLUMN target TYPE NUMERIC,
+  ALTER COLUMN open TYPE NUMERIC,
+  ALTER COLUMN close TYPE NUMERIC,
+  ALTER COLUMN highest_bid TYPE NUMERIC,
+  ALTER COLUMN remaining TYPE NUMERIC,
+  ALTER COLUMN size TYPE NUMERIC,
+  ALTER COLUMN entry_bid TYPE NUMERIC;
+ALTER TABLE executions
+  ALTER COLUMN price TYPE NUMERIC,
+  ALTER COLUMN portion TYPE NUMERIC
\ No newline at end of file
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE markets (
+  base VARCHAR(20) NOT NULL,
+  quote VARCHAR(20) NOT NULL,
+  price_precision INTEGER NOT NULL,
+  amount_precision INTEGER NOT NULL,
+  min_order NUMERIC NOT NULL,
+  is_frozen BOOLEAN NOT NULL,
+  post_only BOOLEAN NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (base, quote)
+)
@@ -0,0 +1,15 @@
+-- Your SQL goes here
+CREATE TABLE ticker_snapshots (
+  base VARCHAR(20) NOT NULL,
+  quote VARCHAR(20) NOT NULL,
+  timestamp TIMESTAMPTZ NOT NULL,
+  last NUMERIC NOT NULL,
+  lowest_ask NUMERIC NOT NULL,
+  highest_bid NUMERIC NOT NULL,
+  percent_change REAL NOT NULL,
+  base_volume NUMERIC NOT NULL,
+  quote_volume NUMERIC NOT NULL,
+  high_24hr NUMERIC NOT NULL,
+  low_24hr NUMERIC NOT NULL,
+  PRIMARY KEY (base, quote, timestamp)
+)
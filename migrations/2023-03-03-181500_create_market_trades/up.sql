@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE market_trades (
+  base VARCHAR(20) NOT NULL,
+  quote VARCHAR(20) NOT NULL,
+  trade_id VARCHAR(40) NOT NULL,
+  timestamp TIMESTAMPTZ NOT NULL,
+  is_buy BOOLEAN NOT NULL,
+  price NUMERIC NOT NULL,
+  size NUMERIC NOT NULL,
+  PRIMARY KEY (base, quote, trade_id)
+);
+CREATE INDEX market_trades_timestamp ON market_trades (base, quote, timestamp)
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+CREATE TABLE trend_verdicts (
+  base VARCHAR(20) NOT NULL,
+  quote VARCHAR(20) NOT NULL,
+  period INTEGER NOT NULL,
+  trend VARCHAR(20) NOT NULL,
+  timestamp TIMESTAMPTZ NOT NULL,
+  passed BOOLEAN NOT NULL,
+  PRIMARY KEY (base, quote, period, trend)
+)
\ No newline at end of file
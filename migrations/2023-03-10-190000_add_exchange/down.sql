@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM candles WHERE exchange <> 'poloniex';
+ALTER TABLE candles DROP CONSTRAINT candles_pkey;
+ALTER TABLE candles DROP COLUMN exchange;
+ALTER TABLE candles ADD PRIMARY KEY (base, quote, period, timestamp);
+DELETE FROM indicators WHERE exchange <> 'poloniex';
+ALTER TABLE indicators DROP CONSTRAINT indicators_pkey;
+ALTER TABLE indicators DROP COLUMN exchange;
+ALTER TABLE indicators ADD PRIMARY KEY (base, quote, period, timestamp);
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+ALTER TABLE candles ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE candles DROP CONSTRAINT candles_pkey;
+ALTER TABLE candles ADD PRIMARY KEY (exchange, base, quote, period, timestamp);
+ALTER TABLE indicators ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE indicators DROP CONSTRAINT indicators_pkey;
+ALTER TABLE indicators ADD PRIMARY KEY (exchange, base, quote, period, timestamp);
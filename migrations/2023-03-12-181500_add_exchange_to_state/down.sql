@@ -0,0 +1,34 @@
+-- This file should undo anything in `up.sql`
+DELETE FROM markets WHERE exchange <> 'poloniex';
+ALTER TABLE markets DROP CONSTRAINT markets_pkey;
+ALTER TABLE markets DROP COLUMN exchange;
+ALTER TABLE markets ADD PRIMARY KEY (base, quote);
+DELETE FROM ticker_snapshots WHERE exchange <> 'poloniex';
+ALTER TABLE ticker_snapshots DROP CONSTRAINT ticker_snapshots_pkey;
+ALTER TABLE ticker_snapshots DROP COLUMN exchange;
+ALTER TABLE ticker_snapshots ADD PRIMARY KEY (base, quote, timestamp);
+DELETE FROM market_trades WHERE exchange <> 'poloniex';
+ALTER TABLE market_trades DROP CONSTRAINT market_trades_pkey;
+ALTER TABLE market_trades DROP COLUMN exchange;
+ALTER TABLE market_trades ADD PRIMARY KEY (base, quote, trade_id);
+DELETE FROM shortlist WHERE exchange <> 'poloniex';
+ALTER TABLE shortlist DROP CONSTRAINT shortlist_pkey;
+ALTER TABLE shortlist DROP COLUMN exchange;
+ALTER TABLE shortlist ADD PRIMARY KEY (quote);
+DELETE FROM trend_verdicts WHERE exchange <> 'poloniex';
+ALTER TABLE trend_verdicts DROP CONSTRAINT trend_verdicts_pkey;
+ALTER TABLE trend_verdicts DROP COLUMN exchange;
+ALTER TABLE trend_verdicts ADD PRIMARY KEY (base, quote, period, trend);
+DELETE FROM correlations WHERE exchange <> 'poloniex';
+ALTER TABLE correlations DROP CONSTRAINT correlations_pkey;
+ALTER TABLE correlations DROP COLUMN exchange;
+ALTER TABLE correlations ADD PRIMARY KEY (quote_a, quote_b);
+DELETE FROM cooldowns WHERE exchange <> 'poloniex';
+ALTER TABLE cooldowns DROP CONSTRAINT cooldowns_pkey;
+ALTER TABLE cooldowns DROP COLUMN exchange;
+ALTER TABLE cooldowns ADD PRIMARY KEY (quote);
+DELETE FROM executions WHERE trade_id IN (SELECT id FROM trades WHERE exchange <> 'poloniex');
+DELETE FROM trades WHERE exchange <> 'poloniex';
+ALTER TABLE trades DROP COLUMN exchange;
+DELETE FROM regimes WHERE exchange <> 'poloniex';
+ALTER TABLE regimes DROP COLUMN exchange;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+ALTER TABLE markets ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE markets DROP CONSTRAINT markets_pkey;
+ALTER TABLE markets ADD PRIMARY KEY (exchange, base, quote);
+ALTER TABLE ticker_snapshots ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE ticker_snapshots DROP CONSTRAINT ticker_snapshots_pkey;
+ALTER TABLE ticker_snapshots ADD PRIMARY KEY (exchange, base, quote, timestamp);
+ALTER TABLE market_trades ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE market_trades DROP CONSTRAINT market_trades_pkey;
+ALTER TABLE market_trades ADD PRIMARY KEY (exchange, base, quote, trade_id);
+ALTER TABLE shortlist ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE shortlist DROP CONSTRAINT shortlist_pkey;
+ALTER TABLE shortlist ADD PRIMARY KEY (exchange, quote);
+ALTER TABLE trend_verdicts ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE trend_verdicts DROP CONSTRAINT trend_verdicts_pkey;
+ALTER TABLE trend_verdicts ADD PRIMARY KEY (exchange, base, quote, period, trend);
+ALTER TABLE correlations ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE correlations DROP CONSTRAINT correlations_pkey;
+ALTER TABLE correlations ADD PRIMARY KEY (exchange, quote_a, quote_b);
+ALTER TABLE cooldowns ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE cooldowns DROP CONSTRAINT cooldowns_pkey;
+ALTER TABLE cooldowns ADD PRIMARY KEY (exchange, quote);
+ALTER TABLE trades ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
+ALTER TABLE regimes ADD COLUMN exchange VARCHAR(20) NOT NULL DEFAULT 'poloniex';
@@ -0,0 +1,2 @@
+-- Your SQL goes here
+ALTER TABLE candles ADD COLUMN source VARCHAR(10) NOT NULL DEFAULT 'rest';
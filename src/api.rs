@@ -0,0 +1,80 @@
+use crate::chart_data::EXCHANGE_PERIODS;
+use crate::error::BotError;
+use crate::message;
+use crate::poloniex_v3::INTERVALS;
+use dotenv::dotenv;
+use std::env;
+
+/// Poloniex API the bot talks to, chosen per deployment with the
+/// `POLONIEX_API` environment variable
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Api {
+    /// `https://poloniex.com/public?command=...` and `wss://api2.poloniex.com`
+    Legacy,
+    /// Spot API at `https://api.poloniex.com` and
+    /// `wss://ws.poloniex.com/ws/public`
+    V3,
+}
+
+impl Api {
+    /// Reads the API from `POLONIEX_API`, "legacy" (the default) or "v3"
+    pub fn from_env() -> Result<Api, BotError> {
+        dotenv().ok();
+
+        match env::var("POLONIEX_API") {
+            Ok(value) => Api::parse(&value),
+            Err(_) => Ok(Api::Legacy),
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Api, BotError> {
+        match value {
+            "legacy" => Ok(Api::Legacy),
+            "v3" => Ok(Api::V3),
+            other => Err(BotError::Config(format!(
+                "POLONIEX_API must be legacy or v3, not {}",
+                other
+            ))),
+        }
+    }
+
+    /// Candle periods in seconds that the API provides
+    pub fn exchange_periods(&self) -> Vec<i32> {
+        match self {
+            Api::Legacy => EXCHANGE_PERIODS.to_vec(),
+            Api::V3 => INTERVALS.iter().map(|(period, _)| *period).collect(),
+        }
+    }
+}
+
+pub const LEGACY_REST_URL: &str = "https://poloniex.com/public";
+
+/// URL of the legacy REST API, `POLONIEX_REST_URL` overrides it, e.g. to
+/// point the bot at a mock server
+pub fn legacy_rest_url() -> String {
+    endpoint("POLONIEX_REST_URL", LEGACY_REST_URL)
+}
+
+/// URL of the legacy websocket API, `POLONIEX_WS_URL` overrides it
+pub fn legacy_ws_url() -> String {
+    endpoint("POLONIEX_WS_URL", message::API_URL)
+}
+
+fn endpoint(var: &str, default: &str) -> String {
+    dotenv().ok();
+
+    env::var(var).unwrap_or_else(|_| default.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_test() {
+        assert_eq!(Api::parse("v3").unwrap(), Api::V3);
+        assert!(Api::parse("v2").is_err());
+        assert!(!Api::Legacy.exchange_periods().contains(&3600));
+        assert!(Api::V3.exchange_periods().contains(&3600));
+    }
+}
@@ -0,0 +1,91 @@
+extern crate diesel;
+extern crate poloniex_bot;
+
+use std::collections::HashMap;
+
+use self::api::Api;
+use self::candle_builder::{
+    insert_market_trade, record_trade, to_market_trade, upsert_candle, CandleBuilder,
+    TRADE_CANDLE_PERIODS,
+};
+use self::error::BotError;
+use self::exchange::Exchange;
+use self::market::get_markets;
+use self::message::*;
+use self::poloniex_bot::*;
+use self::stream::{Channel, MarketStream, StreamMessage};
+
+// cargo run --bin collect_trades
+
+fn main() {
+    run_main(run);
+}
+
+/// Follows the public trades of all markets that aren't frozen, and stores
+/// them with the candles of `TRADE_CANDLE_PERIODS`
+///
+/// Only the Poloniex v3 API has candle channels, with the other exchanges
+/// the candles are built from the trades.
+fn run() -> Result<(), BotError> {
+    let connection = &mut establish_connection()?;
+    let exchange = Exchange::from_env()?;
+
+    let markets = get_markets(connection, exchange, BASE)?;
+    if markets.is_empty() {
+        return Err(BotError::Config(
+            "no markets found, run fetch_data first".to_string(),
+        ));
+    }
+
+    let channels: Vec<Channel> = match exchange {
+        Exchange::Poloniex(Api::Legacy) => vec![Channel::Book],
+        Exchange::Poloniex(Api::V3) => std::iter::once(Channel::Trades)
+            .chain(TRADE_CANDLE_PERIODS.iter().map(|p| Channel::Candles(*p)))
+            .collect(),
+        Exchange::Binance | Exchange::Kraken => vec![Channel::Trades],
+    };
+    let candle_channels = channels.iter().any(|c| matches!(c, Channel::Candles(_)));
+
+    let mut stream = MarketStream::connect(exchange)?;
+    for market in markets.iter() {
+        stream.subscribe(&market.base, &market.quote, &channels)?;
+    }
+    println!("subscribed to {} markets", markets.len());
+
+    let mut builders: HashMap<(String, String), CandleBuilder> = HashMap::new();
+
+    loop {
+        match stream.read()? {
+            StreamMessage::Events {
+                base,
+                quote,
+                events,
+            } => {
+                for event in events.into_iter() {
+                    match event {
+                        BookEvent::Trade(trade) if !candle_channels => {
+                            let builder = builders
+                                .entry((base.clone(), quote.clone()))
+                                .or_insert_with(|| CandleBuilder::new(exchange, &base, &quote));
+                            record_trade(connection, builder, &trade)?;
+                        }
+                        BookEvent::Trade(trade) => {
+                            if let Some(row) =
+                                to_market_trade(exchange.name(), &base, &quote, &trade)
+                            {
+                                insert_market_trade(connection, &row)?;
+                            }
+                        }
+                        BookEvent::Candle(candle) => {
+                            upsert_candle(connection, &candle)?;
+                        }
+                        BookEvent::Snapshot { .. } | BookEvent::Update { .. } => (),
+                    }
+                }
+            }
+            StreamMessage::Error(message) => println!("error from exchange: {}", message),
+            StreamMessage::Skipped(reason) => println!("skipping message: {}", reason),
+            StreamMessage::Other => (),
+        }
+    }
+}
@@ -1,22 +1,24 @@
 extern crate diesel;
 extern crate poloniex_bot;
 
+use self::error::BotError;
 use self::poloniex_bot::*;
 use self::trade_logic::do_trade;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+fn main() {
+    run_main(run);
+}
+
+fn run() -> Result<(), BotError> {
     let args = std::env::args().collect::<Vec<_>>();
-    let connection = &mut establish_connection();
+    let connection = &mut establish_connection()?;
 
-    let trade_id: i32 = match args.get(1) {
-        Some(id) => id.parse().unwrap(),
-        None => {
-            println!("Usage: {} <trade_id>", args[0]);
-            return Ok(());
-        }
+    let trade_id: i32 = match args.get(1).map(|id| id.parse()) {
+        Some(Ok(id)) => id,
+        _ => return Err(BotError::Config(format!("Usage: {} <trade_id>", args[0]))),
     };
 
-    do_trade(connection, trade_id.clone())?;
+    do_trade(connection, trade_id)?;
 
     println!("do_trade {} finished", trade_id);
     Ok(())
@@ -0,0 +1,22 @@
+extern crate diesel;
+extern crate poloniex_bot;
+
+use self::correlation::{correlation_matrix_csv, get_correlations};
+use self::error::BotError;
+use self::exchange::Exchange;
+use self::poloniex_bot::*;
+
+// cargo run --bin export_correlations > correlations.csv
+
+fn main() {
+    run_main(run);
+}
+
+fn run() -> Result<(), BotError> {
+    let connection = &mut establish_connection()?;
+
+    let rows = get_correlations(connection, Exchange::from_env()?)?;
+    print!("{}", correlation_matrix_csv(&rows));
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+extern crate diesel;
+extern crate poloniex_bot;
+
+use self::error::BotError;
+use self::poloniex_bot::*;
+use self::risk::{get_active_halt, reset_halts};
+
+// cargo run --bin reset_halt
+
+fn main() {
+    run_main(run);
+}
+
+fn run() -> Result<(), BotError> {
+    let connection = &mut establish_connection()?;
+
+    match get_active_halt(connection)? {
+        Some(halt) => println!("active halt since {}: {}", halt.created_at, halt.reason),
+        None => println!("no active halt"),
+    }
+
+    let count = reset_halts(connection)?;
+    println!("reset {} halts", count);
+
+    Ok(())
+}
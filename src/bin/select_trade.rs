@@ -5,14 +5,21 @@ use std::process::{Child, Command};
 use std::thread;
 use std::time::Duration;
 
+use self::error::BotError;
+use self::exchange::Exchange;
 use self::models::Trade;
 use self::poloniex_bot::*;
-use self::shortlist::*;
-use self::trade::*;
+use self::selection::select_trades;
+use self::trade::get_trades;
 use self::trade_logic::log_trade;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let connection = &mut establish_connection();
+fn main() {
+    run_main(run);
+}
+
+fn run() -> Result<(), BotError> {
+    let connection = &mut establish_connection()?;
+    let exchange = Exchange::from_env()?;
 
     let mut processes: Vec<(Trade, Child)> = vec![];
 
@@ -23,35 +30,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
         for (trade, mut process) in processes.into_iter() {
             log_trade(&trade, format!("killing process {}", process.id()));
-            process.kill()?;
+            // the process may have exited already
+            if let Err(e) = process.kill() {
+                log_trade(&trade, format!("killing process failed: {}", e));
+            }
         }
 
         processes = vec![];
 
         // restart all open trades
 
-        for trade in get_trades(connection)? {
-            let process = Command::new("./target/release/do_trade")
-                .arg(trade.id.to_string())
-                .spawn()
-                .expect("Failed to fork process for trade");
+        for trade in get_trades(connection, exchange)? {
+            let process = spawn_trade(&trade)?;
             log_trade(&trade, format!("reopening process {}", process.id()));
             processes.push((trade, process));
         }
 
         // start new trades from shortlist
 
-        for s in get_shortlist(connection).unwrap() {
-            if !is_trade_open(connection, &s).unwrap() {
-                let trade = create_trade(connection, &s).unwrap();
-                let process = Command::new("./target/release/do_trade")
-                    .arg(trade.id.to_string())
-                    .spawn()
-                    .expect("Failed to fork process for trade");
-                log_trade(&trade, format!("starting process {}", process.id()));
-                processes.push((trade, process));
-            }
+        let open_quotes: Vec<String> = processes.iter().map(|(t, _)| t.quote.clone()).collect();
+        for trade in select_trades(connection, exchange, &open_quotes)? {
+            let process = spawn_trade(&trade)?;
+            log_trade(&trade, format!("starting process {}", process.id()));
+            processes.push((trade, process));
         }
         thread::sleep(Duration::from_secs(120));
     }
 }
+
+fn spawn_trade(trade: &Trade) -> Result<Child, BotError> {
+    Command::new("./target/release/do_trade")
+        .arg(trade.id.to_string())
+        .spawn()
+        .map_err(|e| BotError::Config(format!("starting process for trade {}: {}", trade.id, e)))
+}
@@ -1,20 +1,35 @@
 extern crate diesel;
 extern crate poloniex_bot;
 
+use self::correlation::update_correlations;
+use self::error::BotError;
+use self::exchange::Exchange;
 use self::poloniex_bot::*;
-use self::shortlist_logic::{update_shortlist, update_trades};
+use self::regime::update_regime;
+use self::shortlist_logic::{
+    indicator_periods, update_indicators, update_shortlist, update_trades, PERIOD,
+};
 
 // cargo run --bin update_shortlist
 
-const PERIOD: i32 = 900;
 const BASE: &str = "USDT";
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let connection = &mut establish_connection();
+fn main() {
+    run_main(run);
+}
+
+fn run() -> Result<(), BotError> {
+    let connection = &mut establish_connection()?;
+    let exchange = Exchange::from_env()?;
     let period = PERIOD;
 
-    update_trades(connection, BASE.to_string(), period)?;
-    update_shortlist(connection, BASE.to_string(), period)?;
+    for indicator_period in indicator_periods() {
+        update_indicators(connection, exchange, BASE.to_string(), indicator_period)?;
+    }
+    update_regime(connection, exchange, BASE.to_string(), period)?;
+    update_trades(connection, exchange, BASE.to_string(), period)?;
+    update_shortlist(connection, exchange, BASE.to_string(), period)?;
+    update_correlations(connection, exchange, BASE.to_string(), period)?;
 
     Ok(())
 }
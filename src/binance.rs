@@ -0,0 +1,680 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::api::Api;
+use crate::chart_data::{ChartDataRequest, SOURCE_REST};
+use crate::decimal::{to_f64, DECIMAL_SCALE};
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
+use crate::fetcher::Fetcher;
+use crate::message::{BookEvent, PublicTrade};
+use crate::models::{Candle, Market, TickerSnapshot};
+use crate::order_book::{OrderBook, OrderType};
+
+pub const API_URL: &str = "https://api.binance.com";
+
+pub const WS_URL: &str = "wss://stream.binance.com:9443/ws";
+
+// the REST API allows 6000 request weight per minute, and the klines used
+// for most requests weigh 2
+pub const REQUESTS_PER_SECOND: f64 = 20.0;
+pub const BURST: f64 = 20.0;
+
+// candle periods in seconds and the names of their intervals
+pub const INTERVALS: &[(i32, &str)] = &[
+    (60, "1m"),
+    (180, "3m"),
+    (300, "5m"),
+    (900, "15m"),
+    (1800, "30m"),
+    (3600, "1h"),
+    (7200, "2h"),
+    (14400, "4h"),
+    (21600, "6h"),
+    (28800, "8h"),
+    (43200, "12h"),
+    (86400, "1d"),
+    (259200, "3d"),
+    (604800, "1w"),
+];
+
+// most candles returned for a request
+pub const MAX_CANDLES: i32 = 1000;
+
+// levels of each side in the order book snapshot
+pub const DEPTH_LIMIT: i32 = 1000;
+
+/// Name of the interval of a candle period
+pub fn interval(period: i32) -> Option<&'static str> {
+    INTERVALS
+        .iter()
+        .find(|(p, _)| *p == period)
+        .map(|(_, name)| *name)
+}
+
+fn timestamp_ms(ms: i64) -> Result<DateTime<Utc>, BotError> {
+    Utc.timestamp_millis_opt(ms)
+        .single()
+        .ok_or_else(|| BotError::Parse(format!("invalid timestamp {}", ms)))
+}
+
+/// Digits after the decimal point of a tick or step size, e.g. 2 for 0.01
+fn precision(step: &BigDecimal) -> i32 {
+    let (_, scale) = step.normalized().as_bigint_and_exponent();
+    scale.max(0) as i32
+}
+
+/// Trading rule of a symbol, only the ones used for markets are read
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "filterType")]
+pub enum SymbolFilter {
+    #[serde(rename = "PRICE_FILTER", rename_all = "camelCase")]
+    Price { tick_size: BigDecimal },
+    #[serde(rename = "LOT_SIZE", rename_all = "camelCase")]
+    LotSize { step_size: BigDecimal },
+    #[serde(rename = "NOTIONAL", rename_all = "camelCase")]
+    Notional { min_notional: BigDecimal },
+    #[serde(rename = "MIN_NOTIONAL", rename_all = "camelCase")]
+    MinNotional { min_notional: BigDecimal },
+    #[serde(other)]
+    Other,
+}
+
+/// Symbol in `/api/v3/exchangeInfo`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolInfo {
+    pub symbol: String,
+    /// TRADING, BREAK, ...
+    pub status: String,
+    /// Quote of the bot, e.g. BTC of BTCUSDT
+    pub base_asset: String,
+    /// Base of the bot, e.g. USDT of BTCUSDT
+    pub quote_asset: String,
+    pub filters: Vec<SymbolFilter>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExchangeInfo {
+    pub symbols: Vec<SymbolInfo>,
+}
+
+/// Market with the precisions, order limit and trading status of the symbol
+pub fn to_market(s: &SymbolInfo) -> Market {
+    let mut market = Market {
+        base: s.quote_asset.clone(),
+        quote: s.base_asset.clone(),
+        price_precision: 8,
+        amount_precision: 8,
+        min_order: BigDecimal::zero(),
+        is_frozen: s.status != "TRADING",
+        post_only: false,
+        updated_at: Utc::now(),
+        exchange: Exchange::Binance.name().to_string(),
+    };
+
+    for filter in s.filters.iter() {
+        match filter {
+            SymbolFilter::Price { tick_size } => market.price_precision = precision(tick_size),
+            SymbolFilter::LotSize { step_size } => market.amount_precision = precision(step_size),
+            SymbolFilter::Notional { min_notional }
+            | SymbolFilter::MinNotional { min_notional } => market.min_order = min_notional.clone(),
+            SymbolFilter::Other => (),
+        }
+    }
+
+    market
+}
+
+/// Fetches the markets of `base`
+pub fn return_markets(fetcher: &Fetcher, base: &str) -> Result<Vec<Market>, BotError> {
+    println!("Fetching markets");
+
+    let info: ExchangeInfo = fetcher
+        .get(
+            &format!("{}/api/v3/exchangeInfo", API_URL),
+            &[],
+            "fetching markets",
+        )?
+        .json()
+        .context("reading markets")?;
+
+    Ok(info
+        .symbols
+        .iter()
+        .filter(|s| s.quote_asset == base)
+        .map(to_market)
+        .collect())
+}
+
+/// 24 hour ticker in `/api/v3/ticker/24hr`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ticker24hr {
+    pub symbol: String,
+    /// Change in percents
+    pub price_change_percent: BigDecimal,
+    pub last_price: BigDecimal,
+    pub bid_price: BigDecimal,
+    pub ask_price: BigDecimal,
+    pub high_price: BigDecimal,
+    pub low_price: BigDecimal,
+    /// Traded volume in the quote currency of the bot
+    pub volume: BigDecimal,
+    /// Traded volume in the base currency of the bot
+    pub quote_volume: BigDecimal,
+}
+
+/// Snapshot row of the ticker, `None` if the symbol can't be parsed
+pub fn to_ticker_snapshot(t: &Ticker24hr, timestamp: DateTime<Utc>) -> Option<TickerSnapshot> {
+    let (base, quote) = Exchange::Binance.parse_symbol(&t.symbol)?;
+
+    Some(TickerSnapshot {
+        base,
+        quote,
+        timestamp,
+        last: t.last_price.clone(),
+        lowest_ask: t.ask_price.clone(),
+        highest_bid: t.bid_price.clone(),
+        percent_change: (to_f64(&t.price_change_percent) / 100.0) as f32,
+        base_volume: t.quote_volume.clone(),
+        quote_volume: t.volume.clone(),
+        high_24hr: t.high_price.clone(),
+        low_24hr: t.low_price.clone(),
+        exchange: Exchange::Binance.name().to_string(),
+    })
+}
+
+/// Fetches the 24 hour tickers of the markets of `base`
+pub fn return_ticker_snapshots(
+    fetcher: &Fetcher,
+    base: &str,
+    timestamp: DateTime<Utc>,
+) -> Result<Vec<TickerSnapshot>, BotError> {
+    println!("Fetching tickers");
+
+    let tickers: Vec<Ticker24hr> = fetcher
+        .get(
+            &format!("{}/api/v3/ticker/24hr", API_URL),
+            &[],
+            "fetching tickers",
+        )?
+        .json()
+        .context("reading tickers")?;
+
+    Ok(tickers
+        .iter()
+        .filter_map(|t| to_ticker_snapshot(t, timestamp))
+        .filter(|t| t.base == base)
+        .collect())
+}
+
+/// Candle in `/api/v3/klines`, an array of open time, open, high, low,
+/// close, volume, close time, quote asset volume, number of trades, taker
+/// buy base asset volume, taker buy quote asset volume and an unused field
+#[derive(Clone, Debug, Deserialize)]
+pub struct Kline(
+    pub i64,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub i64,
+    pub BigDecimal,
+    pub i64,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub String,
+);
+
+/// Candle of the chart data request, volume is the quote asset volume, which
+/// is in the base currency of the bot
+pub fn to_candle(request: &ChartDataRequest, k: Kline) -> Result<Candle, BotError> {
+    let average = if k.5.is_zero() {
+        k.4.clone()
+    } else {
+        (&k.7 / &k.5).round(DECIMAL_SCALE)
+    };
+
+    Ok(Candle {
+        base: request.base.clone(),
+        quote: request.quote.clone(),
+        period: request.period,
+        timestamp: timestamp_ms(k.0)?,
+        high: Some(k.2),
+        low: Some(k.3),
+        open: Some(k.1),
+        close: Some(k.4),
+        average: Some(average),
+        volume: Some(k.7),
+        exchange: Exchange::Binance.name().to_string(),
+        source: SOURCE_REST.to_string(),
+    })
+}
+
+/// Fetches the completed candles of the chart data request
+pub fn return_candles(
+    fetcher: &Fetcher,
+    request: &ChartDataRequest,
+) -> Result<Vec<Candle>, BotError> {
+    let name = interval(request.period).ok_or_else(|| {
+        BotError::Config(format!("no candle interval for period {}", request.period))
+    })?;
+    let what = format!("fetching klines for {} {}", request.quote, request.period);
+
+    let rows: Vec<Kline> = fetcher
+        .get(
+            &format!("{}/api/v3/klines", API_URL),
+            &[
+                (
+                    "symbol",
+                    Exchange::Binance
+                        .symbol(&request.base, &request.quote)
+                        .as_str(),
+                ),
+                ("interval", name),
+                ("startTime", (request.start * 1000).to_string().as_str()),
+                ("endTime", (request.end * 1000).to_string().as_str()),
+                ("limit", MAX_CANDLES.to_string().as_str()),
+            ],
+            &what,
+        )?
+        .json()
+        .context(&format!("reading klines for {}", request.quote))?;
+
+    rows.into_iter()
+        // the candle in progress
+        .filter(|k| k.6 < request.end * 1000)
+        .map(|k| to_candle(request, k))
+        .collect()
+}
+
+/// Order book in `/api/v3/depth`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DepthSnapshot {
+    pub last_update_id: i64,
+    pub bids: Vec<(BigDecimal, BigDecimal)>,
+    pub asks: Vec<(BigDecimal, BigDecimal)>,
+}
+
+/// Fetches the order book of `symbol`
+pub fn return_depth(fetcher: &Fetcher, symbol: &str) -> Result<DepthSnapshot, BotError> {
+    fetcher
+        .get(
+            &format!("{}/api/v3/depth", API_URL),
+            &[
+                ("symbol", symbol),
+                ("limit", DEPTH_LIMIT.to_string().as_str()),
+            ],
+            &format!("fetching order book of {}", symbol),
+        )?
+        .json()
+        .context(&format!("reading order book of {}", symbol))
+}
+
+/// Request sent to the websocket API
+#[derive(Serialize, Debug)]
+pub struct WsRequest {
+    pub method: String,
+    pub params: Vec<String>,
+    pub id: u32,
+}
+
+/// Stream of the order book updates of a symbol
+pub fn depth_stream(symbol: &str) -> String {
+    format!("{}@depth@100ms", symbol.to_lowercase())
+}
+
+/// Stream of the trades of a symbol
+pub fn trade_stream(symbol: &str) -> String {
+    format!("{}@trade", symbol.to_lowercase())
+}
+
+/// Levels of the order book changed between the updates `first_id` and
+/// `last_id`, size of zero removes the level
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DepthUpdate {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "U")]
+    pub first_id: i64,
+    #[serde(rename = "u")]
+    pub last_id: i64,
+    #[serde(rename = "b")]
+    pub bids: Vec<(BigDecimal, BigDecimal)>,
+    #[serde(rename = "a")]
+    pub asks: Vec<(BigDecimal, BigDecimal)>,
+}
+
+/// Trade in the trade stream
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TradeEvent {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "t")]
+    pub id: i64,
+    #[serde(rename = "p")]
+    pub price: BigDecimal,
+    #[serde(rename = "q")]
+    pub quantity: BigDecimal,
+    /// Unix time in milliseconds
+    #[serde(rename = "T")]
+    pub time: i64,
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+}
+
+/// Public trade of the trade event
+pub fn to_public_trade(t: TradeEvent) -> PublicTrade {
+    PublicTrade {
+        id: t.id.to_string(),
+        // the seller took the bid if the buyer was the maker
+        is_buy: !t.is_buyer_maker,
+        price: t.price,
+        size: t.quantity,
+        timestamp: t.time.div_euclid(1000),
+    }
+}
+
+/// Message pushed by the websocket API
+#[derive(Clone, Debug, PartialEq)]
+pub enum WsMessage {
+    /// `{"e": "depthUpdate", ...}`
+    Depth(DepthUpdate),
+    /// `{"e": "trade", ...}`
+    Trade(TradeEvent),
+    /// `{"result": null, "id": <request id>}`
+    Response(u32),
+    /// `{"code": <code>, "msg": "<message>"}`
+    Error(String),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "e")]
+enum RawEvent {
+    #[serde(rename = "depthUpdate")]
+    Depth(DepthUpdate),
+    #[serde(rename = "trade")]
+    Trade(TradeEvent),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawWsMessage {
+    Event(RawEvent),
+    Error { msg: String },
+    Response { id: u32 },
+}
+
+/// Parses a websocket text frame
+pub fn parse_ws_message(input: &str) -> Result<WsMessage, BotError> {
+    let raw: RawWsMessage = serde_json::from_str(input)
+        .map_err(|_| BotError::Parse(format!("unknown message {}", input)))?;
+
+    Ok(match raw {
+        RawWsMessage::Event(RawEvent::Depth(update)) => WsMessage::Depth(update),
+        RawWsMessage::Event(RawEvent::Trade(trade)) => WsMessage::Trade(trade),
+        RawWsMessage::Error { msg } => WsMessage::Error(msg),
+        RawWsMessage::Response { id } => WsMessage::Response(id),
+    })
+}
+
+/// Keeps an order book built from a snapshot in sync with the updates of the
+/// depth stream
+///
+/// Updates older than the snapshot are skipped. The first update applied
+/// must include the update after the snapshot, and each update after that
+/// must start where the previous one ended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DepthSync {
+    last_id: i64,
+    synced: bool,
+}
+
+impl DepthSync {
+    pub fn new(snapshot: &DepthSnapshot) -> DepthSync {
+        DepthSync {
+            last_id: snapshot.last_update_id,
+            synced: false,
+        }
+    }
+
+    /// Whether the update should be applied to the order book, fails if
+    /// updates have been missed and a new snapshot is needed
+    pub fn accept(&mut self, update: &DepthUpdate) -> Result<bool, BotError> {
+        if update.last_id <= self.last_id {
+            return Ok(false);
+        }
+
+        let follows = if self.synced {
+            update.first_id == self.last_id + 1
+        } else {
+            update.first_id <= self.last_id + 1
+        };
+        if !follows {
+            return Err(BotError::Parse(format!(
+                "{} update {} doesn't follow {}",
+                update.symbol, update.first_id, self.last_id
+            )));
+        }
+
+        self.last_id = update.last_id;
+        self.synced = true;
+        Ok(true)
+    }
+}
+
+/// Order books of the subscribed symbols built from REST snapshots and kept
+/// in sync with the depth stream
+#[derive(Debug, Default)]
+pub struct DepthTracker {
+    books: HashMap<String, (OrderBook, DepthSync)>,
+}
+
+impl DepthTracker {
+    pub fn new() -> DepthTracker {
+        DepthTracker::default()
+    }
+
+    /// Whether the symbol has an order book that updates can be applied to
+    pub fn has_book(&self, symbol: &str) -> bool {
+        self.books.contains_key(symbol)
+    }
+
+    /// Replaces the order book of the symbol with a snapshot, and returns it
+    /// as an order book event
+    pub fn insert_snapshot(
+        &mut self,
+        symbol: &str,
+        snapshot: &DepthSnapshot,
+    ) -> Result<BookEvent, BotError> {
+        let (base, quote) = Exchange::Binance
+            .parse_symbol(symbol)
+            .ok_or_else(|| BotError::Parse(format!("invalid symbol {}", symbol)))?;
+
+        let mut order_book = OrderBook::new();
+        for (order_type, levels) in [
+            (OrderType::Ask, &snapshot.asks),
+            (OrderType::Bid, &snapshot.bids),
+        ] {
+            for (price, size) in levels {
+                order_book.update(order_type.clone(), price.clone(), size.clone());
+            }
+        }
+        self.books.insert(
+            symbol.to_string(),
+            (order_book.clone(), DepthSync::new(snapshot)),
+        );
+
+        Ok(BookEvent::Snapshot {
+            currency_pair: Exchange::Poloniex(Api::Legacy).symbol(&base, &quote),
+            order_book,
+        })
+    }
+
+    /// Applies an update, and returns it as order book events
+    ///
+    /// Fails if updates have been missed, the symbol is then dropped until
+    /// the next snapshot.
+    pub fn apply(&mut self, update: DepthUpdate) -> Result<Vec<BookEvent>, BotError> {
+        let (order_book, sync) = match self.books.get_mut(&update.symbol) {
+            Some(book) => book,
+            None => return Ok(vec![]),
+        };
+
+        match sync.accept(&update) {
+            Ok(true) => (),
+            Ok(false) => return Ok(vec![]),
+            Err(err) => {
+                self.books.remove(&update.symbol);
+                return Err(err);
+            }
+        }
+
+        let mut events = vec![];
+        for (order_type, levels) in [(OrderType::Ask, update.asks), (OrderType::Bid, update.bids)] {
+            for (price, size) in levels {
+                order_book.update(order_type.clone(), price.clone(), size.clone());
+                events.push(BookEvent::Update {
+                    order_type: order_type.clone(),
+                    price,
+                    size,
+                });
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse_rest_test() {
+        let info: ExchangeInfo = serde_json::from_str(
+            r#"{"timezone": "UTC", "symbols": [{"symbol": "BTCUSDT", "status": "TRADING",
+              "baseAsset": "BTC", "quoteAsset": "USDT", "filters": [
+                {"filterType": "PRICE_FILTER", "minPrice": "0.01000000",
+                 "maxPrice": "1000000.00000000", "tickSize": "0.01000000"},
+                {"filterType": "LOT_SIZE", "minQty": "0.00001000",
+                 "maxQty": "9000.00000000", "stepSize": "0.00001000"},
+                {"filterType": "ICEBERG_PARTS", "limit": 10},
+                {"filterType": "NOTIONAL", "minNotional": "5.00000000",
+                 "applyMinToMarket": true}]}]}"#,
+        )
+        .unwrap();
+        let market = to_market(&info.symbols[0]);
+        assert_eq!(
+            (market.base.as_str(), market.quote.as_str()),
+            ("USDT", "BTC")
+        );
+        assert_eq!((market.price_precision, market.amount_precision), (2, 5));
+        assert_eq!(market.min_order, d("5"));
+        assert!(!market.is_frozen);
+
+        let klines: Vec<Kline> = serde_json::from_str(
+            r#"[[1499040000000, "0.01634790", "0.80000000", "0.01575800", "0.01577100",
+              "148976.11427815", 1499644799999, "2434.19055334", 308, "1756.87402397",
+              "28.46694368", "0"]]"#,
+        )
+        .unwrap();
+        let request = ChartDataRequest {
+            exchange: Exchange::Binance,
+            base: "BTC".to_string(),
+            quote: "LTC".to_string(),
+            period: 900,
+            start: 1499040000,
+            end: 1499644800,
+        };
+        let candle = to_candle(&request, klines[0].clone()).unwrap();
+        assert_eq!(candle.timestamp.timestamp(), 1499040000);
+        assert_eq!(candle.open, Some(d("0.01634790")));
+        assert_eq!(candle.volume, Some(d("2434.19055334")));
+        assert_eq!(candle.average, Some(d("0.016339468680")));
+        assert_eq!(candle.exchange, "binance");
+    }
+
+    #[test]
+    fn depth_sync_test() {
+        let message = parse_ws_message(
+            r#"{"e": "depthUpdate", "E": 1672515782136, "s": "BNBBTC", "U": 157, "u": 160,
+              "b": [["0.0024", "10"]], "a": [["0.0026", "100"]]}"#,
+        )
+        .unwrap();
+        let update = match message {
+            WsMessage::Depth(update) => update,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(update.bids, vec![(d("0.0024"), d("10"))]);
+
+        let snapshot = DepthSnapshot {
+            last_update_id: 158,
+            bids: vec![],
+            asks: vec![],
+        };
+        let mut sync = DepthSync::new(&snapshot);
+        let old = DepthUpdate {
+            first_id: 150,
+            last_id: 156,
+            ..update.clone()
+        };
+        assert!(!sync.accept(&old).unwrap());
+        assert!(sync.accept(&update).unwrap());
+        let next = DepthUpdate {
+            first_id: 161,
+            last_id: 165,
+            ..update.clone()
+        };
+        assert!(sync.accept(&next).unwrap());
+        let gap = DepthUpdate {
+            first_id: 170,
+            last_id: 175,
+            ..update.clone()
+        };
+        assert!(sync.accept(&gap).is_err());
+
+        let mut tracker = DepthTracker::new();
+        let snapshot = DepthSnapshot {
+            last_update_id: 158,
+            bids: vec![(d("0.0023"), d("5"))],
+            asks: vec![(d("0.0026"), d("1"))],
+        };
+        assert!(matches!(
+            tracker.insert_snapshot("BNBBTC", &snapshot).unwrap(),
+            BookEvent::Snapshot { currency_pair, .. } if currency_pair == "BTC_BNB"
+        ));
+        assert_eq!(tracker.apply(update.clone()).unwrap().len(), 2);
+        assert!(tracker.apply(gap.clone()).is_err());
+        assert!(!tracker.has_book("BNBBTC"));
+        assert!(tracker.apply(gap).unwrap().is_empty());
+
+        let trade = parse_ws_message(
+            r#"{"e": "trade", "E": 1672515782136, "s": "BNBBTC", "t": 12345, "p": "0.001",
+              "q": "100", "T": 1672515782136, "m": true, "M": true}"#,
+        )
+        .unwrap();
+        match trade {
+            WsMessage::Trade(t) => {
+                let trade = to_public_trade(t);
+                assert!(!trade.is_buy);
+                assert_eq!(trade.timestamp, 1672515782);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            parse_ws_message(r#"{"result": null, "id": 1}"#).unwrap(),
+            WsMessage::Response(1)
+        );
+    }
+}
@@ -0,0 +1,258 @@
+extern crate diesel;
+
+use super::chart_data::SOURCE_TRADES;
+use super::decimal::DECIMAL_SCALE;
+use super::diesel::prelude::*;
+use super::error::{BotError, Context};
+use super::exchange::Exchange;
+use super::message::PublicTrade;
+use super::models::*;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{TimeZone, Utc};
+
+// periods in seconds of the candles built from public trades
+pub const TRADE_CANDLE_PERIODS: &[i32] = &[60, 300];
+
+/// Candle of a single period being built, with the sums needed for the
+/// volume weighted average
+struct PeriodCandle {
+    period: i32,
+    candle: Option<Candle>,
+    // sums of price * size and size over the trades of the candle
+    value: BigDecimal,
+    size: BigDecimal,
+    // the candle was in progress when the builder started, so it misses the
+    // trades before that and is left to the chart data
+    partial: bool,
+}
+
+/// Aggregates the public trades of a market into candles of
+/// `TRADE_CANDLE_PERIODS`
+///
+/// Only the latest candle of each period is kept, and trades older than it
+/// are ignored. The candle in progress when starting covers only the trades
+/// added since, so it isn't returned and doesn't replace the stored one.
+pub struct CandleBuilder {
+    exchange: Exchange,
+    base: String,
+    quote: String,
+    candles: Vec<PeriodCandle>,
+}
+
+impl CandleBuilder {
+    pub fn new(exchange: Exchange, base: &str, quote: &str) -> CandleBuilder {
+        CandleBuilder {
+            exchange,
+            base: base.to_string(),
+            quote: quote.to_string(),
+            candles: TRADE_CANDLE_PERIODS
+                .iter()
+                .map(|&period| PeriodCandle {
+                    period,
+                    candle: None,
+                    value: BigDecimal::zero(),
+                    size: BigDecimal::zero(),
+                    partial: true,
+                })
+                .collect(),
+        }
+    }
+
+    /// Adds a trade to the candles, returns the candles it updated apart from
+    /// the ones in progress when the builder started
+    pub fn add(&mut self, trade: &PublicTrade) -> Vec<&Candle> {
+        let mut updated: Vec<bool> = vec![];
+
+        for pc in self.candles.iter_mut() {
+            let start = trade.timestamp - trade.timestamp.rem_euclid(pc.period as i64);
+            let timestamp = match Utc.timestamp_opt(start, 0).single() {
+                Some(timestamp) => timestamp,
+                None => {
+                    updated.push(false);
+                    continue;
+                }
+            };
+
+            let candle = match pc.candle.as_mut() {
+                Some(c) if c.timestamp == timestamp => c,
+                Some(c) if c.timestamp > timestamp => {
+                    updated.push(false);
+                    continue;
+                }
+                _ => {
+                    pc.partial = pc.candle.is_none();
+                    pc.value = BigDecimal::zero();
+                    pc.size = BigDecimal::zero();
+                    pc.candle.insert(Candle {
+                        base: self.base.clone(),
+                        quote: self.quote.clone(),
+                        period: pc.period,
+                        timestamp,
+                        high: Some(trade.price.clone()),
+                        low: Some(trade.price.clone()),
+                        open: Some(trade.price.clone()),
+                        close: None,
+                        average: None,
+                        volume: None,
+                        exchange: self.exchange.name().to_string(),
+                        source: SOURCE_TRADES.to_string(),
+                    })
+                }
+            };
+
+            if !matches!(&candle.high, Some(high) if *high >= trade.price) {
+                candle.high = Some(trade.price.clone());
+            }
+            if !matches!(&candle.low, Some(low) if *low <= trade.price) {
+                candle.low = Some(trade.price.clone());
+            }
+            candle.close = Some(trade.price.clone());
+
+            pc.value += &trade.price * &trade.size;
+            pc.size += &trade.size;
+            // volume in base currency like in the chart data
+            candle.volume = Some(pc.value.clone());
+            candle.average = if pc.size.is_zero() {
+                Some(trade.price.clone())
+            } else {
+                Some((&pc.value / &pc.size).round(DECIMAL_SCALE))
+            };
+
+            updated.push(true);
+        }
+
+        self.candles
+            .iter()
+            .zip(updated)
+            .filter(|(pc, updated)| *updated && !pc.partial)
+            .filter_map(|(pc, _)| pc.candle.as_ref())
+            .collect()
+    }
+}
+
+/// Row of a public trade of the market `base`_`quote` in the exchange named
+/// `exchange`, `None` if the trade timestamp is out of range
+pub fn to_market_trade(
+    exchange: &str,
+    base: &str,
+    quote: &str,
+    trade: &PublicTrade,
+) -> Option<MarketTrade> {
+    Some(MarketTrade {
+        base: base.to_string(),
+        quote: quote.to_string(),
+        trade_id: trade.id.clone(),
+        timestamp: Utc.timestamp_opt(trade.timestamp, 0).single()?,
+        is_buy: trade.is_buy,
+        price: trade.price.clone(),
+        size: trade.size.clone(),
+        exchange: exchange.to_string(),
+    })
+}
+
+/// Stores a public trade, a trade that has been stored already is skipped
+pub fn insert_market_trade(
+    connection: &mut PgConnection,
+    trade: &MarketTrade,
+) -> Result<usize, BotError> {
+    use super::schema::market_trades;
+
+    diesel::insert_into(market_trades::table)
+        .values(trade)
+        .on_conflict_do_nothing()
+        .execute(connection)
+        .context("inserting market trade")
+}
+
+/// Inserts the candle, or replaces it if the period has a candle already
+pub fn upsert_candle(connection: &mut PgConnection, candle: &Candle) -> Result<usize, BotError> {
+    use super::schema::candles::dsl::*;
+
+    diesel::insert_into(candles)
+        .values(candle)
+        .on_conflict((exchange, base, quote, period, timestamp))
+        .do_update()
+        .set(candle)
+        .execute(connection)
+        .context("updating candle")
+}
+
+/// Stores a public trade and the candles it updates
+pub fn record_trade(
+    connection: &mut PgConnection,
+    builder: &mut CandleBuilder,
+    trade: &PublicTrade,
+) -> Result<(), BotError> {
+    if let Some(row) = to_market_trade(
+        builder.exchange.name(),
+        &builder.base,
+        &builder.quote,
+        trade,
+    ) {
+        insert_market_trade(connection, &row)?;
+    }
+
+    for candle in builder.add(trade) {
+        upsert_candle(connection, candle)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Api;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    fn trade(timestamp: i64, price: &str, size: &str) -> PublicTrade {
+        PublicTrade {
+            id: timestamp.to_string(),
+            is_buy: true,
+            price: d(price),
+            size: d(size),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn candle_builder_test() {
+        let mut builder = CandleBuilder::new(Exchange::Poloniex(Api::Legacy), "USDT", "LTC");
+
+        // the candles in progress when starting aren't returned
+        assert!(builder.add(&trade(1674043195, "8", "1")).is_empty());
+
+        // 1674043200 is at a 5 minute boundary
+        assert_eq!(builder.add(&trade(1674043205, "10", "1")).len(), 2);
+        builder.add(&trade(1674043230, "12", "1"));
+        let updated = builder.add(&trade(1674043250, "11", "2"));
+
+        let minute = updated[0];
+        assert_eq!(minute.period, 60);
+        assert_eq!(minute.timestamp.timestamp(), 1674043200);
+        assert_eq!(minute.open, Some(d("10")));
+        assert_eq!(minute.high, Some(d("12")));
+        assert_eq!(minute.low, Some(d("10")));
+        assert_eq!(minute.close, Some(d("11")));
+        assert_eq!(minute.volume, Some(d("44")));
+        assert_eq!(minute.average, Some(d("11")));
+        assert_eq!(minute.exchange, "poloniex");
+
+        // next minute starts a new 1 minute candle, the 5 minute one goes on
+        let updated = builder.add(&trade(1674043265, "9", "1"));
+        assert_eq!(updated[0].timestamp.timestamp(), 1674043260);
+        assert_eq!(updated[0].open, Some(d("9")));
+        assert_eq!(updated[1].timestamp.timestamp(), 1674043200);
+        assert_eq!(updated[1].low, Some(d("9")));
+        assert_eq!(updated[1].volume, Some(d("53")));
+
+        // trades older than the 1 minute candle only update the 5 minute one
+        let updated = builder.add(&trade(1674043240, "10", "1"));
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].period, 300);
+    }
+}
@@ -1,34 +1,44 @@
 extern crate diesel;
 
+use bigdecimal::BigDecimal;
 use chrono::{TimeZone, Utc};
 use serde::{Deserialize, Deserializer, Serialize};
 
+use crate::api::{legacy_rest_url, Api};
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
+use crate::fetcher::Fetcher;
 use crate::models::*;
 use diesel::prelude::*;
 
-const API_URL: &str = "https://poloniex.com/public";
+// sources of the candles, candles fetched from the REST API replace the others
+pub const SOURCE_REST: &str = "rest";
+// candle channels of the exchange websocket
+pub const SOURCE_STREAM: &str = "stream";
+// built from public trades, see candle_builder
+pub const SOURCE_TRADES: &str = "trades";
+pub const SOURCE_RESAMPLED: &str = "resampled";
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+// candle periods in seconds that returnChartData provides
+pub const EXCHANGE_PERIODS: &[i32] = &[300, 900, 1800, 7200, 14400, 86400];
+
+// candle periods in seconds stored for each quote, the ones not in
+// EXCHANGE_PERIODS are resampled from shorter periods
+pub const PERIODS: &[i32] = &[300, 900, 1800, 7200, 14400, 86400];
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PoloniexChartData {
     #[serde(deserialize_with = "deserialize_date")]
     pub date: i64,
-    #[serde(deserialize_with = "deserialize_f32_from_str")]
-    pub high: f32,
-    #[serde(deserialize_with = "deserialize_f32_from_str")]
-    pub low: f32,
-    #[serde(deserialize_with = "deserialize_f32_from_str")]
-    pub open: f32,
-    #[serde(deserialize_with = "deserialize_f32_from_str")]
-    pub close: f32,
-    #[serde(deserialize_with = "deserialize_f32_from_str")]
-    pub volume: f32,
-    #[serde(rename = "quoteVolume", deserialize_with = "deserialize_f32_from_str")]
-    pub quote_volume: f32,
-    #[serde(
-        rename = "weightedAverage",
-        deserialize_with = "deserialize_f32_from_str"
-    )]
-    pub weighted_average: f32,
+    pub high: BigDecimal,
+    pub low: BigDecimal,
+    pub open: BigDecimal,
+    pub close: BigDecimal,
+    pub volume: BigDecimal,
+    #[serde(rename = "quoteVolume")]
+    pub quote_volume: BigDecimal,
+    #[serde(rename = "weightedAverage")]
+    pub weighted_average: BigDecimal,
 }
 
 fn deserialize_date<'de, D>(deserializer: D) -> Result<i64, D::Error>
@@ -41,103 +51,174 @@ where
         .map_err(serde::de::Error::custom)
 }
 
-fn deserialize_f32_from_str<'de, D>(deserializer: D) -> Result<f32, D::Error>
-where
-    D: Deserializer<'de>,
-{
-    let s = String::deserialize(deserializer)?;
-    s.parse::<f32>().map_err(serde::de::Error::custom)
-}
-
 /// Convert poloniex chart data to Candle object
 pub fn chart_data_to_candle(
     base: String,
     quote: String,
     period: i32,
     cd: PoloniexChartData,
-) -> Candle {
-    Candle {
-        base: base,
-        quote: quote,
-        period: period,
-        timestamp: Utc.timestamp_opt(cd.date, 0).unwrap(),
+) -> Result<Candle, BotError> {
+    let timestamp = Utc
+        .timestamp_opt(cd.date, 0)
+        .single()
+        .ok_or_else(|| BotError::Parse(format!("invalid timestamp {}", cd.date)))?;
+
+    Ok(Candle {
+        base,
+        quote,
+        period,
+        timestamp,
         high: Some(cd.high),
         low: Some(cd.low),
         open: Some(cd.open),
         close: Some(cd.close),
         average: Some(cd.weighted_average),
         volume: Some(cd.volume),
-    }
+        exchange: Exchange::Poloniex(Api::Legacy).name().to_string(),
+        source: SOURCE_REST.to_string(),
+    })
 }
 
-/// Get the last candle timestamp in database
+/// Get the timestamp of the last candle fetched from the REST API
 ///
-/// Used for fetching new data starting from this timestamp
+/// Used for fetching new data starting from this timestamp. Candles from
+/// other sources are left out, so that the gaps before them are fetched and
+/// the candles replaced.
 fn get_start_timestamp(
     connection: &mut PgConnection,
+    exchange_p: Exchange,
     base_p: String,
     quote_p: String,
     period_p: i32,
     max_candles: i32,
-) -> Result<i32, Box<dyn std::error::Error>> {
+) -> Result<i64, BotError> {
     use crate::schema::candles::dsl::*;
 
     let results = candles
+        .filter(exchange.eq(exchange_p.name()))
         .filter(base.eq(base_p))
         .filter(quote.eq(quote_p))
-        .filter(period.eq(period))
+        .filter(period.eq(period_p))
+        .filter(source.eq(SOURCE_REST))
         .order(timestamp.desc())
         .limit(1)
         .load::<Candle>(connection)?;
 
     // candle found
-    for candle in results {
-        let last_timestamp: i32 = candle.timestamp.timestamp().try_into().unwrap();
-        return Ok(last_timestamp + period_p);
+    if let Some(candle) = results.first() {
+        return Ok(candle.timestamp.timestamp() + period_p as i64);
     }
 
-    let now = Utc::now().timestamp();
-    let end = i32::try_from(now)?;
-    let start = end - (max_candles * period_p);
+    let end = Utc::now().timestamp();
+    let start = end - (max_candles as i64 * period_p as i64);
 
     Ok(start)
 }
 
-/// Fetch chart data from poloniex
-pub fn return_chart_data(
+/// Chart data to fetch for a quote and period
+#[derive(Clone, Debug)]
+pub struct ChartDataRequest {
+    pub exchange: Exchange,
+    pub base: String,
+    pub quote: String,
+    pub period: i32,
+    pub start: i64,
+    pub end: i64,
+}
+
+/// Chart data request for the candles after the latest stored one, at most
+/// `max_candles`, `None` if no new candle has been completed since
+pub fn get_chart_data_request(
     connection: &mut PgConnection,
+    exchange: Exchange,
     base: String,
     quote: String,
     period: i32,
     max_candles: i32,
-) -> Result<Vec<PoloniexChartData>, Box<dyn std::error::Error>> {
-    let client = reqwest::blocking::Client::new();
-    let now = Utc::now().timestamp();
-    let end = i32::try_from(now)?;
-    let start = get_start_timestamp(connection, base.clone(), quote.clone(), period, max_candles)?;
-
-    let response = client
-        .get(API_URL)
-        .query(&[
-            ("command", "returnChartData"),
-            ("currencyPair", format!("{}_{}", base, quote).as_str()),
-            ("period", period.to_string().as_str()),
-            ("start", start.to_string().as_str()),
-            ("end", end.to_string().as_str()),
-        ])
-        .send()?;
-
-    if response.status().is_success() {
-        let chart_data: Vec<PoloniexChartData> = response
-            .json::<Vec<PoloniexChartData>>()?
-            .into_iter()
-            .filter(|cd| cd.date != 0)
-            .collect();
-        Ok(chart_data)
-    } else {
-        Err(Box::new(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!("Request not successful: {}", response.status()),
-        )))
+) -> Result<Option<ChartDataRequest>, BotError> {
+    let end = Utc::now().timestamp();
+    let start = get_start_timestamp(
+        connection,
+        exchange,
+        base.clone(),
+        quote.clone(),
+        period,
+        max_candles,
+    )?;
+
+    if start + period as i64 > end {
+        return Ok(None);
     }
+
+    Ok(Some(ChartDataRequest {
+        exchange,
+        base,
+        quote,
+        period,
+        start,
+        end,
+    }))
+}
+
+/// Fetch chart data from poloniex
+pub fn return_chart_data(
+    fetcher: &Fetcher,
+    request: &ChartDataRequest,
+) -> Result<Vec<PoloniexChartData>, BotError> {
+    let what = format!(
+        "fetching chart data for {} {}",
+        request.quote, request.period
+    );
+    let response = fetcher.get(
+        &legacy_rest_url(),
+        &[
+            ("command", "returnChartData"),
+            (
+                "currencyPair",
+                Exchange::Poloniex(Api::Legacy)
+                    .symbol(&request.base, &request.quote)
+                    .as_str(),
+            ),
+            ("period", request.period.to_string().as_str()),
+            ("start", request.start.to_string().as_str()),
+            ("end", request.end.to_string().as_str()),
+        ],
+        &what,
+    )?;
+
+    let chart_data: Vec<PoloniexChartData> = response
+        .json::<Vec<PoloniexChartData>>()
+        .context(&format!("reading chart data for {}", request.quote))?
+        .into_iter()
+        .filter(|cd| cd.date != 0)
+        .collect();
+
+    Ok(chart_data)
+}
+
+/// Gets the `limit` most recent candles of a quote at `period` from
+/// `exchange`, oldest first
+///
+/// Any stored period can be requested, see `PERIODS`.
+pub fn get_candles(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+    base_p: &str,
+    quote_p: &str,
+    period_p: i32,
+    limit: i64,
+) -> Result<Vec<Candle>, BotError> {
+    use crate::schema::candles::dsl::*;
+
+    let mut rows = candles
+        .filter(exchange.eq(exchange_p.name()))
+        .filter(base.eq(base_p))
+        .filter(quote.eq(quote_p))
+        .filter(period.eq(period_p))
+        .order(timestamp.desc())
+        .limit(limit)
+        .load::<Candle>(connection)?;
+    rows.reverse();
+
+    Ok(rows)
 }
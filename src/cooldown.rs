@@ -0,0 +1,128 @@
+extern crate diesel;
+
+use super::diesel::prelude::*;
+use super::error::BotError;
+use super::exchange::Exchange;
+use super::models::*;
+use chrono::{DateTime, Duration, Utc};
+
+// block re-entry to a quote for this many seconds after a losing trade
+pub const COOLDOWN_TIME: i64 = 4 * 60 * 60;
+
+// multiply the cooldown by this for each consecutive losing trade,
+// 1.0 keeps the cooldown constant
+pub const COOLDOWN_ESCALATION: f64 = 2.0;
+
+// upper limit for the escalated cooldown in seconds
+pub const MAX_COOLDOWN_TIME: i64 = 48 * 60 * 60;
+
+/// Returns the end of the cooldown if the quote is cooling down in
+/// `exchange_p`
+pub fn get_cooldown(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+    quote_p: &str,
+) -> Result<Option<DateTime<Utc>>, BotError> {
+    use super::schema::cooldowns::dsl::*;
+
+    let row = cooldowns
+        .find((exchange_p.name(), quote_p))
+        .filter(until.gt(Utc::now()))
+        .first::<Cooldown>(connection)
+        .optional()?;
+
+    Ok(row.map(|c| c.until))
+}
+
+/// Consecutive losses after a closed trade, a profitable (or unknown) result
+/// resets the count
+fn count_losses(previous: i32, profit: Option<f32>) -> i32 {
+    match profit {
+        Some(p) if p < 0.0 => previous + 1,
+        _ => 0,
+    }
+}
+
+/// Cooldown length in seconds after `losses` consecutive losses
+fn cooldown_seconds(losses: i32) -> i64 {
+    if losses <= 0 {
+        return 0;
+    }
+    (COOLDOWN_TIME as f64 * COOLDOWN_ESCALATION.powi(losses - 1)).min(MAX_COOLDOWN_TIME as f64)
+        as i64
+}
+
+/// Updates the cooldown of the trade quote based on a closed trade
+///
+/// A losing trade starts a cooldown, which gets longer with consecutive
+/// losses. A profitable trade resets the loss count.
+pub fn register_trade_result(
+    connection: &mut PgConnection,
+    trade: &Trade,
+) -> Result<Option<Cooldown>, BotError> {
+    use super::schema::cooldowns::dsl::*;
+
+    let previous = cooldowns
+        .find((&trade.exchange, &trade.quote))
+        .first::<Cooldown>(connection)
+        .optional()?;
+
+    let new_losses = count_losses(previous.map(|c| c.losses).unwrap_or(0), trade.profit);
+
+    if new_losses == 0 {
+        diesel::update(cooldowns.find((&trade.exchange, &trade.quote)))
+            .set((losses.eq(0), updated_at.eq(Utc::now())))
+            .execute(connection)?;
+        return Ok(None);
+    }
+
+    let seconds = cooldown_seconds(new_losses);
+
+    let cooldown = Cooldown {
+        quote: trade.quote.clone(),
+        losses: new_losses,
+        until: Utc::now() + Duration::seconds(seconds),
+        updated_at: Utc::now(),
+        exchange: trade.exchange.clone(),
+    };
+
+    let row = diesel::insert_into(cooldowns)
+        .values(&cooldown)
+        .on_conflict((exchange, quote))
+        .do_update()
+        .set(&cooldown)
+        .get_result::<Cooldown>(connection)?;
+
+    Ok(Some(row))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_losses_test() {
+        assert_eq!(count_losses(0, Some(-0.01)), 1);
+        assert_eq!(count_losses(2, Some(-0.01)), 3);
+        assert_eq!(count_losses(3, Some(0.01)), 0);
+        assert_eq!(count_losses(3, Some(0.0)), 0);
+        assert_eq!(count_losses(3, None), 0);
+    }
+
+    #[test]
+    fn cooldown_seconds_test() {
+        assert_eq!(cooldown_seconds(0), 0);
+        assert_eq!(cooldown_seconds(1), COOLDOWN_TIME);
+        assert_eq!(
+            cooldown_seconds(2),
+            (COOLDOWN_TIME as f64 * COOLDOWN_ESCALATION) as i64
+        );
+
+        // escalation stops at the cap
+        assert_eq!(cooldown_seconds(20), MAX_COOLDOWN_TIME);
+        assert_eq!(cooldown_seconds(100), MAX_COOLDOWN_TIME);
+        for losses in 1..10 {
+            assert!(cooldown_seconds(losses) <= cooldown_seconds(losses + 1));
+        }
+    }
+}
@@ -0,0 +1,335 @@
+extern crate diesel;
+
+use super::diesel::prelude::*;
+use super::error::{BotError, Context};
+use super::exchange::Exchange;
+use super::models::Correlation;
+use diesel::{delete, sql_query};
+
+// correlation of returns is computed over this many most recent candles
+pub const CORRELATION_CANDLES: i32 = 96;
+
+// don't compute correlation from fewer common candles than this
+pub const MIN_SAMPLES: i32 = 30;
+
+// don't open a trade if its returns correlate more than this with an open trade
+pub const MAX_CORRELATION: f32 = 0.8;
+
+/// Computes pairwise correlations of candle returns between shortlisted
+/// quotes and quotes with open trades
+///
+/// Shortlist must be up to date, see `update_shortlist`.
+pub fn update_correlations(
+    connection: &mut PgConnection,
+    exchange: Exchange,
+    base: String,
+    period: i32,
+) -> Result<usize, BotError> {
+    use crate::schema::correlations;
+
+    println!("updating correlations");
+
+    let max_seconds = period * CORRELATION_CANDLES;
+
+    delete(correlations::table.filter(correlations::exchange.eq(exchange.name())))
+        .execute(connection)?;
+
+    sql_query(format!(
+        "
+      WITH symbols AS (
+        SELECT
+          quote
+        FROM
+          shortlist
+        WHERE
+          exchange = '{exchange}'
+        UNION
+        SELECT
+          quote
+        FROM
+          trades
+        WHERE
+          exchange = '{exchange}'
+          AND base = '{base}'
+          AND close_at IS NULL
+      ),
+      returns AS (
+        SELECT
+          quote,
+          timestamp,
+          average / LAG(average) OVER(
+            PARTITION BY quote
+            ORDER BY
+              timestamp
+          ) - 1 AS ret
+        FROM
+          candles
+        WHERE
+          exchange = '{exchange}'
+          AND base = '{base}'
+          AND period = {period}
+          AND timestamp > (current_timestamp - interval '{max_seconds} seconds')
+          AND quote IN (SELECT quote FROM symbols)
+      )
+      INSERT INTO correlations(exchange, quote_a, quote_b, timestamp, correlation, samples) (SELECT
+        '{exchange}',
+        a.quote,
+        b.quote,
+        NOW(),
+        CORR(a.ret, b.ret),
+        COUNT(*)
+      FROM
+        returns AS a
+        JOIN returns AS b ON a.timestamp = b.timestamp
+        AND a.quote <> b.quote
+      WHERE
+        a.ret IS NOT NULL
+        AND b.ret IS NOT NULL
+      GROUP BY
+        a.quote,
+        b.quote
+      HAVING
+        COUNT(*) >= {min_samples}
+        AND CORR(a.ret, b.ret) IS NOT NULL
+      );
+    ",
+        exchange = exchange.name(),
+        base = base,
+        period = period,
+        max_seconds = max_seconds,
+        min_samples = MIN_SAMPLES,
+    ))
+    .execute(connection)
+    .context("updating correlations")
+}
+
+/// Gets the stored correlations of `exchange_p`
+pub fn get_correlations(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+) -> Result<Vec<Correlation>, BotError> {
+    use super::schema::correlations::dsl::*;
+
+    let rows = correlations
+        .filter(exchange.eq(exchange_p.name()))
+        .order((quote_a.asc(), quote_b.asc()))
+        .load::<Correlation>(connection)?;
+
+    Ok(rows)
+}
+
+/// Returns the quote among `others` that correlates most with `quote_p`,
+/// if its correlation is above `MAX_CORRELATION`
+pub fn get_too_correlated(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+    quote_p: &str,
+    others: &[String],
+) -> Result<Option<Correlation>, BotError> {
+    use super::schema::correlations::dsl::*;
+
+    let row = correlations
+        .filter(exchange.eq(exchange_p.name()))
+        .filter(quote_a.eq(quote_p))
+        .filter(quote_b.eq_any(others))
+        .filter(correlation.gt(MAX_CORRELATION))
+        .order(correlation.desc())
+        .first::<Correlation>(connection)
+        .optional()?;
+
+    Ok(row)
+}
+
+/// Formats the correlations as a CSV matrix with quotes as row and column
+/// headers
+pub fn correlation_matrix_csv(rows: &[Correlation]) -> String {
+    let mut quotes: Vec<&str> = rows.iter().map(|r| r.quote_a.as_str()).collect();
+    quotes.sort_unstable();
+    quotes.dedup();
+
+    let mut csv = format!("quote,{}\n", quotes.join(","));
+
+    for a in quotes.iter() {
+        let values: Vec<String> = quotes
+            .iter()
+            .map(|b| {
+                if a == b {
+                    return "1".to_string();
+                }
+                rows.iter()
+                    .find(|r| r.quote_a == *a && r.quote_b == *b)
+                    .map(|r| format!("{:.3}", r.correlation))
+                    .unwrap_or_default()
+            })
+            .collect();
+        csv.push_str(&format!("{},{}\n", a, values.join(",")));
+    }
+
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Api;
+    use crate::chart_data::SOURCE_REST;
+    use crate::decimal::decimal;
+    use crate::models::{Candle, Shortlist};
+    use crate::test_connection;
+    use bigdecimal::BigDecimal;
+    use chrono::{DateTime, Duration, Utc};
+
+    const TEST_BASE: &str = "TEST_CORRELATION";
+    const PERIOD: i32 = 300;
+
+    fn correlation_row(a: &str, b: &str, value: f32) -> Correlation {
+        Correlation {
+            quote_a: a.to_string(),
+            quote_b: b.to_string(),
+            timestamp: Utc::now(),
+            correlation: value,
+            samples: MIN_SAMPLES,
+            exchange: "poloniex".to_string(),
+        }
+    }
+
+    /// Candles ending at `end` whose averages have the given returns
+    fn candles(quote: &str, end: DateTime<Utc>, returns: &[f64]) -> Vec<Candle> {
+        let mut price = 100.0;
+        let mut prices = vec![price];
+        for r in returns {
+            price *= 1.0 + r;
+            prices.push(price);
+        }
+
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let average = Some(decimal(*p).unwrap());
+                Candle {
+                    base: TEST_BASE.to_string(),
+                    quote: quote.to_string(),
+                    period: PERIOD,
+                    timestamp: end - Duration::seconds(((prices.len() - i) as i64) * PERIOD as i64),
+                    high: average.clone(),
+                    low: average.clone(),
+                    open: average.clone(),
+                    close: average.clone(),
+                    average,
+                    volume: Some(BigDecimal::from(1)),
+                    exchange: Exchange::Poloniex(Api::Legacy).name().to_string(),
+                    source: SOURCE_REST.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    fn shortlisted(quote: &str) -> Shortlist {
+        Shortlist {
+            quote: quote.to_string(),
+            timestamp: Utc::now(),
+            average: BigDecimal::from(100),
+            target: BigDecimal::from(99),
+            confidence: 1.0,
+            stop_loss: 0.02,
+            exchange: Exchange::Poloniex(Api::Legacy).name().to_string(),
+        }
+    }
+
+    #[test]
+    fn correlation_matrix_csv_test() {
+        let rows = [
+            correlation_row("ETH", "LTC", 0.9),
+            correlation_row("LTC", "ETH", 0.9),
+            correlation_row("ETH", "XRP", -0.1234),
+            correlation_row("XRP", "ETH", -0.1234),
+        ];
+
+        // pairs without enough samples are left empty
+        assert_eq!(
+            correlation_matrix_csv(&rows),
+            "quote,ETH,LTC,XRP\n\
+             ETH,1,0.900,-0.123\n\
+             LTC,0.900,1,\n\
+             XRP,-0.123,,1\n"
+        );
+        assert_eq!(correlation_matrix_csv(&[]), "quote,\n");
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn update_correlations_test() {
+        use crate::schema::{candles, shortlist};
+
+        let (_lock, mut connection) = test_connection();
+        let exchange = Exchange::Poloniex(Api::Legacy);
+
+        // over a multiple of four samples the alternating returns and the
+        // ones alternating every other candle don't correlate at all
+        let samples = MIN_SAMPLES as usize + 10;
+        let alternating: Vec<f64> = (0..samples)
+            .map(|i| if i % 2 == 0 { 0.01 } else { -0.01 })
+            .collect();
+        let doubled: Vec<f64> = alternating.iter().map(|r| 2.0 * r).collect();
+        let uncorrelated: Vec<f64> = (0..samples)
+            .map(|i| if i / 2 % 2 == 0 { 0.01 } else { -0.01 })
+            .collect();
+
+        connection.test_transaction::<_, BotError, _>(|conn| {
+            let end = Utc::now();
+            for (quote, returns) in [
+                ("TEST_A", &alternating[..]),
+                ("TEST_B", &doubled[..]),
+                ("TEST_C", &uncorrelated[..]),
+                ("TEST_SHORT", &doubled[..MIN_SAMPLES as usize - 2]),
+            ] {
+                diesel::insert_into(candles::table)
+                    .values(candles(quote, end, returns))
+                    .execute(conn)?;
+                diesel::insert_into(shortlist::table)
+                    .values(shortlisted(quote))
+                    .execute(conn)?;
+            }
+
+            update_correlations(conn, exchange, TEST_BASE.to_string(), PERIOD)?;
+
+            let rows = get_correlations(conn, exchange)?;
+            let pairs: Vec<(&str, &str)> = rows
+                .iter()
+                .map(|r| (r.quote_a.as_str(), r.quote_b.as_str()))
+                .collect();
+            // quotes with fewer than MIN_SAMPLES returns have no correlations
+            assert_eq!(
+                pairs,
+                [
+                    ("TEST_A", "TEST_B"),
+                    ("TEST_A", "TEST_C"),
+                    ("TEST_B", "TEST_A"),
+                    ("TEST_B", "TEST_C"),
+                    ("TEST_C", "TEST_A"),
+                    ("TEST_C", "TEST_B"),
+                ]
+            );
+            for row in rows.iter() {
+                assert_eq!(row.samples, samples as i32);
+                let expected = if row.quote_a == "TEST_C" || row.quote_b == "TEST_C" {
+                    0.0
+                } else {
+                    1.0
+                };
+                assert!((row.correlation - expected).abs() < 1e-3, "{:?}", row);
+            }
+
+            let others = ["TEST_B".to_string(), "TEST_C".to_string()];
+            let too_correlated = get_too_correlated(conn, exchange, "TEST_A", &others)?;
+            assert_eq!(too_correlated.unwrap().quote_b, "TEST_B");
+
+            let others = ["TEST_A".to_string(), "TEST_B".to_string()];
+            assert!(get_too_correlated(conn, exchange, "TEST_C", &others)?.is_none());
+            assert!(get_too_correlated(conn, exchange, "TEST_SHORT", &others)?.is_none());
+            Ok(())
+        });
+    }
+}
@@ -0,0 +1,62 @@
+use bigdecimal::BigDecimal;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::BotError;
+
+/// Decimals computed by division, such as average prices, are rounded to
+/// this many digits after the decimal point
+pub const DECIMAL_SCALE: i64 = 12;
+
+/// Exact decimal for a float constant or ratio as it's printed, e.g.
+/// `decimal(0.005)` is 0.005 rather than the binary expansion of the float
+///
+/// NaN and infinite values are a parse error.
+pub fn decimal(value: impl fmt::Display) -> Result<BigDecimal, BotError> {
+    let s = value.to_string();
+    BigDecimal::from_str(&s).map_err(|_| BotError::Parse(format!("invalid decimal {}", s)))
+}
+
+/// Nearest float of a decimal, for ratios and logging
+///
+/// `BigDecimal::to_f64` doesn't round to the nearest float.
+pub fn to_f64(value: &BigDecimal) -> f64 {
+    value.to_string().parse::<f64>().unwrap_or(f64::NAN)
+}
+
+/// Rounds towards zero to `scale` digits after the decimal point, e.g. to
+/// the tick size of a price
+pub fn round_down(value: &BigDecimal, scale: i64) -> BigDecimal {
+    value.with_scale(scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_test() {
+        assert_eq!(
+            decimal(0.005).unwrap(),
+            BigDecimal::from_str("0.005").unwrap()
+        );
+        assert_eq!(
+            decimal(1e-8).unwrap(),
+            BigDecimal::from_str("0.00000001").unwrap()
+        );
+        assert_eq!(
+            decimal(0.005f32).unwrap(),
+            BigDecimal::from_str("0.005").unwrap()
+        );
+        assert!(decimal(f64::NAN).is_err());
+        assert!(decimal(f64::INFINITY).is_err());
+        assert_eq!(
+            to_f64(&BigDecimal::from_str("123.61626625").unwrap()),
+            123.61626625
+        );
+        assert_eq!(
+            round_down(&BigDecimal::from_str("0.123456789").unwrap(), 4),
+            BigDecimal::from_str("0.1234").unwrap()
+        );
+    }
+}
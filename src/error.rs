@@ -0,0 +1,171 @@
+use diesel::result::DatabaseErrorKind;
+use std::fmt;
+
+/// Errors returned by the library
+///
+/// Each variant carries a message describing what was being done when the
+/// error happened, see `Context`.
+#[derive(Debug)]
+pub enum BotError {
+    /// Connecting to or reading from the exchange failed
+    Network(String),
+    /// Exchange responded with an error, rate limits and server errors are
+    /// retryable
+    Exchange { message: String, retryable: bool },
+    /// Data from the exchange doesn't have the expected format
+    Parse(String),
+    /// Database query failed, connection problems are retryable
+    Database { message: String, retryable: bool },
+    /// Configuration, e.g. an environment variable or an argument, is
+    /// missing or invalid
+    Config(String),
+    /// Risk limits don't allow opening the trade
+    RiskRejected(String),
+}
+
+impl BotError {
+    /// Whether the same operation may succeed when tried again later
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BotError::Network(_) => true,
+            BotError::Exchange { retryable, .. } | BotError::Database { retryable, .. } => {
+                *retryable
+            }
+            BotError::Parse(_) | BotError::Config(_) | BotError::RiskRejected(_) => false,
+        }
+    }
+
+    /// Process exit code for the error, following sysexits.h so that a
+    /// supervisor can restart on temporary failures (75) only
+    pub fn exit_code(&self) -> i32 {
+        if self.is_retryable() {
+            return 75;
+        }
+        match self {
+            BotError::Parse(_) => 65,
+            BotError::Config(_) => 78,
+            BotError::RiskRejected(_) => 77,
+            _ => 70,
+        }
+    }
+
+    /// Prefixes the error message with what was being done
+    pub fn context(self, context: &str) -> BotError {
+        let prefix = |message: String| format!("{}: {}", context, message);
+        match self {
+            BotError::Network(m) => BotError::Network(prefix(m)),
+            BotError::Exchange { message, retryable } => BotError::Exchange {
+                message: prefix(message),
+                retryable,
+            },
+            BotError::Parse(m) => BotError::Parse(prefix(m)),
+            BotError::Database { message, retryable } => BotError::Database {
+                message: prefix(message),
+                retryable,
+            },
+            BotError::Config(m) => BotError::Config(prefix(m)),
+            BotError::RiskRejected(m) => BotError::RiskRejected(prefix(m)),
+        }
+    }
+}
+
+/// Adds context to the error of a result, e.g.
+/// `trades.find(id).first(connection).context("loading trade")?`
+pub trait Context<T> {
+    fn context(self, context: &str) -> Result<T, BotError>;
+}
+
+impl<T, E: Into<BotError>> Context<T> for Result<T, E> {
+    fn context(self, context: &str) -> Result<T, BotError> {
+        self.map_err(|err| err.into().context(context))
+    }
+}
+
+impl fmt::Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BotError::Network(message) => write!(f, "network error: {}", message),
+            BotError::Exchange { message, .. } => write!(f, "exchange error: {}", message),
+            BotError::Parse(message) => write!(f, "parse error: {}", message),
+            BotError::Database { message, .. } => write!(f, "database error: {}", message),
+            BotError::Config(message) => write!(f, "config error: {}", message),
+            BotError::RiskRejected(message) => write!(f, "rejected by risk limits: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BotError {}
+
+impl From<serde_json::Error> for BotError {
+    fn from(err: serde_json::Error) -> BotError {
+        BotError::Parse(err.to_string())
+    }
+}
+
+impl From<diesel::result::Error> for BotError {
+    fn from(err: diesel::result::Error) -> BotError {
+        let retryable = matches!(
+            err,
+            diesel::result::Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand | DatabaseErrorKind::SerializationFailure,
+                _
+            )
+        );
+        BotError::Database {
+            message: err.to_string(),
+            retryable,
+        }
+    }
+}
+
+impl From<diesel::ConnectionError> for BotError {
+    fn from(err: diesel::ConnectionError) -> BotError {
+        let retryable = matches!(err, diesel::ConnectionError::BadConnection(_));
+        BotError::Database {
+            message: err.to_string(),
+            retryable,
+        }
+    }
+}
+
+impl From<reqwest::Error> for BotError {
+    fn from(err: reqwest::Error) -> BotError {
+        if err.is_decode() {
+            BotError::Parse(err.to_string())
+        } else {
+            BotError::Network(err.to_string())
+        }
+    }
+}
+
+impl From<tungstenite::Error> for BotError {
+    fn from(err: tungstenite::Error) -> BotError {
+        BotError::Network(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_test() {
+        let result: Result<(), diesel::result::Error> = Err(diesel::result::Error::NotFound);
+        let err = result.context("loading trade 1").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "database error: loading trade 1: Record not found"
+        );
+        assert!(!err.is_retryable());
+        assert_eq!(err.exit_code(), 70);
+        assert_eq!(BotError::Network("timeout".to_string()).exit_code(), 75);
+
+        let rejected = BotError::Exchange {
+            message: "response 400 Bad Request".to_string(),
+            retryable: false,
+        };
+        assert!(!rejected.is_retryable());
+        assert_eq!(rejected.exit_code(), 70);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::api::Api;
+use crate::error::BotError;
+use crate::fetcher;
+use crate::{binance, kraken};
+use dotenv::dotenv;
+use std::env;
+
+// currencies that Binance symbols end with, the bases of the markets, e.g.
+// BTCUSDT is base USDT and quote BTC
+const BINANCE_BASES: &[&str] = &[
+    "FDUSD", "USDT", "USDC", "BUSD", "TUSD", "BTC", "ETH", "BNB", "EUR", "TRY",
+];
+
+// currencies that Kraken names differently, name used by the bot and by
+// Kraken
+const KRAKEN_ASSETS: &[(&str, &str)] = &[("BTC", "XBT"), ("DOGE", "XDG")];
+
+/// Exchange the market data is read from, chosen per deployment with the
+/// `EXCHANGE` environment variable
+///
+/// Markets are named by base and quote like in the legacy Poloniex API, e.g.
+/// base USDT and quote BTC, and converted to the symbols of the exchange
+/// here.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exchange {
+    Poloniex(Api),
+    Binance,
+    Kraken,
+}
+
+impl Exchange {
+    /// Reads the exchange from `EXCHANGE`, "poloniex" (the default),
+    /// "binance" or "kraken", the Poloniex API is read from `POLONIEX_API`
+    pub fn from_env() -> Result<Exchange, BotError> {
+        dotenv().ok();
+
+        match env::var("EXCHANGE") {
+            Ok(value) if value != "poloniex" => Exchange::parse(&value),
+            _ => Ok(Exchange::Poloniex(Api::from_env()?)),
+        }
+    }
+
+    pub fn parse(value: &str) -> Result<Exchange, BotError> {
+        match value {
+            "poloniex" => Ok(Exchange::Poloniex(Api::Legacy)),
+            "binance" => Ok(Exchange::Binance),
+            "kraken" => Ok(Exchange::Kraken),
+            other => Err(BotError::Config(format!(
+                "EXCHANGE must be poloniex, binance or kraken, not {}",
+                other
+            ))),
+        }
+    }
+
+    /// Name of the exchange in the candles and indicators
+    pub fn name(&self) -> &'static str {
+        match self {
+            Exchange::Poloniex(_) => "poloniex",
+            Exchange::Binance => "binance",
+            Exchange::Kraken => "kraken",
+        }
+    }
+
+    /// Symbol of the market `base`_`quote`, e.g. `USDT_BTC` in the legacy
+    /// Poloniex API, `BTC_USDT` in the v3 API, `BTCUSDT` in Binance and
+    /// `XBT/USDT` in Kraken
+    pub fn symbol(&self, base: &str, quote: &str) -> String {
+        match self {
+            Exchange::Poloniex(Api::Legacy) => format!("{}_{}", base, quote),
+            Exchange::Poloniex(Api::V3) => format!("{}_{}", quote, base),
+            Exchange::Binance => format!("{}{}", quote, base),
+            Exchange::Kraken => format!("{}/{}", kraken_asset(quote), kraken_asset(base)),
+        }
+    }
+
+    /// Base and quote of a symbol, `None` if it isn't a symbol of the
+    /// exchange
+    pub fn parse_symbol(&self, symbol: &str) -> Option<(String, String)> {
+        let (base, quote) = match self {
+            Exchange::Poloniex(Api::Legacy) => symbol.split_once('_')?,
+            Exchange::Poloniex(Api::V3) => {
+                let (quote, base) = symbol.split_once('_')?;
+                (base, quote)
+            }
+            Exchange::Binance => {
+                let base = BINANCE_BASES
+                    .iter()
+                    .find(|base| symbol.len() > base.len() && symbol.ends_with(*base))?;
+                (*base, &symbol[..symbol.len() - base.len()])
+            }
+            Exchange::Kraken => {
+                let (quote, base) = symbol.split_once('/')?;
+                return Some((from_kraken_asset(base), from_kraken_asset(quote)));
+            }
+        };
+
+        Some((base.to_string(), quote.to_string()))
+    }
+
+    /// Requests per second and burst allowed by the REST API, see
+    /// `fetcher::RateLimiter`
+    pub fn rate_limit(&self) -> (f64, f64) {
+        match self {
+            Exchange::Poloniex(_) => (fetcher::REQUESTS_PER_SECOND, fetcher::BURST),
+            Exchange::Binance => (binance::REQUESTS_PER_SECOND, binance::BURST),
+            Exchange::Kraken => (kraken::REQUESTS_PER_SECOND, kraken::BURST),
+        }
+    }
+
+    /// Candle periods in seconds that the exchange provides
+    pub fn exchange_periods(&self) -> Vec<i32> {
+        let intervals = match self {
+            Exchange::Poloniex(api) => return api.exchange_periods(),
+            Exchange::Binance => binance::INTERVALS,
+            Exchange::Kraken => kraken::INTERVALS,
+        };
+
+        intervals.iter().map(|(period, _)| *period).collect()
+    }
+}
+
+/// Kraken name of a currency
+pub fn kraken_asset(asset: &str) -> String {
+    KRAKEN_ASSETS
+        .iter()
+        .find(|(bot, _)| *bot == asset)
+        .map_or(asset, |(_, kraken)| kraken)
+        .to_string()
+}
+
+/// Currency of a Kraken currency name
+pub fn from_kraken_asset(asset: &str) -> String {
+    KRAKEN_ASSETS
+        .iter()
+        .find(|(_, kraken)| *kraken == asset)
+        .map_or(asset, |(bot, _)| bot)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limit_test() {
+        let (rate, burst) = Exchange::Kraken.rate_limit();
+        assert!(rate <= 1.0);
+        assert!(burst <= 1.0);
+        assert!(Exchange::Binance.rate_limit().0 > rate);
+    }
+
+    #[test]
+    fn symbol_test() {
+        let exchanges = [
+            (Exchange::Poloniex(Api::Legacy), "USDT_BTC"),
+            (Exchange::Poloniex(Api::V3), "BTC_USDT"),
+            (Exchange::Binance, "BTCUSDT"),
+            (Exchange::Kraken, "XBT/USDT"),
+        ];
+
+        for (exchange, symbol) in exchanges {
+            assert_eq!(exchange.symbol("USDT", "BTC"), symbol);
+            assert_eq!(
+                exchange.parse_symbol(symbol),
+                Some(("USDT".to_string(), "BTC".to_string()))
+            );
+        }
+
+        assert_eq!(
+            Exchange::Binance.parse_symbol("ETHBTC"),
+            Some(("BTC".to_string(), "ETH".to_string()))
+        );
+        assert_eq!(Exchange::Binance.parse_symbol("USDT"), None);
+        assert_eq!(Exchange::Kraken.symbol("USDT", "DOGE"), "XDG/USDT");
+        assert!(Exchange::parse("bitstamp").is_err());
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel::PgConnection;
+
+use crate::api::Api;
+use crate::binance;
+use crate::chart_data::*;
+use crate::correlation::update_correlations;
+use crate::error::BotError;
+use crate::exchange::Exchange;
+use crate::fetcher::{run_pool, Fetcher, WORKERS};
+use crate::kraken;
+use crate::market::update_markets;
+use crate::models::*;
+use crate::poloniex_v3;
+use crate::regime::update_regime;
+use crate::resample::{resample_candles, resample_source};
+use crate::schema::candles;
+use crate::shortlist_logic::{
+    indicator_periods, update_indicators, update_shortlist, update_trades, PERIOD,
+};
+use crate::ticker::*;
+use crate::BASE;
+
+// candles fetched per quote and period
+pub const CANDLES: i32 = 400;
+
+/// Fetches the markets, tickers and candles of `exchange`, and updates the
+/// indicators, regime, shortlist and correlations from them
+pub fn fetch_data(connection: &mut PgConnection, exchange: Exchange) -> Result<(), BotError> {
+    let fetcher = Fetcher::new(exchange)?;
+    let now = Utc::now();
+
+    let (markets, snapshots): (Vec<Market>, Vec<TickerSnapshot>) = match exchange {
+        Exchange::Poloniex(Api::Legacy) => {
+            let tickers = return_tickers(&fetcher, BASE.to_string())?;
+            (
+                tickers
+                    .iter()
+                    .map(ticker_to_market)
+                    .collect::<Result<_, _>>()?,
+                tickers.iter().map(|t| ticker_to_snapshot(t, now)).collect(),
+            )
+        }
+        Exchange::Poloniex(Api::V3) => (
+            poloniex_v3::return_markets(&fetcher, BASE)?,
+            poloniex_v3::return_ticker_snapshots(&fetcher, BASE, now)?,
+        ),
+        Exchange::Binance => (
+            binance::return_markets(&fetcher, BASE)?,
+            binance::return_ticker_snapshots(&fetcher, BASE, now)?,
+        ),
+        Exchange::Kraken => (
+            kraken::return_markets(&fetcher, BASE)?,
+            kraken::return_ticker_snapshots(&fetcher, BASE, now)?,
+        ),
+    };
+
+    update_markets(connection, &markets)?;
+    insert_ticker_snapshots(connection, &snapshots)?;
+
+    let quotes: Vec<String> = markets.into_iter().map(|m| m.quote).collect();
+    let period = PERIOD;
+    let exchange_periods = exchange.exchange_periods();
+
+    let mut requests: Vec<ChartDataRequest> = vec![];
+    for quote in quotes {
+        for &candle_period in PERIODS.iter().filter(|p| exchange_periods.contains(p)) {
+            if let Some(request) = get_chart_data_request(
+                connection,
+                exchange,
+                BASE.to_string(),
+                quote.clone(),
+                candle_period,
+                CANDLES,
+            )? {
+                requests.push(request);
+            }
+        }
+    }
+
+    let total = requests.len();
+    let mut failures: Vec<(ChartDataRequest, BotError)> = vec![];
+    let mut insert_error: Option<BotError> = None;
+
+    run_pool(
+        requests,
+        WORKERS,
+        |request| match exchange {
+            Exchange::Poloniex(Api::Legacy) => {
+                return_chart_data(&fetcher, request).and_then(|chart_datas| {
+                    chart_datas
+                        .into_iter()
+                        .map(|cd| {
+                            chart_data_to_candle(
+                                request.base.clone(),
+                                request.quote.clone(),
+                                request.period,
+                                cd,
+                            )
+                        })
+                        .collect()
+                })
+            }
+            Exchange::Poloniex(Api::V3) => poloniex_v3::return_candles(&fetcher, request),
+            Exchange::Binance => binance::return_candles(&fetcher, request),
+            Exchange::Kraken => kraken::return_candles(&fetcher, request),
+        },
+        |request, result: Result<Vec<Candle>, BotError>| match result {
+            Ok(candles) => {
+                println!("{} {}: {}", request.quote, request.period, candles.len());
+                // candles from the REST API replace the ones built from
+                // public trades or streamed
+                let inserted = diesel::insert_into(candles::table)
+                    .values(&candles)
+                    .on_conflict((
+                        candles::exchange,
+                        candles::base,
+                        candles::quote,
+                        candles::period,
+                        candles::timestamp,
+                    ))
+                    .do_update()
+                    .set((
+                        candles::high.eq(excluded(candles::high)),
+                        candles::low.eq(excluded(candles::low)),
+                        candles::open.eq(excluded(candles::open)),
+                        candles::close.eq(excluded(candles::close)),
+                        candles::average.eq(excluded(candles::average)),
+                        candles::volume.eq(excluded(candles::volume)),
+                        candles::source.eq(excluded(candles::source)),
+                    ))
+                    .execute(connection);
+                if let Err(e) = inserted {
+                    insert_error.get_or_insert(e.into());
+                }
+            }
+            Err(e) => failures.push((request, e)),
+        },
+    );
+
+    if let Some(e) = insert_error {
+        return Err(e.context("inserting candles"));
+    }
+
+    println!("Fetched chart data {}/{}", total - failures.len(), total);
+    for (request, e) in &failures {
+        println!("Failed {} {}: {}", request.quote, request.period, e);
+    }
+
+    for &candle_period in PERIODS.iter().filter(|p| !exchange_periods.contains(p)) {
+        match resample_source(candle_period, &exchange_periods) {
+            Some(source) => {
+                resample_candles(
+                    connection,
+                    exchange,
+                    BASE.to_string(),
+                    source,
+                    candle_period,
+                )?;
+            }
+            None => println!("Can't resample candles to {}", candle_period),
+        }
+    }
+
+    for indicator_period in indicator_periods() {
+        update_indicators(connection, exchange, BASE.to_string(), indicator_period)?;
+    }
+    update_regime(connection, exchange, BASE.to_string(), period)?;
+    update_trades(connection, exchange, BASE.to_string(), period)?;
+    update_shortlist(connection, exchange, BASE.to_string(), period)?;
+    update_correlations(connection, exchange, BASE.to_string(), period)?;
+
+    Ok(())
+}
@@ -0,0 +1,248 @@
+use super::error::{BotError, Context};
+use super::exchange::Exchange;
+use rand::Rng;
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Poloniex allows 6 calls per second to the public API, the other
+// exchanges have their limits in their modules, see `Exchange::rate_limit`
+pub const REQUESTS_PER_SECOND: f64 = 6.0;
+
+// requests that can be sent at once after being idle
+pub const BURST: f64 = 6.0;
+
+// threads fetching chart data at the same time
+pub const WORKERS: usize = 4;
+
+// attempts of a request before giving up
+pub const MAX_ATTEMPTS: u32 = 5;
+
+// delay before the first retry, doubled for each retry after that
+const BACKOFF_INITIAL: Duration = Duration::from_millis(500);
+const BACKOFF_MAX: Duration = Duration::from_secs(16);
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket limiting the rate of requests over all threads
+///
+/// The bucket holds up to `capacity` tokens and gains `rate` tokens per
+/// second, and each request takes one.
+pub struct RateLimiter {
+    rate: f64,
+    capacity: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, capacity: f64) -> RateLimiter {
+        RateLimiter {
+            rate,
+            capacity,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes a token if there is one, otherwise returns how long until there
+    /// is
+    fn try_acquire(&self, now: Instant) -> Result<(), Duration> {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|e| e.into_inner());
+
+        if now > bucket.updated {
+            let elapsed = (now - bucket.updated).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.capacity);
+            bucket.updated = now;
+        }
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate))
+        }
+    }
+
+    /// Blocks until a request can be sent
+    pub fn acquire(&self) {
+        while let Err(wait) = self.try_acquire(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+/// HTTP client shared by the threads fetching from the exchange, with the
+/// rate limit of the exchange
+pub struct Fetcher {
+    client: Client,
+    limiter: RateLimiter,
+}
+
+impl Fetcher {
+    pub fn new(exchange: Exchange) -> Result<Fetcher, BotError> {
+        let (rate, burst) = exchange.rate_limit();
+        let client = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("creating HTTP client")?;
+
+        Ok(Fetcher {
+            client,
+            limiter: RateLimiter::new(rate, burst),
+        })
+    }
+
+    /// Sends a GET request, retrying with exponential backoff on network
+    /// errors and on 429 and 5xx responses
+    ///
+    /// `what` describes the request in errors, e.g. "fetching tickers".
+    pub fn get(&self, url: &str, query: &[(&str, &str)], what: &str) -> Result<Response, BotError> {
+        let mut attempt = 0;
+
+        loop {
+            attempt += 1;
+            self.limiter.acquire();
+
+            let err = match self.client.get(url).query(query).send() {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let err = BotError::Exchange {
+                        message: format!("{}: response {}", what, status),
+                        retryable: is_retryable_status(status),
+                    };
+                    if !err.is_retryable() {
+                        return Err(err);
+                    }
+                    err
+                }
+                Err(err) => BotError::from(err).context(what),
+            };
+
+            if attempt >= MAX_ATTEMPTS {
+                return Err(err.context(&format!("giving up after {} attempts", attempt)));
+            }
+
+            let delay = backoff(attempt);
+            println!("{}, retrying in {:?}", err, delay);
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Whether the exchange may answer successfully to the same request later
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Delay before retrying after `attempt` failed attempts
+///
+/// Up to half of the delay is added at random, so that the workers that hit
+/// the rate limit together don't retry together.
+pub fn backoff(attempt: u32) -> Duration {
+    let delay = BACKOFF_INITIAL
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(BACKOFF_MAX);
+
+    delay.mul_f64(1.0 + rand::thread_rng().gen::<f64>() * 0.5)
+}
+
+/// Runs `work` for each job on `workers` threads, and `done` with each job
+/// and its result on the calling thread as they complete
+///
+/// Jobs are run in order, but may complete in any order.
+pub fn run_pool<J, R, W, D>(jobs: Vec<J>, workers: usize, work: W, mut done: D)
+where
+    J: Send,
+    R: Send,
+    W: Fn(&J) -> R + Sync,
+    D: FnMut(J, R),
+{
+    let queue = Mutex::new(jobs.into_iter());
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            let sender = sender.clone();
+            let queue = &queue;
+            let work = &work;
+
+            scope.spawn(move || loop {
+                let job = queue.lock().unwrap_or_else(|e| e.into_inner()).next();
+                let job = match job {
+                    Some(job) => job,
+                    None => break,
+                };
+
+                let result = work(&job);
+                if sender.send((job, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        for (job, result) in receiver {
+            done(job, result);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limiter_test() {
+        let limiter = RateLimiter::new(2.0, 2.0);
+        let start = limiter.bucket.lock().unwrap().updated;
+
+        assert!(limiter.try_acquire(start).is_ok());
+        assert!(limiter.try_acquire(start).is_ok());
+        assert_eq!(limiter.try_acquire(start), Err(Duration::from_millis(500)));
+        assert!(limiter
+            .try_acquire(start + Duration::from_millis(500))
+            .is_ok());
+        // the bucket doesn't fill over its capacity
+        let later = start + Duration::from_secs(10);
+        assert!(limiter.try_acquire(later).is_ok());
+        assert!(limiter.try_acquire(later).is_ok());
+        assert!(limiter.try_acquire(later).is_err());
+    }
+
+    #[test]
+    fn backoff_test() {
+        let first = backoff(1);
+        assert!(first >= BACKOFF_INITIAL && first <= BACKOFF_INITIAL.mul_f64(1.5));
+        let third = backoff(3);
+        assert!(third >= BACKOFF_INITIAL * 4 && third <= BACKOFF_INITIAL.mul_f64(6.0));
+        assert!(backoff(40) <= BACKOFF_MAX.mul_f64(1.5));
+        assert!(is_retryable_status(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_retryable_status(StatusCode::BAD_REQUEST));
+    }
+
+    #[test]
+    fn run_pool_test() {
+        let mut results = vec![];
+        run_pool(
+            (1..=10).collect(),
+            3,
+            |job: &i32| job * job,
+            |job, result| results.push((job, result)),
+        );
+        results.sort();
+
+        assert_eq!(results.len(), 10);
+        assert_eq!(results[2], (3, 9));
+    }
+}
@@ -0,0 +1,718 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, TimeZone, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use crate::api::Api;
+use crate::chart_data::{ChartDataRequest, SOURCE_REST};
+use crate::decimal::{to_f64, DECIMAL_SCALE};
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
+use crate::fetcher::Fetcher;
+use crate::message::{BookEvent, PublicTrade};
+use crate::models::{Candle, Market, TickerSnapshot};
+use crate::order_book::{OrderBook, OrderType};
+use crate::poloniex_v3::crc32;
+
+pub const API_URL: &str = "https://api.kraken.com/0/public";
+
+pub const WS_URL: &str = "wss://ws.kraken.com";
+
+// the public REST API allows about one call per second
+pub const REQUESTS_PER_SECOND: f64 = 1.0;
+pub const BURST: f64 = 1.0;
+
+// candle periods in seconds and their intervals in minutes
+pub const INTERVALS: &[(i32, &str)] = &[
+    (60, "1"),
+    (300, "5"),
+    (900, "15"),
+    (1800, "30"),
+    (3600, "60"),
+    (14400, "240"),
+    (86400, "1440"),
+    (604800, "10080"),
+];
+
+// levels of each side of the subscribed order books
+pub const BOOK_DEPTH: usize = 25;
+
+// levels of each side of the order book included in its checksum
+pub const CHECKSUM_LEVELS: usize = 10;
+
+pub const BOOK_CHANNEL: &str = "book";
+pub const TRADES_CHANNEL: &str = "trade";
+
+/// Interval in minutes of a candle period
+pub fn interval(period: i32) -> Option<&'static str> {
+    INTERVALS
+        .iter()
+        .find(|(p, _)| *p == period)
+        .map(|(_, name)| *name)
+}
+
+/// Name of a pair in the REST API, the websocket name without the slash,
+/// e.g. `XBTUSDT` of `XBT/USDT`
+pub fn altname(base: &str, quote: &str) -> String {
+    Exchange::Kraken.symbol(base, quote).replace('/', "")
+}
+
+/// Errors are sent in the body of successful responses, next to the result
+#[derive(Deserialize)]
+struct KrakenResponse<T> {
+    error: Vec<String>,
+    result: Option<T>,
+}
+
+fn get_result<T: DeserializeOwned>(
+    fetcher: &Fetcher,
+    method: &str,
+    query: &[(&str, &str)],
+    what: &str,
+) -> Result<T, BotError> {
+    let response: KrakenResponse<T> = fetcher
+        .get(&format!("{}/{}", API_URL, method), query, what)?
+        .json()
+        .context(what)?;
+
+    match response.result {
+        Some(result) if response.error.is_empty() => Ok(result),
+        _ => Err(BotError::Exchange {
+            message: format!("{}: {}", what, response.error.join(", ")),
+            // e.g. "EAPI:Rate limit exceeded" or "EService:Unavailable"
+            retryable: response
+                .error
+                .iter()
+                .any(|e| e.starts_with("EService") || e.contains("Rate limit")),
+        }),
+    }
+}
+
+/// Pair in `/AssetPairs`
+#[derive(Clone, Debug, Deserialize)]
+pub struct AssetPair {
+    pub altname: String,
+    /// e.g. `XBT/USDT`, missing for pairs that can't be subscribed
+    pub wsname: Option<String>,
+    pub pair_decimals: i32,
+    pub lot_decimals: i32,
+    /// Smallest order in the quote currency of the pair, the base of the bot
+    pub costmin: Option<BigDecimal>,
+    /// online, cancel_only, post_only, limit_only or reduce_only
+    pub status: Option<String>,
+}
+
+/// Market with the precisions, order limit and trading status of the pair,
+/// `None` if it has no websocket name
+pub fn to_market(pair: &AssetPair) -> Option<Market> {
+    let (base, quote) = Exchange::Kraken.parse_symbol(pair.wsname.as_deref()?)?;
+    let status = pair.status.as_deref().unwrap_or("online");
+
+    Some(Market {
+        base,
+        quote,
+        price_precision: pair.pair_decimals,
+        amount_precision: pair.lot_decimals,
+        min_order: pair.costmin.clone().unwrap_or_else(BigDecimal::zero),
+        is_frozen: status != "online" && status != "post_only",
+        post_only: status == "post_only",
+        updated_at: Utc::now(),
+        exchange: Exchange::Kraken.name().to_string(),
+    })
+}
+
+/// Fetches the pairs by their REST names, e.g. `XXBTZUSD`
+pub fn return_asset_pairs(fetcher: &Fetcher) -> Result<HashMap<String, AssetPair>, BotError> {
+    get_result(fetcher, "AssetPairs", &[], "fetching asset pairs")
+}
+
+/// Fetches the markets of `base`
+pub fn return_markets(fetcher: &Fetcher, base: &str) -> Result<Vec<Market>, BotError> {
+    println!("Fetching markets");
+
+    Ok(return_asset_pairs(fetcher)?
+        .values()
+        .filter_map(to_market)
+        .filter(|m| m.base == base)
+        .collect())
+}
+
+/// Ticker in `/Ticker`, the lists have the value of today and of the last
+/// 24 hours, or a price followed by volumes
+#[derive(Clone, Debug, Deserialize)]
+pub struct Ticker {
+    /// Ask price, whole lot volume and lot volume
+    pub a: Vec<BigDecimal>,
+    /// Bid price, whole lot volume and lot volume
+    pub b: Vec<BigDecimal>,
+    /// Last trade price and lot volume
+    pub c: Vec<BigDecimal>,
+    /// Volume in the quote currency of the bot
+    pub v: Vec<BigDecimal>,
+    /// Volume weighted average price
+    pub p: Vec<BigDecimal>,
+    pub l: Vec<BigDecimal>,
+    pub h: Vec<BigDecimal>,
+    /// Opening price of the day (UTC)
+    pub o: BigDecimal,
+}
+
+/// Snapshot row of the ticker of the pair `wsname`, `None` if the pair
+/// can't be parsed
+///
+/// The change is from the opening price of the day, Kraken has no 24 hour
+/// change.
+pub fn to_ticker_snapshot(
+    wsname: &str,
+    t: &Ticker,
+    timestamp: DateTime<Utc>,
+) -> Option<TickerSnapshot> {
+    let (base, quote) = Exchange::Kraken.parse_symbol(wsname)?;
+    let last = t.c.first()?;
+    let quote_volume = t.v.get(1)?;
+    let percent_change = if t.o.is_zero() {
+        0.0
+    } else {
+        to_f64(&((last - &t.o) / &t.o))
+    };
+
+    Some(TickerSnapshot {
+        base,
+        quote,
+        timestamp,
+        last: last.clone(),
+        lowest_ask: t.a.first()?.clone(),
+        highest_bid: t.b.first()?.clone(),
+        percent_change: percent_change as f32,
+        base_volume: (quote_volume * t.p.get(1)?).round(DECIMAL_SCALE),
+        quote_volume: quote_volume.clone(),
+        high_24hr: t.h.get(1)?.clone(),
+        low_24hr: t.l.get(1)?.clone(),
+        exchange: Exchange::Kraken.name().to_string(),
+    })
+}
+
+/// Fetches the tickers of the markets of `base`
+pub fn return_ticker_snapshots(
+    fetcher: &Fetcher,
+    base: &str,
+    timestamp: DateTime<Utc>,
+) -> Result<Vec<TickerSnapshot>, BotError> {
+    let pairs = return_asset_pairs(fetcher)?;
+
+    println!("Fetching tickers");
+
+    let tickers: HashMap<String, Ticker> = get_result(fetcher, "Ticker", &[], "fetching tickers")?;
+
+    Ok(tickers
+        .iter()
+        .filter_map(|(name, t)| {
+            let wsname = pairs.get(name)?.wsname.as_deref()?;
+            to_ticker_snapshot(wsname, t, timestamp)
+        })
+        .filter(|t| t.base == base)
+        .collect())
+}
+
+/// Candle in `/OHLC`, an array of time, open, high, low, close, volume
+/// weighted average price, volume and trade count
+#[derive(Clone, Debug, Deserialize)]
+pub struct Ohlc(
+    pub i64,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub i64,
+);
+
+/// Candle of the chart data request, volume is in the base currency of the
+/// bot like in the Poloniex chart data
+pub fn to_candle(request: &ChartDataRequest, o: Ohlc) -> Result<Candle, BotError> {
+    let timestamp = Utc
+        .timestamp_opt(o.0, 0)
+        .single()
+        .ok_or_else(|| BotError::Parse(format!("invalid timestamp {}", o.0)))?;
+
+    Ok(Candle {
+        base: request.base.clone(),
+        quote: request.quote.clone(),
+        period: request.period,
+        timestamp,
+        high: Some(o.2),
+        low: Some(o.3),
+        open: Some(o.1),
+        close: Some(o.4),
+        volume: Some((&o.5 * &o.6).round(DECIMAL_SCALE)),
+        average: Some(o.5),
+        exchange: Exchange::Kraken.name().to_string(),
+        source: SOURCE_REST.to_string(),
+    })
+}
+
+/// Fetches the completed candles of the chart data request
+///
+/// Only the latest 720 candles of a period are available.
+pub fn return_candles(
+    fetcher: &Fetcher,
+    request: &ChartDataRequest,
+) -> Result<Vec<Candle>, BotError> {
+    let name = interval(request.period).ok_or_else(|| {
+        BotError::Config(format!("no candle interval for period {}", request.period))
+    })?;
+    let what = format!("fetching OHLC for {} {}", request.quote, request.period);
+
+    // the candles are under the REST name of the pair, next to "last"
+    let result: HashMap<String, Value> = get_result(
+        fetcher,
+        "OHLC",
+        &[
+            ("pair", altname(&request.base, &request.quote).as_str()),
+            ("interval", name),
+            ("since", request.start.to_string().as_str()),
+        ],
+        &what,
+    )?;
+    let rows: Vec<Ohlc> = match result.into_iter().find(|(key, _)| key != "last") {
+        Some((_, rows)) => serde_json::from_value(rows)?,
+        None => vec![],
+    };
+
+    rows.into_iter()
+        // the candle in progress
+        .filter(|o| o.0 >= request.start && o.0 + request.period as i64 <= request.end)
+        .map(|o| to_candle(request, o))
+        .collect()
+}
+
+/// Subscription in a websocket request
+#[derive(Serialize, Debug)]
+pub struct Subscription {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+}
+
+/// Request sent to the websocket API
+#[derive(Serialize, Debug)]
+pub struct WsRequest {
+    pub event: String,
+    pub pair: Vec<String>,
+    pub subscription: Subscription,
+}
+
+impl WsRequest {
+    pub fn new(event: &str, channel: &str, pairs: &[String]) -> WsRequest {
+        WsRequest {
+            event: event.to_string(),
+            pair: pairs.to_vec(),
+            subscription: Subscription {
+                name: channel.to_string(),
+                depth: (channel == BOOK_CHANNEL).then_some(BOOK_DEPTH),
+            },
+        }
+    }
+}
+
+/// Price and volume of a level as sent, the checksum is computed from the
+/// exact text
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub price: BigDecimal,
+    pub volume: BigDecimal,
+}
+
+/// Order book snapshot or the levels changed in an update, volume of zero
+/// removes the level
+#[derive(Clone, Debug, PartialEq)]
+pub struct BookData {
+    pub pair: String,
+    pub snapshot: bool,
+    pub asks: Vec<Level>,
+    pub bids: Vec<Level>,
+    /// CRC32 of the book after an update, see `book_checksum`
+    pub checksum: Option<u32>,
+}
+
+/// Trade in the trade channel
+#[derive(Clone, Debug, PartialEq)]
+pub struct TradeData {
+    pub price: String,
+    pub volume: String,
+    /// Unix time in seconds with decimals, e.g. `1534614057.321597`
+    pub time: String,
+    /// "b" for buy or "s" for sell
+    pub side: String,
+}
+
+/// Message pushed by the websocket API
+#[derive(Clone, Debug, PartialEq)]
+pub enum WsMessage {
+    /// `{"event": "<heartbeat, subscriptionStatus, ...>", ...}`
+    Event(String),
+    /// `{"event": "<...>", "errorMessage": "<message>"}`
+    Error(String),
+    /// `[<channel id>, {"as": [...], "bs": [...]}, "book-25", "<pair>"]`, or
+    /// updates with `a`, `b` and `c` in one or two objects
+    Book(BookData),
+    /// `[<channel id>, [[<price>, <volume>, <time>, <side>, ...]], "trade", "<pair>"]`
+    Trades { pair: String, data: Vec<TradeData> },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawEvent {
+    event: String,
+    error_message: Option<String>,
+}
+
+fn parse_levels(value: &Value) -> Result<Vec<Level>, BotError> {
+    let rows: Vec<Vec<Value>> = serde_json::from_value(value.clone())?;
+
+    rows.iter()
+        .map(|row| {
+            let field = |i: usize| {
+                row.get(i)
+                    .and_then(Value::as_str)
+                    .and_then(|s| BigDecimal::from_str(s).ok())
+                    .ok_or_else(|| BotError::Parse(format!("invalid level {:?}", row)))
+            };
+            Ok(Level {
+                price: field(0)?,
+                volume: field(1)?,
+            })
+        })
+        .collect()
+}
+
+/// Parses a websocket text frame
+pub fn parse_ws_message(input: &str) -> Result<WsMessage, BotError> {
+    let unknown = || BotError::Parse(format!("unknown message {}", input));
+    let value: Value = serde_json::from_str(input).map_err(|_| unknown())?;
+
+    let items = match value {
+        Value::Array(items) if items.len() >= 4 => items,
+        Value::Object(_) => {
+            let event: RawEvent = serde_json::from_value(value).map_err(|_| unknown())?;
+            return Ok(match event.error_message {
+                Some(message) => WsMessage::Error(message),
+                None => WsMessage::Event(event.event),
+            });
+        }
+        _ => return Err(unknown()),
+    };
+
+    // channel id, payloads, channel name and pair
+    let pair = items[items.len() - 1].as_str().ok_or_else(unknown)?;
+    let channel = items[items.len() - 2].as_str().ok_or_else(unknown)?;
+    let payloads = &items[1..items.len() - 2];
+
+    if channel.starts_with(BOOK_CHANNEL) {
+        let mut book = BookData {
+            pair: pair.to_string(),
+            snapshot: false,
+            asks: vec![],
+            bids: vec![],
+            checksum: None,
+        };
+        for payload in payloads {
+            let fields = payload.as_object().ok_or_else(unknown)?;
+            for (key, value) in fields {
+                match key.as_str() {
+                    "as" | "bs" => book.snapshot = true,
+                    _ => (),
+                }
+                match key.as_str() {
+                    "as" | "a" => book.asks.extend(parse_levels(value)?),
+                    "bs" | "b" => book.bids.extend(parse_levels(value)?),
+                    "c" => {
+                        book.checksum = value.as_str().and_then(|c| c.parse::<u32>().ok());
+                    }
+                    _ => (),
+                }
+            }
+        }
+        Ok(WsMessage::Book(book))
+    } else if channel == TRADES_CHANNEL {
+        let rows: Vec<Vec<Value>> =
+            serde_json::from_value(payloads.first().cloned().ok_or_else(unknown)?)?;
+        let data = rows
+            .iter()
+            .map(|row| {
+                let field = |i: usize| {
+                    row.get(i)
+                        .and_then(Value::as_str)
+                        .map(str::to_string)
+                        .ok_or_else(|| BotError::Parse(format!("invalid trade {:?}", row)))
+                };
+                Ok(TradeData {
+                    price: field(0)?,
+                    volume: field(1)?,
+                    time: field(2)?,
+                    side: field(3)?,
+                })
+            })
+            .collect::<Result<Vec<TradeData>, BotError>>()?;
+        Ok(WsMessage::Trades {
+            pair: pair.to_string(),
+            data,
+        })
+    } else {
+        Err(BotError::Parse(format!("unknown channel {}", channel)))
+    }
+}
+
+/// Public trade of the trade data, Kraken has no trade ids so the id is
+/// made of the time, price and volume
+pub fn to_public_trade(t: TradeData) -> Result<PublicTrade, BotError> {
+    let invalid = || BotError::Parse(format!("invalid trade {:?}", t));
+    let timestamp = t
+        .time
+        .split('.')
+        .next()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or_else(invalid)?;
+
+    Ok(PublicTrade {
+        id: format!("{}_{}_{}", t.time, t.price, t.volume),
+        is_buy: t.side == "b",
+        price: BigDecimal::from_str(&t.price).map_err(|_| invalid())?,
+        size: BigDecimal::from_str(&t.volume).map_err(|_| invalid())?,
+        timestamp,
+    })
+}
+
+/// Digits of a price or volume in the checksum, without the decimal point
+/// and leading zeros
+fn checksum_digits(value: &BigDecimal) -> String {
+    value
+        .to_string()
+        .replace('.', "")
+        .trim_start_matches('0')
+        .to_string()
+}
+
+/// Checksum of the order book, CRC32 of the price and volume digits of the
+/// best `CHECKSUM_LEVELS` asks from the lowest price up followed by the
+/// best bids from the highest price down
+pub fn book_checksum(order_book: &OrderBook) -> u32 {
+    let digits: String = order_book
+        .asks()
+        .take(CHECKSUM_LEVELS)
+        .chain(order_book.bids().take(CHECKSUM_LEVELS))
+        .map(|level| checksum_digits(&level.price) + &checksum_digits(&level.size))
+        .collect();
+
+    crc32(digits.as_bytes())
+}
+
+/// Order books of the subscribed pairs, kept to validate the updates
+#[derive(Debug, Default)]
+pub struct BookTracker {
+    books: HashMap<String, OrderBook>,
+}
+
+impl BookTracker {
+    pub fn new() -> BookTracker {
+        BookTracker::default()
+    }
+
+    /// Applies a snapshot or an update, and returns it as order book events
+    ///
+    /// Levels pushed out of the subscribed depth are removed, as Kraken
+    /// doesn't send updates for them. Fails if the checksum doesn't match,
+    /// the pair is then dropped and its updates are skipped until the next
+    /// snapshot.
+    pub fn apply(&mut self, data: BookData) -> Result<Vec<BookEvent>, BotError> {
+        let mut order_book = if data.snapshot {
+            OrderBook::new()
+        } else {
+            match self.books.remove(&data.pair) {
+                Some(order_book) => order_book,
+                None => return Ok(vec![]),
+            }
+        };
+
+        let mut events = vec![];
+        for (order_type, levels) in [(OrderType::Ask, &data.asks), (OrderType::Bid, &data.bids)] {
+            for level in levels {
+                order_book.update(
+                    order_type.clone(),
+                    level.price.clone(),
+                    level.volume.clone(),
+                );
+                events.push(BookEvent::Update {
+                    order_type: order_type.clone(),
+                    price: level.price.clone(),
+                    size: level.volume.clone(),
+                });
+            }
+            order_book.truncate(order_type, BOOK_DEPTH);
+        }
+
+        let checksum = book_checksum(&order_book);
+        if let Some(expected) = data.checksum {
+            if checksum != expected {
+                return Err(BotError::Parse(format!(
+                    "{} checksum {} doesn't match {}",
+                    data.pair, expected, checksum
+                )));
+            }
+        }
+
+        if data.snapshot {
+            let (base, quote) = Exchange::Kraken
+                .parse_symbol(&data.pair)
+                .ok_or_else(|| BotError::Parse(format!("invalid pair {}", data.pair)))?;
+            events = vec![BookEvent::Snapshot {
+                currency_pair: Exchange::Poloniex(Api::Legacy).symbol(&base, &quote),
+                order_book: order_book.clone(),
+            }];
+        }
+        self.books.insert(data.pair, order_book);
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse_rest_test() {
+        let response: KrakenResponse<HashMap<String, AssetPair>> = serde_json::from_str(
+            r#"{"error": [], "result": {"XBTUSDT": {"altname": "XBTUSDT", "wsname": "XBT/USDT",
+              "aclass_base": "currency", "base": "XXBT", "quote": "USDT", "pair_decimals": 1,
+              "cost_decimals": 5, "lot_decimals": 8, "lot_multiplier": 1, "ordermin": "0.0001",
+              "costmin": "0.5", "tick_size": "0.1", "status": "online"}}}"#,
+        )
+        .unwrap();
+        let market = to_market(&response.result.unwrap()["XBTUSDT"]).unwrap();
+        assert_eq!(
+            (market.base.as_str(), market.quote.as_str()),
+            ("USDT", "BTC")
+        );
+        assert_eq!((market.price_precision, market.amount_precision), (1, 8));
+        assert_eq!(market.min_order, d("0.5"));
+        assert!(!market.is_frozen);
+
+        let ticker: Ticker = serde_json::from_str(
+            r#"{"a": ["30300.10000", "1", "1.000"], "b": ["30300.00000", "1", "1.000"],
+              "c": ["30303.20000", "0.00067643"], "v": ["4083.67001100", "4412.73601799"],
+              "p": ["30706.77771", "30689.13205"], "t": [34619, 38907],
+              "l": ["29868.30000", "29868.30000"], "h": ["31631.00000", "31631.00000"],
+              "o": "30000.00000"}"#,
+        )
+        .unwrap();
+        let snapshot = to_ticker_snapshot("XBT/USDT", &ticker, Utc::now()).unwrap();
+        assert_eq!(snapshot.quote, "BTC");
+        assert_eq!(snapshot.percent_change, 0.010106667);
+        assert_eq!(snapshot.quote_volume, d("4412.73601799"));
+
+        let rows: Vec<Ohlc> = serde_json::from_str(
+            r#"[[1688671200, "30306.1", "30306.2", "30305.7", "30305.7", "30306.1", "2", 3]]"#,
+        )
+        .unwrap();
+        let request = ChartDataRequest {
+            exchange: Exchange::Kraken,
+            base: "USDT".to_string(),
+            quote: "BTC".to_string(),
+            period: 900,
+            start: 1688671200,
+            end: 1688672100,
+        };
+        let candle = to_candle(&request, rows[0].clone()).unwrap();
+        assert_eq!(candle.open, Some(d("30306.1")));
+        assert_eq!(candle.volume, Some(d("60612.2")));
+        assert_eq!(candle.exchange, "kraken");
+        assert_eq!(altname("USDT", "BTC"), "XBTUSDT");
+    }
+
+    #[test]
+    fn book_tracker_test() {
+        assert_eq!(
+            parse_ws_message(r#"{"event": "heartbeat"}"#).unwrap(),
+            WsMessage::Event("heartbeat".to_string())
+        );
+        assert_eq!(
+            parse_ws_message(
+                r#"{"event": "subscriptionStatus", "status": "error",
+                  "errorMessage": "Currency pair not supported XBT/ABC"}"#
+            )
+            .unwrap(),
+            WsMessage::Error("Currency pair not supported XBT/ABC".to_string())
+        );
+
+        let checksum = |s: &str| crc32(s.as_bytes());
+        let mut tracker = BookTracker::new();
+
+        let snapshot = parse_ws_message(
+            r#"[0, {"as": [["5541.30000", "2.50700000", "1534614248.123678"],
+                           ["5541.80000", "0.33000000", "1534614098.345543"]],
+                    "bs": [["5541.20000", "1.52900000", "1534614248.765567"]]},
+              "book-25", "XBT/USD"]"#,
+        )
+        .unwrap();
+        let snapshot = match snapshot {
+            WsMessage::Book(book) => book,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert!(snapshot.snapshot);
+        let events = tracker.apply(snapshot).unwrap();
+        assert!(matches!(
+            &events[..],
+            [BookEvent::Snapshot { currency_pair, .. }] if currency_pair == "USD_BTC"
+        ));
+
+        // asks and bids of an update may come in separate objects
+        let update = parse_ws_message(&format!(
+            r#"[0, {{"a": [["5541.30000", "0.00000000", "1534614335.345903"]]}},
+                {{"b": [["5541.00000", "0.40100000", "1534614335.345903", "r"]],
+                  "c": "{}"}}, "book-25", "XBT/USD"]"#,
+            checksum("5541800003300000055412000015290000055410000040100000")
+        ))
+        .unwrap();
+        let update = match update {
+            WsMessage::Book(book) => book,
+            other => panic!("unexpected {:?}", other),
+        };
+        assert!(!update.snapshot);
+        assert_eq!(tracker.apply(update.clone()).unwrap().len(), 2);
+
+        // a wrong checksum drops the book until the next snapshot
+        let wrong = BookData {
+            asks: vec![],
+            bids: vec![],
+            checksum: Some(1),
+            ..update.clone()
+        };
+        assert!(tracker.apply(wrong).is_err());
+        assert!(tracker.apply(update).unwrap().is_empty());
+
+        let trades = parse_ws_message(
+            r#"[0, [["5541.20000", "0.15850568", "1534614057.321597", "s", "l", ""]],
+              "trade", "XBT/USD"]"#,
+        )
+        .unwrap();
+        let trade = match trades {
+            WsMessage::Trades { pair, mut data } => {
+                assert_eq!(pair, "XBT/USD");
+                to_public_trade(data.remove(0)).unwrap()
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+        assert!(!trade.is_buy);
+        assert_eq!(trade.timestamp, 1534614057);
+        assert_eq!(trade.size, d("0.15850568"));
+    }
+}
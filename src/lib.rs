@@ -2,12 +2,33 @@
 extern crate diesel;
 extern crate dotenv;
 
+pub mod api;
+pub mod binance;
+pub mod candle_builder;
 pub mod chart_data;
+pub mod cooldown;
+pub mod correlation;
+pub mod decimal;
+pub mod error;
+pub mod exchange;
+pub mod fetch;
+pub mod fetcher;
+pub mod kraken;
+pub mod market;
+pub mod message;
+#[cfg(test)]
+pub mod mock_server;
 pub mod models;
 pub mod order_book;
+pub mod poloniex_v3;
+pub mod regime;
+pub mod resample;
+pub mod risk;
 pub mod schema;
+pub mod selection;
 pub mod shortlist;
 pub mod shortlist_logic;
+pub mod stream;
 pub mod ticker;
 pub mod trade;
 pub mod trade_logic;
@@ -15,14 +36,35 @@ pub mod trade_logic;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
 use dotenv::dotenv;
+use error::{BotError, Context};
 use std::env;
 
 pub const BASE: &str = "USDT";
 
-pub fn establish_connection() -> PgConnection {
+pub fn establish_connection() -> Result<PgConnection, BotError> {
     dotenv().ok();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+    let database_url = env::var("DATABASE_URL")
+        .map_err(|_| BotError::Config("DATABASE_URL must be set".to_string()))?;
+    PgConnection::establish(&database_url).context("connecting to database")
+}
+
+/// Connection to the database of `TEST_DATABASE_URL`, which must be set,
+/// and a lock so that the tests using the database run one at a time
+#[cfg(test)]
+pub fn test_connection() -> (std::sync::MutexGuard<'static, ()>, PgConnection) {
+    static DATABASE: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    let lock = DATABASE.lock().unwrap_or_else(|e| e.into_inner());
+    let database_url = env::var("TEST_DATABASE_URL").expect("TEST_DATABASE_URL must be set");
+    (lock, PgConnection::establish(&database_url).unwrap())
+}
+
+/// Runs the main function of a binary, and on error prints it to stderr and
+/// exits with the exit code of the error
+pub fn run_main(main: impl FnOnce() -> Result<(), BotError>) {
+    if let Err(err) = main() {
+        eprintln!("{}", err);
+        std::process::exit(err.exit_code());
+    }
 }
@@ -0,0 +1,116 @@
+extern crate diesel;
+
+use super::decimal::round_down;
+use super::diesel::prelude::*;
+use super::error::{BotError, Context};
+use super::exchange::Exchange;
+use super::models::*;
+use bigdecimal::{BigDecimal, Zero};
+
+impl Market {
+    /// Rounds a price down to the tick size, so that a bid never goes above
+    /// the intended price
+    pub fn round_price(&self, price: &BigDecimal) -> BigDecimal {
+        round_down(price, self.price_precision as i64)
+    }
+
+    /// Rounds an amount in quote currency down to the lot size, so that
+    /// more than the position is never sold
+    pub fn round_amount(&self, amount: &BigDecimal) -> BigDecimal {
+        round_down(amount, self.amount_precision as i64)
+    }
+
+    /// Whether an order of `amount` at `price` is large enough to be placed
+    pub fn is_order_valid(&self, price: &BigDecimal, amount: &BigDecimal) -> bool {
+        !amount.is_zero() && price * amount >= self.min_order
+    }
+}
+
+/// Inserts or updates the markets, returns the number of markets stored
+pub fn update_markets(connection: &mut PgConnection, rows: &[Market]) -> Result<usize, BotError> {
+    use super::schema::markets::dsl::*;
+
+    println!("updating markets");
+
+    for market in rows {
+        diesel::insert_into(markets)
+            .values(market)
+            .on_conflict((exchange, base, quote))
+            .do_update()
+            .set(market)
+            .execute(connection)
+            .context("updating markets")?;
+    }
+
+    Ok(rows.len())
+}
+
+/// Gets the markets of `base` in `exchange_p` that aren't frozen
+pub fn get_markets(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+    base_p: &str,
+) -> Result<Vec<Market>, BotError> {
+    use super::schema::markets::dsl::*;
+
+    let rows = markets
+        .filter(exchange.eq(exchange_p.name()))
+        .filter(base.eq(base_p))
+        .filter(is_frozen.eq(false))
+        .order(quote.asc())
+        .load::<Market>(connection)?;
+
+    Ok(rows)
+}
+
+/// Gets a market, `None` if the markets haven't been fetched or the market
+/// isn't listed
+pub fn get_market(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+    base_p: &str,
+    quote_p: &str,
+) -> Result<Option<Market>, BotError> {
+    use super::schema::markets::dsl::*;
+
+    let row = markets
+        .find((exchange_p.name(), base_p, quote_p))
+        .first::<Market>(connection)
+        .optional()?;
+
+    Ok(row)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn rounding_test() {
+        let market = Market {
+            base: "USDT".to_string(),
+            quote: "LTC".to_string(),
+            price_precision: 2,
+            amount_precision: 4,
+            min_order: d("1"),
+            is_frozen: false,
+            post_only: false,
+            updated_at: Utc::now(),
+            exchange: "poloniex".to_string(),
+        };
+
+        assert_eq!(market.round_price(&d("123.456")), d("123.45"));
+        assert_eq!(market.round_price(&d("123.4")), d("123.40"));
+        assert_eq!(market.round_amount(&d("0.81234567")), d("0.8123"));
+
+        assert!(market.is_order_valid(&d("123.45"), &d("0.0082")));
+        assert!(!market.is_order_valid(&d("123.45"), &d("0.008")));
+        assert!(!market.is_order_valid(&d("123.45"), &d("0")));
+    }
+}
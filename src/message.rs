@@ -0,0 +1,249 @@
+use crate::error::BotError;
+use crate::models::Candle;
+use crate::order_book::{OrderBook, OrderType, PoloniexOrderBook};
+use bigdecimal::BigDecimal;
+use serde::de::{self, Deserializer, IgnoredAny, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fmt;
+use std::str::FromStr;
+
+pub const API_URL: &str = "wss://api2.poloniex.com";
+
+pub const HEARTBEAT_ID: u32 = 1010;
+
+/// Command sent to the websocket API, e.g. to subscribe to a channel
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Command {
+    pub command: String,
+    pub channel: String,
+}
+
+/// Message pushed by the Poloniex websocket API
+#[derive(Clone, Debug, PartialEq)]
+pub enum PushMessage {
+    /// `[1010]`
+    Heartbeat,
+    /// `[<channel id>, <1 if subscribed, 0 if unsubscribed>]`
+    Subscription { channel_id: u32, subscribed: bool },
+    /// `[<channel id>, <sequence number>, [<event>, ...]]`
+    Update {
+        channel_id: u32,
+        sequence_num: u32,
+        events: Vec<BookEvent>,
+    },
+    /// `{"error": "<message>"}`
+    Error(String),
+}
+
+/// Single event in a channel update
+#[derive(Clone, Debug, PartialEq)]
+pub enum BookEvent {
+    /// `["i", <order book snapshot>, "<epoch_ms>"]`
+    Snapshot {
+        currency_pair: String,
+        order_book: OrderBook,
+    },
+    /// `["o", <1 for bid 0 for ask>, "<price>", "<size>", "<epoch_ms>"]`,
+    /// size of zero removes the level
+    Update {
+        order_type: OrderType,
+        price: BigDecimal,
+        size: BigDecimal,
+    },
+    /// `["t", "<trade id>", <1 for buy 0 for sell>, "<price>", "<size>", <timestamp>, "<epoch_ms>"]`
+    Trade(PublicTrade),
+    /// Candle pushed by the exchange, only in the v3 API
+    Candle(Candle),
+}
+
+/// Trade that happened in the market
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublicTrade {
+    pub id: String,
+    /// Whether the buyer took the ask
+    pub is_buy: bool,
+    pub price: BigDecimal,
+    pub size: BigDecimal,
+    /// Unix time in seconds
+    pub timestamp: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawMessage {
+    Error { error: String },
+    Heartbeat((u32,)),
+    Subscription((u32, u8)),
+    Update((u32, u32, Vec<Value>)),
+}
+
+/// Parses a websocket text frame
+///
+/// Events of an update that can't be parsed, e.g. of an unknown kind, are
+/// logged and skipped so that the rest of the update can still be used.
+pub fn parse_message(input: &str) -> Result<PushMessage, BotError> {
+    let raw: RawMessage = serde_json::from_str(input)
+        .map_err(|_| BotError::Parse(format!("unknown message {}", input)))?;
+
+    let message = match raw {
+        RawMessage::Error { error } => PushMessage::Error(error),
+        RawMessage::Heartbeat((HEARTBEAT_ID,)) => PushMessage::Heartbeat,
+        RawMessage::Heartbeat((channel_id,)) => {
+            return Err(BotError::Parse(format!(
+                "unknown message on channel {}",
+                channel_id
+            )))
+        }
+        RawMessage::Subscription((channel_id, subscribed)) => PushMessage::Subscription {
+            channel_id,
+            subscribed: subscribed == 1,
+        },
+        RawMessage::Update((channel_id, sequence_num, raw_events)) => {
+            let events = raw_events
+                .into_iter()
+                .filter_map(|event| match serde_json::from_value(event) {
+                    Ok(event) => Some(event),
+                    Err(err) => {
+                        println!("skipping event on channel {}: {}", channel_id, err);
+                        None
+                    }
+                })
+                .collect();
+
+            PushMessage::Update {
+                channel_id,
+                sequence_num,
+                events,
+            }
+        }
+    };
+
+    Ok(message)
+}
+
+impl<'de> Deserialize<'de> for BookEvent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BookEvent, D::Error> {
+        deserializer.deserialize_seq(BookEventVisitor)
+    }
+}
+
+struct BookEventVisitor;
+
+impl<'de> Visitor<'de> for BookEventVisitor {
+    type Value = BookEvent;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an order book event array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<BookEvent, A::Error> {
+        let kind: String = next(&mut seq, 0)?;
+
+        let event = match kind.as_str() {
+            "i" => {
+                let snapshot: PoloniexOrderBook = next(&mut seq, 1)?;
+                BookEvent::Snapshot {
+                    order_book: OrderBook::from_snapshot(&snapshot).map_err(de::Error::custom)?,
+                    currency_pair: snapshot.currency_pair,
+                }
+            }
+            "o" => {
+                let side: u8 = next(&mut seq, 1)?;
+                BookEvent::Update {
+                    order_type: match side {
+                        1 => OrderType::Bid,
+                        _ => OrderType::Ask,
+                    },
+                    price: next_decimal(&mut seq, 2)?,
+                    size: next_decimal(&mut seq, 3)?,
+                }
+            }
+            "t" => {
+                let id: String = next(&mut seq, 1)?;
+                let side: u8 = next(&mut seq, 2)?;
+                BookEvent::Trade(PublicTrade {
+                    id,
+                    is_buy: side == 1,
+                    price: next_decimal(&mut seq, 3)?,
+                    size: next_decimal(&mut seq, 4)?,
+                    timestamp: next(&mut seq, 5)?,
+                })
+            }
+            other => return Err(de::Error::unknown_variant(other, &["i", "o", "t"])),
+        };
+
+        // epoch_ms and possible fields added later
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+
+        Ok(event)
+    }
+}
+
+fn next<'de, A: SeqAccess<'de>, T: Deserialize<'de>>(
+    seq: &mut A,
+    index: usize,
+) -> Result<T, A::Error> {
+    seq.next_element()?
+        .ok_or_else(|| de::Error::invalid_length(index, &"a longer event array"))
+}
+
+fn next_decimal<'de, A: SeqAccess<'de>>(seq: &mut A, index: usize) -> Result<BigDecimal, A::Error> {
+    let s: String = next(seq, index)?;
+    BigDecimal::from_str(&s).map_err(de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_message_test() {
+        assert_eq!(parse_message("[1010]").unwrap(), PushMessage::Heartbeat);
+        assert_eq!(
+            parse_message("[148, 1]").unwrap(),
+            PushMessage::Subscription {
+                channel_id: 148,
+                subscribed: true
+            }
+        );
+        assert_eq!(
+            parse_message(r#"{"error": "Invalid channel."}"#).unwrap(),
+            PushMessage::Error("Invalid channel.".to_string())
+        );
+
+        let update = parse_message(
+            r#"[148, 42, [
+              ["o", 1, "123.5", "0.25", "1674043205000"],
+              ["t", "4813405", 0, "123.4", "1.5", 1674043205, "1674043205123"],
+              ["x", "unknown"],
+              ["o", 0, "not a price", "1.0", "1674043205000"]
+            ]]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            update,
+            PushMessage::Update {
+                channel_id: 148,
+                sequence_num: 42,
+                events: vec![
+                    BookEvent::Update {
+                        order_type: OrderType::Bid,
+                        price: BigDecimal::from_str("123.5").unwrap(),
+                        size: BigDecimal::from_str("0.25").unwrap(),
+                    },
+                    BookEvent::Trade(PublicTrade {
+                        id: "4813405".to_string(),
+                        is_buy: false,
+                        price: BigDecimal::from_str("123.4").unwrap(),
+                        size: BigDecimal::from_str("1.5").unwrap(),
+                        timestamp: 1674043205,
+                    }),
+                ],
+            }
+        );
+
+        assert!(parse_message("[148, 42, {}]").is_err());
+        assert!(parse_message("not json").is_err());
+    }
+}
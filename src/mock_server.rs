@@ -0,0 +1,375 @@
+//! Mock of the legacy Poloniex API for testing the bot end to end
+//!
+//! The REST API answers `returnTicker` and `returnChartData` from the markets
+//! of a scenario, the candles follow each market's daily change up to its
+//! price at the time of the request. The websocket API waits for a client to
+//! subscribe to a pair and then sends the frames scripted for it, e.g. an
+//! order book snapshot followed by updates that move the price.
+//!
+//! Scenarios are JSON files in `scenarios/`, see `Scenario`.
+
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use bigdecimal::BigDecimal;
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tungstenite::{accept, Message};
+use url::Url;
+
+use crate::message::Command;
+
+// candles span this much above and below their open and close prices
+const CANDLE_RANGE: f64 = 0.003;
+
+/// Market served by the mock, e.g. `{"pair": "USDT_BTC", "price": 100.0,
+/// "daily_change": 0.02, "spread": 0.001, "base_volume": 1000000.0}`
+#[derive(Clone, Debug, Deserialize)]
+pub struct MockMarket {
+    pub pair: String,
+    /// Price now, the highest bid of the ticker
+    pub price: f64,
+    /// Relative price change per day, the candles are computed from it
+    pub daily_change: f64,
+    /// Relative spread of the ticker
+    pub spread: f64,
+    /// 24h volume in base currency
+    pub base_volume: f64,
+}
+
+/// What the bot is expected to do in a scenario
+#[derive(Clone, Debug, Deserialize)]
+pub struct Expectation {
+    pub regime: String,
+    pub shortlist: Vec<String>,
+    pub trades: Vec<ExpectedTrade>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExpectedTrade {
+    pub quote: String,
+    pub entry_type: String,
+    pub open: BigDecimal,
+    pub close_reason: String,
+    /// Reasons of the executions in order
+    pub executions: Vec<String>,
+}
+
+/// Market data served by the mock, and what the bot should do with it
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    pub description: String,
+    pub markets: Vec<MockMarket>,
+    /// Websocket frames sent to a client subscribing to the pair
+    pub streams: HashMap<String, Vec<Value>>,
+    pub expect: Expectation,
+}
+
+impl Scenario {
+    pub fn parse(json: &str) -> Result<Scenario, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    fn market(&self, pair: &str) -> Option<&MockMarket> {
+        self.markets.iter().find(|m| m.pair == pair)
+    }
+}
+
+/// Mock REST and websocket servers on local ports, running until the
+/// process exits
+pub struct MockServer {
+    pub rest_url: String,
+    pub ws_url: String,
+}
+
+impl MockServer {
+    pub fn start(scenario: &Scenario) -> io::Result<MockServer> {
+        let rest = TcpListener::bind("127.0.0.1:0")?;
+        let ws = TcpListener::bind("127.0.0.1:0")?;
+        let server = MockServer {
+            rest_url: format!("http://{}/public", rest.local_addr()?),
+            ws_url: format!("ws://{}", ws.local_addr()?),
+        };
+
+        let scenario = Arc::new(scenario.clone());
+        let rest_scenario = scenario.clone();
+        thread::spawn(move || serve(rest, rest_scenario, respond));
+        thread::spawn(move || serve(ws, scenario, stream_frames));
+
+        Ok(server)
+    }
+
+    /// Points the bot at the mock, see `api::legacy_rest_url` and
+    /// `api::legacy_ws_url`
+    pub fn use_endpoints(&self) {
+        env::set_var("EXCHANGE", "poloniex");
+        env::set_var("POLONIEX_API", "legacy");
+        env::set_var("POLONIEX_REST_URL", &self.rest_url);
+        env::set_var("POLONIEX_WS_URL", &self.ws_url);
+    }
+}
+
+fn serve(
+    listener: TcpListener,
+    scenario: Arc<Scenario>,
+    handle: fn(TcpStream, &Scenario) -> io::Result<()>,
+) {
+    for stream in listener.incoming().flatten() {
+        let scenario = scenario.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &scenario) {
+                println!("mock server: {}", e);
+            }
+        });
+    }
+}
+
+/// Answers a single HTTP request
+fn respond(mut stream: TcpStream, scenario: &Scenario) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the requests are GETs, so the headers end the request
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? <= 2 {
+            break;
+        }
+    }
+
+    let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+    let query: HashMap<String, String> = Url::parse(&format!("http://mock{}", target))
+        .map(|url| url.query_pairs().into_owned().collect())
+        .unwrap_or_default();
+
+    let body = match query.get("command").map(String::as_str) {
+        Some("returnTicker") => Some(tickers(scenario)),
+        Some("returnChartData") => chart_data(scenario, &query),
+        _ => None,
+    };
+    let (status, body) = match body {
+        Some(body) => ("200 OK", body),
+        None => ("400 Bad Request", json!({"error": "Invalid command."})),
+    };
+    let body = body.to_string();
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+fn tickers(scenario: &Scenario) -> Value {
+    let tickers: serde_json::Map<String, Value> = scenario
+        .markets
+        .iter()
+        .enumerate()
+        .map(|(i, m)| {
+            let ticker = json!({
+                "id": i,
+                "last": format!("{:.8}", m.price),
+                "lowestAsk": format!("{:.8}", m.price * (1.0 + m.spread)),
+                "highestBid": format!("{:.8}", m.price),
+                "percentChange": format!("{:.8}", m.daily_change),
+                "baseVolume": format!("{:.8}", m.base_volume),
+                "quoteVolume": format!("{:.8}", m.base_volume / m.price),
+                "isFrozen": "0",
+                "postOnly": "0",
+                "high24hr": format!("{:.8}", m.price * (1.0 + m.daily_change.abs())),
+                "low24hr": format!("{:.8}", m.price * (1.0 - m.daily_change.abs())),
+            });
+            (m.pair.clone(), ticker)
+        })
+        .collect();
+
+    Value::Object(tickers)
+}
+
+/// Candles of the pair that have closed between start and end
+fn chart_data(scenario: &Scenario, query: &HashMap<String, String>) -> Option<Value> {
+    let market = scenario.market(query.get("currencyPair")?)?;
+    let period: i64 = query.get("period")?.parse().ok()?;
+    let start: i64 = query.get("start")?.parse().ok()?;
+    let end: i64 = query.get("end")?.parse().ok()?;
+
+    let now = Utc::now().timestamp();
+    let price_at = |timestamp: i64| {
+        market.price * (1.0 + market.daily_change).powf((timestamp - now) as f64 / 86400.0)
+    };
+
+    let mut candles = vec![];
+    let mut timestamp = (start + period - 1) / period * period;
+    while timestamp + period <= end {
+        let open = price_at(timestamp);
+        let close = price_at(timestamp + period);
+        let average = price_at(timestamp + period / 2);
+        let volume = market.base_volume * period as f64 / 86400.0;
+        candles.push(json!({
+            "date": format!("{}000", timestamp),
+            "high": format!("{:.8}", open.max(close) * (1.0 + CANDLE_RANGE)),
+            "low": format!("{:.8}", open.min(close) * (1.0 - CANDLE_RANGE)),
+            "open": format!("{:.8}", open),
+            "close": format!("{:.8}", close),
+            "volume": format!("{:.8}", volume),
+            "quoteVolume": format!("{:.8}", volume / average),
+            "weightedAverage": format!("{:.8}", average),
+        }));
+        timestamp += period;
+    }
+
+    Some(Value::Array(candles))
+}
+
+/// Sends the scripted frames once the client subscribes, and then closes
+/// the connection
+fn stream_frames(stream: TcpStream, scenario: &Scenario) -> io::Result<()> {
+    let to_io = |e: tungstenite::Error| io::Error::other(e.to_string());
+    let mut socket = accept(stream).map_err(|e| io::Error::other(e.to_string()))?;
+
+    let frames = loop {
+        if let Message::Text(text) = socket.read_message().map_err(to_io)? {
+            match serde_json::from_str::<Command>(&text) {
+                Ok(command) if command.command == "subscribe" => {
+                    if let Some(frames) = scenario.streams.get(&command.channel) {
+                        break frames;
+                    }
+                }
+                _ => (),
+            }
+        }
+    };
+
+    for frame in frames {
+        socket
+            .write_message(Message::Text(frame.to_string()))
+            .map_err(to_io)?;
+    }
+    socket.close(None).map_err(to_io)?;
+
+    // the client may still be reading the frames, so wait for it to close too
+    while socket.read_message().is_ok() {}
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::Exchange;
+    use crate::fetch::fetch_data;
+    use crate::models::Trade;
+    use crate::regime::get_regime;
+    use crate::selection::select_trades;
+    use crate::test_connection;
+    use crate::trade::get_executions;
+    use crate::trade_logic::do_trade;
+    use diesel::prelude::*;
+    use diesel::sql_query;
+
+    // tables the flows write to, in order of deletion
+    const TABLES: &[&str] = &[
+        "executions",
+        "trades",
+        "cooldowns",
+        "halts",
+        "shortlist",
+        "trend_verdicts",
+        "correlations",
+        "regimes",
+        "indicators",
+        "candles",
+        "market_trades",
+        "ticker_snapshots",
+        "markets",
+    ];
+
+    /// Runs fetch_data, select_trade and do_trade against the scenario
+    ///
+    /// The tables are emptied first, so the database is given with
+    /// `TEST_DATABASE_URL` rather than `DATABASE_URL`, which must be set.
+    fn run_scenario(json: &str) {
+        // the lock is shared with the other database tests, and also guards
+        // the endpoint variables
+        let (_lock, mut connection) = test_connection();
+
+        let scenario = Scenario::parse(json).unwrap();
+        println!("scenario: {}", scenario.description);
+        let server = MockServer::start(&scenario).unwrap();
+        server.use_endpoints();
+
+        let connection = &mut connection;
+        for table in TABLES {
+            sql_query(format!("DELETE FROM {}", table))
+                .execute(connection)
+                .unwrap();
+        }
+
+        let exchange = Exchange::from_env().unwrap();
+        fetch_data(connection, exchange).unwrap();
+
+        let regime = get_regime(connection, exchange).unwrap().unwrap();
+        assert_eq!(regime.regime, scenario.expect.regime);
+        // get_shortlist would empty the shortlist
+        let shortlist: Vec<String> = {
+            use crate::schema::shortlist::dsl::*;
+            shortlist
+                .select(quote)
+                .order(quote)
+                .load(connection)
+                .unwrap()
+        };
+        assert_eq!(shortlist, scenario.expect.shortlist);
+
+        let trades = select_trades(connection, exchange, &[]).unwrap();
+        assert_eq!(trades.len(), scenario.expect.trades.len());
+
+        for (trade, expected) in trades.iter().zip(&scenario.expect.trades) {
+            assert_eq!(trade.quote, expected.quote);
+            do_trade(connection, trade.id).unwrap();
+
+            let trade: Trade = {
+                use crate::schema::trades::dsl::*;
+                trades.find(trade.id).first(connection).unwrap()
+            };
+            assert_eq!(
+                trade.entry_type.as_deref(),
+                Some(expected.entry_type.as_str())
+            );
+            assert_eq!(trade.open.as_ref(), Some(&expected.open));
+            assert_eq!(
+                trade.close_reason.as_deref(),
+                Some(expected.close_reason.as_str())
+            );
+            assert!(trade.close.is_some());
+
+            let reasons: Vec<String> = get_executions(connection, &trade)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.reason)
+                .collect();
+            assert_eq!(reasons, expected.executions);
+        }
+    }
+
+    // the scenarios need a database, run them with
+    // TEST_DATABASE_URL=... cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn rise_then_drop_test() {
+        run_scenario(include_str!("../scenarios/rise_then_drop.json"));
+    }
+
+    #[test]
+    #[ignore]
+    fn take_profit_then_drop_test() {
+        run_scenario(include_str!("../scenarios/take_profit_then_drop.json"));
+    }
+}
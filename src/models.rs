@@ -1,20 +1,41 @@
+use bigdecimal::BigDecimal;
 use chrono::{DateTime, Utc};
 
-use super::schema::{candles, shortlist, trades};
+use super::schema::{
+    candles, cooldowns, correlations, executions, halts, indicators, market_trades, markets,
+    regimes, shortlist, ticker_snapshots, trades, trend_verdicts,
+};
 
-#[derive(Debug, Insertable, Queryable)]
+#[derive(Debug, Insertable, Queryable, AsChangeset, Clone, PartialEq)]
 #[diesel(table_name = candles)]
 pub struct Candle {
     pub base: String,
     pub quote: String,
     pub period: i32,
     pub timestamp: DateTime<Utc>,
-    pub high: Option<f32>,
-    pub low: Option<f32>,
-    pub open: Option<f32>,
-    pub close: Option<f32>,
-    pub average: Option<f32>,
-    pub volume: Option<f32>,
+    pub high: Option<BigDecimal>,
+    pub low: Option<BigDecimal>,
+    pub open: Option<BigDecimal>,
+    pub close: Option<BigDecimal>,
+    pub average: Option<BigDecimal>,
+    pub volume: Option<BigDecimal>,
+    pub exchange: String,
+    pub source: String,
+}
+
+#[derive(Debug, Insertable, Queryable)]
+#[diesel(table_name = indicators)]
+pub struct Indicator {
+    pub base: String,
+    pub quote: String,
+    pub period: i32,
+    pub timestamp: DateTime<Utc>,
+    pub ma_short: Option<BigDecimal>,
+    pub ma_med: Option<BigDecimal>,
+    pub ma_long: Option<BigDecimal>,
+    pub base_volume_med: Option<BigDecimal>,
+    pub volatility_med: Option<f32>,
+    pub exchange: String,
 }
 
 #[derive(Debug, Insertable, Queryable, Clone)]
@@ -22,9 +43,11 @@ pub struct Candle {
 pub struct Shortlist {
     pub quote: String,
     pub timestamp: DateTime<Utc>,
-    pub average: f32,
-    pub target: f32,
+    pub average: BigDecimal,
+    pub target: BigDecimal,
     pub confidence: f32,
+    pub stop_loss: f32,
+    pub exchange: String,
 }
 
 #[derive(Debug, Identifiable, Insertable, Queryable, Clone)]
@@ -36,11 +59,25 @@ pub struct Trade {
     pub open_at: DateTime<Utc>,
     pub close_at: Option<DateTime<Utc>>,
     pub updated_at: DateTime<Utc>,
-    pub open_average: f32,
-    pub target: f32,
-    pub open: Option<f32>,
-    pub close: Option<f32>,
-    pub highest_bid: Option<f32>,
+    pub open_average: BigDecimal,
+    pub target: BigDecimal,
+    pub open: Option<BigDecimal>,
+    pub close: Option<BigDecimal>,
+    pub highest_bid: Option<BigDecimal>,
+    pub stop_loss: f32,
+    pub remaining: BigDecimal,
+    pub take_profit_level: i32,
+    pub profit: Option<f32>,
+    pub close_reason: Option<String>,
+    pub size: BigDecimal,
+    pub entry_bid: Option<BigDecimal>,
+    pub entry_bid_at: Option<DateTime<Utc>>,
+    pub entry_bids: i32,
+    pub entry_type: Option<String>,
+    pub exit_reason: Option<String>,
+    pub exit_started_at: Option<DateTime<Utc>>,
+    pub exit_slices: i32,
+    pub exchange: String,
 }
 
 #[derive(Debug, Insertable)]
@@ -48,8 +85,141 @@ pub struct Trade {
 pub struct NewTrade {
     pub base: String,
     pub quote: String,
-    pub target: f32,
-    pub open_average: f32,
+    pub target: BigDecimal,
+    pub open_average: BigDecimal,
     pub open_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub stop_loss: f32,
+    pub size: BigDecimal,
+    pub exchange: String,
+}
+
+#[derive(Debug, Identifiable, Queryable, Associations, Clone)]
+#[diesel(belongs_to(Trade))]
+#[diesel(table_name = executions)]
+pub struct Execution {
+    pub id: i32,
+    pub trade_id: i32,
+    pub executed_at: DateTime<Utc>,
+    pub price: BigDecimal,
+    pub portion: BigDecimal,
+    pub reason: String,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = executions)]
+pub struct NewExecution {
+    pub trade_id: i32,
+    pub executed_at: DateTime<Utc>,
+    pub price: BigDecimal,
+    pub portion: BigDecimal,
+    pub reason: String,
+}
+
+#[derive(Debug, Insertable, Queryable, AsChangeset, Clone)]
+#[diesel(table_name = cooldowns)]
+pub struct Cooldown {
+    pub quote: String,
+    pub losses: i32,
+    pub until: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub exchange: String,
+}
+
+#[derive(Debug, Identifiable, Queryable, Clone)]
+#[diesel(table_name = halts)]
+pub struct Halt {
+    pub id: i32,
+    pub created_at: DateTime<Utc>,
+    pub reason: String,
+    pub until: Option<DateTime<Utc>>,
+    pub reset_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = halts)]
+pub struct NewHalt {
+    pub created_at: DateTime<Utc>,
+    pub reason: String,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Identifiable, Queryable, Clone)]
+#[diesel(table_name = regimes)]
+pub struct Regime {
+    pub id: i32,
+    pub timestamp: DateTime<Utc>,
+    pub trend_average: Option<BigDecimal>,
+    pub trend_ma_long: Option<BigDecimal>,
+    pub breadth: Option<f32>,
+    pub quotes: i32,
+    pub regime: String,
+    pub exchange: String,
+}
+
+#[derive(Debug, Insertable, Queryable, Clone)]
+#[diesel(table_name = correlations)]
+pub struct Correlation {
+    pub quote_a: String,
+    pub quote_b: String,
+    pub timestamp: DateTime<Utc>,
+    pub correlation: f32,
+    pub samples: i32,
+    pub exchange: String,
+}
+
+#[derive(Debug, Insertable, Queryable, AsChangeset, Clone)]
+#[diesel(table_name = markets)]
+pub struct Market {
+    pub base: String,
+    pub quote: String,
+    pub price_precision: i32,
+    pub amount_precision: i32,
+    pub min_order: BigDecimal,
+    pub is_frozen: bool,
+    pub post_only: bool,
+    pub updated_at: DateTime<Utc>,
+    pub exchange: String,
+}
+
+#[derive(Debug, Insertable, Queryable, Clone)]
+#[diesel(table_name = ticker_snapshots)]
+pub struct TickerSnapshot {
+    pub base: String,
+    pub quote: String,
+    pub timestamp: DateTime<Utc>,
+    pub last: BigDecimal,
+    pub lowest_ask: BigDecimal,
+    pub highest_bid: BigDecimal,
+    pub percent_change: f32,
+    pub base_volume: BigDecimal,
+    pub quote_volume: BigDecimal,
+    pub high_24hr: BigDecimal,
+    pub low_24hr: BigDecimal,
+    pub exchange: String,
+}
+
+#[derive(Debug, Insertable, Queryable, Clone)]
+#[diesel(table_name = market_trades)]
+pub struct MarketTrade {
+    pub base: String,
+    pub quote: String,
+    pub trade_id: String,
+    pub timestamp: DateTime<Utc>,
+    pub is_buy: bool,
+    pub price: BigDecimal,
+    pub size: BigDecimal,
+    pub exchange: String,
+}
+
+#[derive(Debug, Insertable, Queryable, Clone)]
+#[diesel(table_name = trend_verdicts)]
+pub struct TrendVerdict {
+    pub base: String,
+    pub quote: String,
+    pub period: i32,
+    pub trend: String,
+    pub timestamp: DateTime<Utc>,
+    pub passed: bool,
+    pub exchange: String,
 }
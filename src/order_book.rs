@@ -1,169 +1,390 @@
+use crate::decimal::{decimal, to_f64, DECIMAL_SCALE};
+use crate::error::BotError;
+use crate::message::BookEvent;
+use bigdecimal::{BigDecimal, Zero};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::HashMap;
-
-pub const HEARTBEAT_ID: u32 = 1010;
-pub const F64_EPSILON: f64 = 1e-10;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
 
+/// Order book snapshot as sent in Poloniex `i` messages, price levels
+/// mapped to sizes as decimal strings, asks first
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub struct PoloniexMessage {
-    pub channel_id: u32,
-    pub sequence_num: Option<u32>,
-    pub messages: Vec<Value>,
-}
-
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-struct PoloniexOrderBook {
+pub struct PoloniexOrderBook {
     #[serde(rename = "currencyPair")]
     pub currency_pair: String,
     #[serde(rename = "orderBook")]
     pub order_book: (HashMap<String, String>, HashMap<String, String>),
 }
 
+/// Side of the order book
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum OrderType {
     Bid,
     Ask,
 }
 
+/// Single price level of the order book
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct OrderBookEntry {
-    order_type: OrderType,
-    size: f64,
-    pub price: f64,
+    pub order_type: OrderType,
+    /// Total size of the level in quote currency
+    pub size: BigDecimal,
+    pub price: BigDecimal,
 }
 
-pub type OrderBook = HashMap<String, OrderBookEntry>;
-
-#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
-pub struct OrderBookMiddle {
-    pub highest_bid: Option<OrderBookEntry>,
-    pub lowest_ask: Option<OrderBookEntry>,
+/// Order book with bid and ask levels sorted by exact decimal price
+///
+/// Best bid and ask prices are cached, so that reading them doesn't need
+/// to search the levels.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct OrderBook {
+    bids: BTreeMap<BigDecimal, BigDecimal>,
+    asks: BTreeMap<BigDecimal, BigDecimal>,
+    best_bid: Option<BigDecimal>,
+    best_ask: Option<BigDecimal>,
+    // sizes of the bid levels used by simulated sells, see `walk_bids`,
+    // until the next update of the level
+    used_bids: BTreeMap<BigDecimal, BigDecimal>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct Command {
-    pub command: String,
-    pub channel: String,
-}
+impl OrderBook {
+    /// Empty order book
+    pub fn new() -> OrderBook {
+        OrderBook::default()
+    }
+
+    /// Builds an order book from a snapshot
+    ///
+    /// Fails if a price or a size can't be parsed.
+    pub fn from_snapshot(snapshot: &PoloniexOrderBook) -> Result<OrderBook, BotError> {
+        let mut ret = OrderBook::new();
+        let (asks, bids) = &snapshot.order_book;
 
-pub fn parse_message(input: String) -> PoloniexMessage {
-    let parsed: Vec<Value> = serde_json::from_str(&input).unwrap();
-    let channel_id: u32 = serde_json::from_value(parsed[0].clone()).unwrap();
-    match channel_id {
-        HEARTBEAT_ID => PoloniexMessage {
-            channel_id: channel_id,
-            sequence_num: None,
-            messages: vec![],
-        },
-        _ => {
-            let sequence_num: u32 = serde_json::from_value(parsed[1].clone()).unwrap();
-            let messages: Vec<Value> = serde_json::from_value(parsed[2].clone()).unwrap();
-            PoloniexMessage {
-                channel_id: channel_id,
-                sequence_num: Some(sequence_num),
-                messages: messages,
+        for (order_type, levels) in [(OrderType::Ask, asks), (OrderType::Bid, bids)] {
+            for (price_s, size_s) in levels {
+                let price = BigDecimal::from_str(price_s)
+                    .map_err(|_| BotError::Parse(format!("invalid price {}", price_s)))?;
+                let size = BigDecimal::from_str(size_s)
+                    .map_err(|_| BotError::Parse(format!("invalid size {}", size_s)))?;
+                ret.update(order_type.clone(), price, size);
             }
         }
+
+        Ok(ret)
     }
-}
 
-pub fn find_middle(order_book: OrderBook) -> OrderBookMiddle {
-    let mut highest_bid: Option<OrderBookEntry> = None;
-    let mut lowest_ask: Option<OrderBookEntry> = None;
-
-    for entry in order_book.into_values() {
-        match entry.order_type {
-            OrderType::Bid => match highest_bid {
-                None => highest_bid = Some(entry),
-                Some(h) if { entry.price > h.price } => highest_bid = Some(entry),
-                _ => (),
-            },
-            OrderType::Ask => match lowest_ask {
-                None => lowest_ask = Some(entry),
-                Some(l) if { entry.price < l.price } => lowest_ask = Some(entry),
-                _ => (),
-            },
+    /// Snapshot of the order book for `currency_pair`, can be serialised
+    /// with serde and read back with `from_snapshot`
+    pub fn to_snapshot(&self, currency_pair: &str) -> PoloniexOrderBook {
+        let levels = |side: &BTreeMap<BigDecimal, BigDecimal>| -> HashMap<String, String> {
+            side.iter()
+                .map(|(price, size)| (price.to_string(), size.to_string()))
+                .collect()
+        };
+
+        PoloniexOrderBook {
+            currency_pair: currency_pair.to_string(),
+            order_book: (levels(&self.asks), levels(&self.bids)),
         }
     }
 
-    OrderBookMiddle {
-        highest_bid: highest_bid,
-        lowest_ask: lowest_ask,
+    /// Whether both sides are empty
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    /// Bid levels from the highest price down
+    pub fn bids(&self) -> impl Iterator<Item = OrderBookEntry> + '_ {
+        self.levels(OrderType::Bid)
+    }
+
+    /// Ask levels from the lowest price up
+    pub fn asks(&self) -> impl Iterator<Item = OrderBookEntry> + '_ {
+        self.levels(OrderType::Ask)
+    }
+
+    /// Sets the size of a price level, size of zero removes the level
+    pub fn update(&mut self, order_type: OrderType, price: BigDecimal, size: BigDecimal) {
+        if order_type == OrderType::Bid {
+            self.used_bids.remove(&price);
+        }
+        let (levels, best) = match order_type {
+            OrderType::Bid => (&mut self.bids, &mut self.best_bid),
+            OrderType::Ask => (&mut self.asks, &mut self.best_ask),
+        };
+
+        if size <= BigDecimal::zero() {
+            levels.remove(&price);
+            if best.as_ref() == Some(&price) {
+                *best = match order_type {
+                    OrderType::Bid => levels.keys().next_back().cloned(),
+                    OrderType::Ask => levels.keys().next().cloned(),
+                };
+            }
+            return;
+        }
+
+        let is_better = match (&order_type, best.as_ref()) {
+            (_, None) => true,
+            (OrderType::Bid, Some(b)) => price > *b,
+            (OrderType::Ask, Some(b)) => price < *b,
+        };
+        if is_better {
+            *best = Some(price.clone());
+        }
+        levels.insert(price, size);
+    }
+
+    /// Best bid level
+    pub fn highest_bid(&self) -> Option<OrderBookEntry> {
+        self.best_bid
+            .as_ref()
+            .map(|price| entry(OrderType::Bid, price, &self.bids[price]))
+    }
+
+    /// Best ask level
+    pub fn lowest_ask(&self) -> Option<OrderBookEntry> {
+        self.best_ask
+            .as_ref()
+            .map(|price| entry(OrderType::Ask, price, &self.asks[price]))
+    }
+
+    /// Best bid and ask levels
+    pub fn middle(&self) -> OrderBookMiddle {
+        OrderBookMiddle {
+            highest_bid: self.highest_bid(),
+            lowest_ask: self.lowest_ask(),
+        }
+    }
+
+    /// Levels of one side from the best price outwards
+    pub fn levels(&self, order_type: OrderType) -> Box<dyn Iterator<Item = OrderBookEntry> + '_> {
+        match order_type {
+            OrderType::Bid => Box::new(
+                self.bids
+                    .iter()
+                    .rev()
+                    .map(|(price, size)| entry(OrderType::Bid, price, size)),
+            ),
+            OrderType::Ask => Box::new(
+                self.asks
+                    .iter()
+                    .map(|(price, size)| entry(OrderType::Ask, price, size)),
+            ),
+        }
+    }
+
+    /// Removes the levels of one side past the best `depth` ones, for
+    /// exchanges that only update the levels within a subscribed depth
+    pub fn truncate(&mut self, order_type: OrderType, depth: usize) {
+        let worse: Vec<BigDecimal> = self
+            .levels(order_type.clone())
+            .skip(depth)
+            .map(|level| level.price)
+            .collect();
+
+        for price in worse {
+            self.update(order_type.clone(), price, BigDecimal::zero());
+        }
     }
-}
 
-pub fn update_orderbook(order_book: Option<OrderBook>, input: Value) -> Option<OrderBook> {
-    // ["o", <1 for bid 0 for ask>, "<price>", "<size>", "<epoch_ms>"]
-    let bid: u8 = serde_json::from_value(input[1].clone()).unwrap();
-    let price_s: String = serde_json::from_value(input[2].clone()).unwrap();
-    let size_s: String = serde_json::from_value(input[3].clone()).unwrap();
-    let price: f64 = price_s.parse::<f64>().unwrap();
-    let size: f64 = size_s.parse::<f64>().unwrap();
-    let order_type: OrderType = match bid {
-        1 => OrderType::Bid,
-        _ => OrderType::Ask,
-    };
-    let new_val = if size < F64_EPSILON {
-        None
-    } else {
-        Some(OrderBookEntry {
-            order_type: order_type,
-            price: price,
-            size: size,
-        })
-    };
-
-    match order_book {
-        None => None,
-        Some(ob) => {
-            let mut new_ob = ob.clone();
-            match new_val {
-                None => new_ob.remove(&price_s),
-                Some(val) => new_ob.insert(price_s, val),
+    /// Splits trading `amount` against the levels of one side from the best
+    /// price outwards, not going past `limit_price`, all levels if `amount`
+    /// is `None`
+    fn walk(
+        &self,
+        order_type: OrderType,
+        amount: Option<&BigDecimal>,
+        limit_price: Option<&BigDecimal>,
+    ) -> Vec<(BigDecimal, BigDecimal)> {
+        let mut left = amount.cloned();
+        let mut fills: Vec<(BigDecimal, BigDecimal)> = vec![];
+
+        for level in self.levels(order_type) {
+            let past_limit = match (&level.order_type, limit_price) {
+                (_, None) => false,
+                (OrderType::Bid, Some(limit)) => level.price < *limit,
+                (OrderType::Ask, Some(limit)) => level.price > *limit,
+            };
+            if past_limit {
+                break;
+            }
+            let size = match left.as_mut() {
+                None => level.size,
+                Some(l) if *l <= BigDecimal::zero() => break,
+                Some(l) => {
+                    let size = l.clone().min(level.size);
+                    *l -= &size;
+                    size
+                }
             };
-            Some(new_ob)
+            fills.push((level.price, size));
+        }
+
+        fills
+    }
+
+    /// Average of the highest bid and the lowest ask
+    pub fn mid_price(&self) -> Option<BigDecimal> {
+        match (self.highest_bid(), self.lowest_ask()) {
+            (Some(bid), Some(ask)) => Some((bid.price + ask.price) / BigDecimal::from(2)),
+            _ => None,
+        }
+    }
+
+    /// Cumulative size of one side within `range` (0.01 for 1%) of the
+    /// middle price
+    pub fn depth(&self, order_type: OrderType, range: f64) -> Result<BigDecimal, BotError> {
+        let mid = match self.mid_price() {
+            Some(mid) => mid,
+            None => return Ok(BigDecimal::zero()),
+        };
+        let limit = match order_type {
+            OrderType::Bid => mid * (BigDecimal::from(1) - decimal(range)?),
+            OrderType::Ask => mid * (BigDecimal::from(1) + decimal(range)?),
+        };
+        Ok(self
+            .walk(order_type, None, Some(&limit))
+            .into_iter()
+            .map(|(_, size)| size)
+            .sum())
+    }
+
+    /// Expected average price for trading `amount` against one side, asks
+    /// when buying and bids when selling
+    ///
+    /// Returns `None` if the side isn't deep enough for the whole amount.
+    pub fn fill_price(&self, order_type: OrderType, amount: &BigDecimal) -> Option<BigDecimal> {
+        let fills = self.walk(order_type, Some(amount), None);
+        let filled: BigDecimal = fills.iter().map(|(_, size)| size).sum();
+        if filled < *amount {
+            return None;
+        }
+        get_average_price(&fills)
+    }
+
+    /// Bid depth minus ask depth within `range` of the middle price,
+    /// relative to their sum, between -1 (only asks) and 1 (only bids)
+    pub fn imbalance(&self, range: f64) -> Result<Option<f64>, BotError> {
+        let bids = self.depth(OrderType::Bid, range)?;
+        let asks = self.depth(OrderType::Ask, range)?;
+        let total = &bids + &asks;
+        if total.is_zero() {
+            return Ok(None);
+        }
+        Ok(Some(to_f64(&((bids - asks) / total))))
+    }
+
+    /// Middle price weighted by the sizes at the best levels, so that it
+    /// leans towards the side with less size
+    pub fn microprice(&self) -> Option<BigDecimal> {
+        match (self.highest_bid(), self.lowest_ask()) {
+            (Some(bid), Some(ask)) => {
+                let weighted = &bid.price * &ask.size + &ask.price * &bid.size;
+                Some((weighted / (bid.size + ask.size)).round(DECIMAL_SCALE))
+            }
+            _ => None,
         }
     }
 }
 
-pub fn parse_orderbook(input: Value) -> Option<OrderBook> {
-    let parsed: PoloniexOrderBook = serde_json::from_value(input).unwrap();
-    let mut ret: HashMap<String, OrderBookEntry> = HashMap::new();
-
-    for (price_s, size_s) in parsed.order_book.0 {
-        let price: f64 = price_s.parse::<f64>().unwrap();
-        let size: f64 = size_s.parse::<f64>().unwrap();
-        ret.insert(
-            price_s,
-            OrderBookEntry {
-                order_type: OrderType::Ask,
-                price: price,
-                size: size,
-            },
-        );
+fn entry(order_type: OrderType, price: &BigDecimal, size: &BigDecimal) -> OrderBookEntry {
+    OrderBookEntry {
+        order_type,
+        size: size.clone(),
+        price: price.clone(),
     }
+}
 
-    for (price_s, size_s) in parsed.order_book.1 {
-        let price: f64 = price_s.parse::<f64>().unwrap();
-        let size: f64 = size_s.parse::<f64>().unwrap();
-        ret.insert(
-            price_s,
-            OrderBookEntry {
-                order_type: OrderType::Bid,
-                price: price,
-                size: size,
-            },
-        );
+/// Best levels of both sides, `None` for an empty side
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct OrderBookMiddle {
+    pub highest_bid: Option<OrderBookEntry>,
+    pub lowest_ask: Option<OrderBookEntry>,
+}
+
+/// Splits selling `amount` to the bid levels from the highest price down,
+/// not going below `min_price`
+///
+/// Returns the price and amount sold for each level. The amounts add up to
+/// less than `amount` if the bids above `min_price` aren't deep enough. The
+/// amounts sold are left out of the levels in the following calls, until
+/// the level is updated.
+pub fn walk_bids(
+    order_book: &mut OrderBook,
+    amount: &BigDecimal,
+    min_price: &BigDecimal,
+) -> Vec<(BigDecimal, BigDecimal)> {
+    let mut left = amount.clone();
+    let mut fills: Vec<(BigDecimal, BigDecimal)> = vec![];
+
+    for level in order_book.bids() {
+        if level.price < *min_price || left <= BigDecimal::zero() {
+            break;
+        }
+        let available = match order_book.used_bids.get(&level.price) {
+            Some(used) => &level.size - used,
+            None => level.size,
+        };
+        if available <= BigDecimal::zero() {
+            continue;
+        }
+        let size = left.clone().min(available);
+        left -= &size;
+        fills.push((level.price, size));
+    }
+
+    for (price, size) in fills.iter() {
+        let used = order_book
+            .used_bids
+            .entry(price.clone())
+            .or_insert_with(BigDecimal::zero);
+        *used += size;
+    }
+
+    fills
+}
+
+/// Volume weighted average price of (price, amount) fills
+pub fn get_average_price(fills: &[(BigDecimal, BigDecimal)]) -> Option<BigDecimal> {
+    let amount: BigDecimal = fills.iter().map(|(_, size)| size).sum();
+    if amount.is_zero() {
+        return None;
+    }
+    let value: BigDecimal = fills.iter().map(|(price, size)| price * size).sum();
+    Some((value / amount).round(DECIMAL_SCALE))
+}
+
+/// Applies the level update of an `o` event to the order book
+pub fn update_orderbook(order_book: &mut OrderBook, input: Value) -> Result<(), BotError> {
+    match serde_json::from_value(input)? {
+        BookEvent::Update {
+            order_type,
+            price,
+            size,
+        } => {
+            order_book.update(order_type, price, size);
+            Ok(())
+        }
+        other => Err(BotError::Parse(format!("not a book update {:?}", other))),
     }
+}
 
-    Some(ret)
+/// Builds an order book from the snapshot of an `i` event
+pub fn parse_orderbook(input: Value) -> Result<OrderBook, BotError> {
+    let parsed: PoloniexOrderBook = serde_json::from_value(input)?;
+    OrderBook::from_snapshot(&parsed)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
     const INPUT: &str = r#"{
         "currencyPair": "USDT_LTC",
         "orderBook": [
@@ -197,178 +418,235 @@ mod tests {
 
     #[test]
     fn parse_orderbook_test() {
-        let res = parse_orderbook(INPUT.to_string());
-        let expected = HashMap::from([
-            (
-                "123.61626625".to_string(),
+        let res = parse_orderbook(serde_json::from_str(INPUT).unwrap()).unwrap();
+
+        assert_eq!(
+            res.bids().collect::<Vec<OrderBookEntry>>(),
+            vec![
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 30.54540138,
-                    price: 123.61626625,
+                    size: d("30.54540138"),
+                    price: d("123.61626625"),
                 },
-            ),
-            (
-                "1235.20000000".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Ask,
-                    size: 163.08933013,
-                    price: 1235.2,
+                    order_type: OrderType::Bid,
+                    size: d("8.36335043"),
+                    price: d("123.61626624"),
                 },
-            ),
-            (
-                "123.15153929".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 145.7,
-                    price: 123.15153929,
+                    size: d("15.00000000"),
+                    price: d("123.61432906"),
                 },
-            ),
-            (
-                "999.77707823".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Ask,
-                    size: 15.70192415,
-                    price: 999.77707823,
+                    order_type: OrderType::Bid,
+                    size: d("34.09055384"),
+                    price: d("123.58845138"),
                 },
-            ),
-            (
-                "123.71470735".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Ask,
-                    size: 0.80831133,
-                    price: 123.71470735,
+                    order_type: OrderType::Bid,
+                    size: d("72.80000000"),
+                    price: d("123.54238195"),
                 },
-            ),
-            (
-                "123.29200001".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 50.0,
-                    price: 123.29200001,
+                    size: d("4.90000000"),
+                    price: d("123.42600000"),
                 },
-            ),
-            (
-                "123.39300000".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 12.9,
-                    price: 123.393,
+                    size: d("12.90000000"),
+                    price: d("123.39300000"),
                 },
-            ),
-            (
-                "123.61626624".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 8.36335043,
-                    price: 123.61626624,
+                    size: d("50.00000000"),
+                    price: d("123.29200001"),
                 },
-            ),
-            (
-                "123.96200000".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Ask,
-                    size: 4.9,
-                    price: 123.962,
+                    order_type: OrderType::Bid,
+                    size: d("25.50000000"),
+                    price: d("123.15200000"),
                 },
-            ),
-            (
-                "123.54238195".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 72.8,
-                    price: 123.54238195,
+                    size: d("145.70000000"),
+                    price: d("123.15153929"),
                 },
-            ),
-            (
-                "123.58845138".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 34.09055384,
-                    price: 123.58845138,
+                    size: d("22222222.22222222"),
+                    price: d("0.00000009"),
                 },
-            ),
-            (
-                "0.00000002".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 11524758480.5,
-                    price: 0.00000002,
+                    size: d("11524758480.50000000"),
+                    price: d("0.00000002"),
                 },
-            ),
-            (
-                "123.15200000".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Bid,
-                    size: 25.5,
-                    price: 123.152,
+                    size: d("6164523.62636999"),
+                    price: d("0.00000001"),
                 },
-            ),
-            (
-                "123.87038423".to_string(),
+            ]
+        );
+        assert_eq!(
+            res.asks().collect::<Vec<OrderBookEntry>>(),
+            vec![
                 OrderBookEntry {
                     order_type: OrderType::Ask,
-                    size: 72.8,
-                    price: 123.87038423,
+                    size: d("0.80831133"),
+                    price: d("123.71470735"),
                 },
-            ),
-            (
-                "0.00000001".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Bid,
-                    size: 6164523.62636999,
-                    price: 0.00000001,
+                    order_type: OrderType::Ask,
+                    size: d("72.80000000"),
+                    price: d("123.87038423"),
                 },
-            ),
-            (
-                "123.92495682".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Ask,
-                    size: 37.096372,
-                    price: 123.92495682,
+                    size: d("37.09637200"),
+                    price: d("123.92495682"),
                 },
-            ),
-            (
-                "123.61432906".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Bid,
-                    size: 15.0,
-                    price: 123.61432906,
+                    order_type: OrderType::Ask,
+                    size: d("4.90000000"),
+                    price: d("123.96200000"),
                 },
-            ),
-            (
-                "1235.00000000".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Ask,
-                    size: 17.6,
-                    price: 1235.0,
+                    size: d("12.90000000"),
+                    price: d("124.04400000"),
                 },
-            ),
-            (
-                "0.00000009".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Bid,
-                    size: 22222222.22222222,
-                    price: 0.00000009,
+                    order_type: OrderType::Ask,
+                    size: d("15.70192415"),
+                    price: d("999.77707823"),
                 },
-            ),
-            (
-                "123.42600000".to_string(),
                 OrderBookEntry {
-                    order_type: OrderType::Bid,
-                    size: 4.9,
-                    price: 123.426,
+                    order_type: OrderType::Ask,
+                    size: d("17.60000000"),
+                    price: d("1235.00000000"),
                 },
-            ),
-            (
-                "124.04400000".to_string(),
                 OrderBookEntry {
                     order_type: OrderType::Ask,
-                    size: 12.9,
-                    price: 124.044,
+                    size: d("163.08933013"),
+                    price: d("1235.20000000"),
                 },
-            ),
-        ]);
+            ]
+        );
+        assert_eq!(
+            res.middle(),
+            OrderBookMiddle {
+                highest_bid: Some(OrderBookEntry {
+                    order_type: OrderType::Bid,
+                    size: d("30.54540138"),
+                    price: d("123.61626625"),
+                }),
+                lowest_ask: Some(OrderBookEntry {
+                    order_type: OrderType::Ask,
+                    size: d("0.80831133"),
+                    price: d("123.71470735"),
+                }),
+            }
+        );
+    }
+
+    #[test]
+    fn update_orderbook_test() {
+        let mut ob = parse_orderbook(serde_json::from_str(INPUT).unwrap()).unwrap();
+
+        // same price with different number of decimals is the same level
+        update_orderbook(
+            &mut ob,
+            serde_json::json!(["o", 1, "123.6162662500", "1.5", "0"]),
+        )
+        .unwrap();
+        assert_eq!(ob.bids.len(), 13);
+        assert_eq!(ob.highest_bid().unwrap().size, d("1.5"));
+
+        // removing the best bid falls back to the next level
+        update_orderbook(
+            &mut ob,
+            serde_json::json!(["o", 1, "123.61626625", "0.00000000", "0"]),
+        )
+        .unwrap();
+        assert_eq!(ob.bids.len(), 12);
+        assert_eq!(ob.highest_bid().unwrap().price, d("123.61626624"));
+
+        // new best ask
+        update_orderbook(&mut ob, serde_json::json!(["o", 0, "123.7", "2.0", "0"])).unwrap();
+        assert_eq!(ob.lowest_ask().unwrap().price, d("123.7"));
+    }
+
+    #[test]
+    fn walk_bids_test() {
+        let mut ob = parse_orderbook(serde_json::from_str(INPUT).unwrap()).unwrap();
+        let mut fresh = ob.clone();
+
+        let fills = walk_bids(&mut ob, &d("40"), &d("123.6"));
+        assert_eq!(
+            fills,
+            vec![
+                (d("123.61626625"), d("30.54540138")),
+                (d("123.61626624"), d("8.36335043")),
+                (d("123.61432906"), d("1.09124819")),
+            ]
+        );
+
+        // the sizes sold already are left out until the level is updated
+        let fills = walk_bids(&mut ob, &d("1"), &d("123.6"));
+        assert_eq!(fills, vec![(d("123.61432906"), d("1"))]);
+        ob.update(OrderType::Bid, d("123.61626625"), d("2"));
+        let fills = walk_bids(&mut ob, &d("1"), &d("123.6"));
+        assert_eq!(fills, vec![(d("123.61626625"), d("1"))]);
+
+        // not enough bids above the minimum price
+        let fills = walk_bids(&mut fresh, &d("100"), &d("123.6"));
+        assert_eq!(fills.len(), 3);
+        assert!(get_average_price(&fills).unwrap() > d("123.6"));
+    }
+
+    #[test]
+    fn depth_analytics_test() {
+        let mut ob = OrderBook::new();
+        ob.update(OrderType::Bid, d("99"), d("2"));
+        ob.update(OrderType::Bid, d("98"), d("4"));
+        ob.update(OrderType::Bid, d("90"), d("10"));
+        ob.update(OrderType::Ask, d("101"), d("1"));
+        ob.update(OrderType::Ask, d("102"), d("3"));
+
+        assert_eq!(ob.mid_price(), Some(d("100")));
+        assert_eq!(ob.depth(OrderType::Bid, 0.05).unwrap(), d("6"));
+        assert_eq!(ob.depth(OrderType::Ask, 0.05).unwrap(), d("4"));
+        assert_eq!(ob.imbalance(0.05).unwrap(), Some(0.2));
+        // (99 * 1 + 101 * 2) / 3
+        assert_eq!(ob.microprice(), Some(d("100.333333333333")));
+
+        assert_eq!(ob.fill_price(OrderType::Ask, &d("2")), Some(d("101.5")));
+        assert_eq!(ob.fill_price(OrderType::Bid, &d("4")), Some(d("98.5")));
+        // not enough asks
+        assert_eq!(ob.fill_price(OrderType::Ask, &d("5")), None);
+
+        ob.truncate(OrderType::Bid, 2);
+        assert_eq!(ob.bids().count(), 2);
+        assert_eq!(ob.depth(OrderType::Bid, 1.0).unwrap(), d("6"));
+        assert_eq!(ob.highest_bid().map(|bid| bid.price), Some(d("99")));
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let ob = parse_orderbook(serde_json::from_str(INPUT).unwrap()).unwrap();
+
+        let prices: Vec<BigDecimal> = ob.asks().take(3).map(|ask| ask.price).collect();
+        assert_eq!(
+            prices,
+            vec![d("123.71470735"), d("123.87038423"), d("123.92495682")]
+        );
+        assert_eq!(ob.bids().count(), 13);
 
-        assert_eq!(res, expected);
+        let json = serde_json::to_value(ob.to_snapshot("USDT_LTC")).unwrap();
+        assert_eq!(parse_orderbook(json).unwrap(), ob);
+        assert!(OrderBook::new().is_empty());
     }
 }
@@ -0,0 +1,698 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::api::Api;
+use crate::chart_data::{ChartDataRequest, SOURCE_REST, SOURCE_STREAM};
+use crate::decimal::to_f64;
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
+use crate::fetcher::Fetcher;
+use crate::message::{BookEvent, PublicTrade};
+use crate::models::{Candle, Market, TickerSnapshot};
+use crate::order_book::{OrderBook, OrderType};
+
+pub const API_URL: &str = "https://api.poloniex.com";
+
+pub const WS_URL: &str = "wss://ws.poloniex.com/ws/public";
+
+// candle periods in seconds and the names of their intervals
+pub const INTERVALS: &[(i32, &str)] = &[
+    (60, "MINUTE_1"),
+    (300, "MINUTE_5"),
+    (600, "MINUTE_10"),
+    (900, "MINUTE_15"),
+    (1800, "MINUTE_30"),
+    (3600, "HOUR_1"),
+    (7200, "HOUR_2"),
+    (14400, "HOUR_4"),
+    (21600, "HOUR_6"),
+    (43200, "HOUR_12"),
+    (86400, "DAY_1"),
+    (259200, "DAY_3"),
+    (604800, "WEEK_1"),
+];
+
+// most candles returned for a request
+pub const MAX_CANDLES: i32 = 500;
+
+// levels of each side of the order book included in its checksum
+pub const CHECKSUM_LEVELS: usize = 25;
+
+pub const BOOK_CHANNEL: &str = "book_lv2";
+pub const TRADES_CHANNEL: &str = "trades";
+
+// symbols name the currencies the other way round, e.g. base USDT and quote
+// BTC is `BTC_USDT`
+const EXCHANGE: Exchange = Exchange::Poloniex(Api::V3);
+
+/// Name of the interval of a candle period
+pub fn interval(period: i32) -> Option<&'static str> {
+    INTERVALS
+        .iter()
+        .find(|(p, _)| *p == period)
+        .map(|(_, name)| *name)
+}
+
+/// Websocket channel of the candles of a period, e.g. `candles_minute_5`
+pub fn candles_channel(period: i32) -> Option<String> {
+    interval(period).map(|name| format!("candles_{}", name.to_lowercase()))
+}
+
+fn timestamp_ms(ms: i64) -> Result<DateTime<Utc>, BotError> {
+    Utc.timestamp_millis_opt(ms)
+        .single()
+        .ok_or_else(|| BotError::Parse(format!("invalid timestamp {}", ms)))
+}
+
+/// Ids are numbers in some responses and strings in others
+fn deserialize_id<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Value::deserialize(deserializer)? {
+        Value::String(s) => s,
+        other => other.to_string(),
+    })
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SymbolTradeLimit {
+    pub price_scale: i32,
+    pub quantity_scale: i32,
+    pub min_amount: BigDecimal,
+}
+
+/// Market in `/markets`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct V3Market {
+    pub symbol: String,
+    pub base_currency_name: String,
+    pub quote_currency_name: String,
+    /// NORMAL, PAUSE, POST_ONLY or OFFLINE
+    pub state: String,
+    pub symbol_trade_limit: SymbolTradeLimit,
+}
+
+/// Market with the precisions, order limit and trading status
+pub fn to_market(m: &V3Market) -> Market {
+    Market {
+        base: m.quote_currency_name.clone(),
+        quote: m.base_currency_name.clone(),
+        price_precision: m.symbol_trade_limit.price_scale,
+        amount_precision: m.symbol_trade_limit.quantity_scale,
+        min_order: m.symbol_trade_limit.min_amount.clone(),
+        is_frozen: m.state != "NORMAL" && m.state != "POST_ONLY",
+        post_only: m.state == "POST_ONLY",
+        updated_at: Utc::now(),
+        exchange: EXCHANGE.name().to_string(),
+    }
+}
+
+/// Fetches the markets of `base`
+pub fn return_markets(fetcher: &Fetcher, base: &str) -> Result<Vec<Market>, BotError> {
+    println!("Fetching markets");
+
+    let markets: Vec<V3Market> = fetcher
+        .get(&format!("{}/markets", API_URL), &[], "fetching markets")?
+        .json()
+        .context("reading markets")?;
+
+    Ok(markets
+        .iter()
+        .filter(|m| m.quote_currency_name == base)
+        .map(to_market)
+        .collect())
+}
+
+/// 24 hour ticker in `/markets/ticker24h`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Ticker24h {
+    pub symbol: String,
+    pub low: BigDecimal,
+    pub high: BigDecimal,
+    pub close: BigDecimal,
+    /// Traded volume in the quote currency of the bot, e.g. BTC of BTC_USDT
+    pub quantity: BigDecimal,
+    /// Traded volume in the base currency of the bot, e.g. USDT of BTC_USDT
+    pub amount: BigDecimal,
+    pub daily_change: BigDecimal,
+    pub bid: BigDecimal,
+    pub ask: BigDecimal,
+}
+
+/// Snapshot row of the ticker, `None` if the symbol can't be parsed
+pub fn to_ticker_snapshot(t: &Ticker24h, timestamp: DateTime<Utc>) -> Option<TickerSnapshot> {
+    let (base, quote) = EXCHANGE.parse_symbol(&t.symbol)?;
+
+    Some(TickerSnapshot {
+        base,
+        quote,
+        timestamp,
+        last: t.close.clone(),
+        lowest_ask: t.ask.clone(),
+        highest_bid: t.bid.clone(),
+        percent_change: to_f64(&t.daily_change) as f32,
+        base_volume: t.amount.clone(),
+        quote_volume: t.quantity.clone(),
+        high_24hr: t.high.clone(),
+        low_24hr: t.low.clone(),
+        exchange: EXCHANGE.name().to_string(),
+    })
+}
+
+/// Fetches the 24 hour tickers of the markets of `base`
+pub fn return_ticker_snapshots(
+    fetcher: &Fetcher,
+    base: &str,
+    timestamp: DateTime<Utc>,
+) -> Result<Vec<TickerSnapshot>, BotError> {
+    println!("Fetching tickers");
+
+    let tickers: Vec<Ticker24h> = fetcher
+        .get(
+            &format!("{}/markets/ticker24h", API_URL),
+            &[],
+            "fetching tickers",
+        )?
+        .json()
+        .context("reading tickers")?;
+
+    Ok(tickers
+        .iter()
+        .filter_map(|t| to_ticker_snapshot(t, timestamp))
+        .filter(|t| t.base == base)
+        .collect())
+}
+
+/// Candle in `/markets/{symbol}/candles`, an array of low, high, open,
+/// close, amount, quantity, buy taker amount, buy taker quantity, trade
+/// count, ts, weighted average, interval, start time and close time
+#[derive(Clone, Debug, Deserialize)]
+pub struct V3Candle(
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub BigDecimal,
+    pub i64,
+    pub i64,
+    pub BigDecimal,
+    pub String,
+    pub i64,
+    pub i64,
+);
+
+/// Candle of the chart data request, volume is the amount in base currency
+/// like in the legacy chart data
+pub fn to_candle(request: &ChartDataRequest, c: V3Candle) -> Result<Candle, BotError> {
+    Ok(Candle {
+        base: request.base.clone(),
+        quote: request.quote.clone(),
+        period: request.period,
+        timestamp: timestamp_ms(c.12)?,
+        high: Some(c.1),
+        low: Some(c.0),
+        open: Some(c.2),
+        close: Some(c.3),
+        average: Some(c.10),
+        volume: Some(c.4),
+        exchange: EXCHANGE.name().to_string(),
+        source: SOURCE_REST.to_string(),
+    })
+}
+
+/// Fetches the completed candles of the chart data request
+pub fn return_candles(
+    fetcher: &Fetcher,
+    request: &ChartDataRequest,
+) -> Result<Vec<Candle>, BotError> {
+    let name = interval(request.period).ok_or_else(|| {
+        BotError::Config(format!("no candle interval for period {}", request.period))
+    })?;
+    let what = format!("fetching candles for {} {}", request.quote, request.period);
+
+    let rows: Vec<V3Candle> = fetcher
+        .get(
+            &format!(
+                "{}/markets/{}/candles",
+                API_URL,
+                EXCHANGE.symbol(&request.base, &request.quote)
+            ),
+            &[
+                ("interval", name),
+                ("startTime", (request.start * 1000).to_string().as_str()),
+                ("endTime", (request.end * 1000).to_string().as_str()),
+                ("limit", MAX_CANDLES.to_string().as_str()),
+            ],
+            &what,
+        )?
+        .json()
+        .context(&format!("reading candles for {}", request.quote))?;
+
+    rows.into_iter()
+        // the candle in progress
+        .filter(|c| c.13 < request.end * 1000)
+        .map(|c| to_candle(request, c))
+        .collect()
+}
+
+/// Command sent to the websocket API
+#[derive(Serialize, Debug)]
+pub struct WsCommand {
+    pub event: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub channel: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub symbols: Vec<String>,
+}
+
+impl WsCommand {
+    pub fn new(event: &str, channels: &[String], symbols: &[String]) -> WsCommand {
+        WsCommand {
+            event: event.to_string(),
+            channel: channels.to_vec(),
+            symbols: symbols.to_vec(),
+        }
+    }
+}
+
+/// Order book in the `book_lv2` channel, a snapshot or the levels changed
+/// since the update `last_id`
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BookData {
+    pub symbol: String,
+    pub asks: Vec<(BigDecimal, BigDecimal)>,
+    pub bids: Vec<(BigDecimal, BigDecimal)>,
+    pub last_id: i64,
+    pub id: i64,
+    /// CRC32 of the book after the update, see `book_checksum`
+    pub checksum: i64,
+}
+
+/// Trade in the `trades` channel
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeData {
+    pub symbol: String,
+    #[serde(deserialize_with = "deserialize_id")]
+    pub id: String,
+    /// "buy" or "sell"
+    pub taker_side: String,
+    pub price: BigDecimal,
+    pub quantity: BigDecimal,
+    pub create_time: i64,
+}
+
+/// Candle in the `candles_<interval>` channels
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CandleData {
+    pub symbol: String,
+    pub low: BigDecimal,
+    pub high: BigDecimal,
+    pub open: BigDecimal,
+    pub close: BigDecimal,
+    pub amount: BigDecimal,
+    pub quantity: BigDecimal,
+    pub start_time: i64,
+}
+
+/// Message pushed by the v3 websocket API
+#[derive(Clone, Debug, PartialEq)]
+pub enum WsMessage {
+    /// `{"event": "<subscribe, pong, ...>", ...}`
+    Event(String),
+    /// `{"event": "error", "message": "<message>"}`
+    Error(String),
+    /// `{"channel": "book_lv2", "action": "<snapshot or update>", "data": [...]}`
+    Book { snapshot: bool, data: Vec<BookData> },
+    /// `{"channel": "trades", "data": [...]}`
+    Trades(Vec<TradeData>),
+    /// `{"channel": "candles_<interval>", "data": [...]}`
+    Candles { period: i32, data: Vec<CandleData> },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawWsMessage {
+    Event {
+        event: String,
+        message: Option<String>,
+    },
+    Data {
+        channel: String,
+        action: Option<String>,
+        data: Value,
+    },
+}
+
+/// Parses a websocket text frame
+pub fn parse_ws_message(input: &str) -> Result<WsMessage, BotError> {
+    let raw: RawWsMessage = serde_json::from_str(input)
+        .map_err(|_| BotError::Parse(format!("unknown message {}", input)))?;
+
+    let message = match raw {
+        RawWsMessage::Event { event, message } if event == "error" => {
+            WsMessage::Error(message.unwrap_or_default())
+        }
+        RawWsMessage::Event { event, .. } => WsMessage::Event(event),
+        RawWsMessage::Data {
+            channel,
+            action,
+            data,
+        } => match channel.as_str() {
+            BOOK_CHANNEL => WsMessage::Book {
+                snapshot: action.as_deref() == Some("snapshot"),
+                data: serde_json::from_value(data)?,
+            },
+            TRADES_CHANNEL => WsMessage::Trades(serde_json::from_value(data)?),
+            other => {
+                let period = INTERVALS
+                    .iter()
+                    .map(|(period, _)| *period)
+                    .find(|period| candles_channel(*period).as_deref() == Some(other))
+                    .ok_or_else(|| BotError::Parse(format!("unknown channel {}", other)))?;
+                WsMessage::Candles {
+                    period,
+                    data: serde_json::from_value(data)?,
+                }
+            }
+        },
+    };
+
+    Ok(message)
+}
+
+/// Public trade of the trade data
+pub fn to_public_trade(t: TradeData) -> PublicTrade {
+    PublicTrade {
+        id: t.id,
+        is_buy: t.taker_side == "buy",
+        price: t.price,
+        size: t.quantity,
+        timestamp: t.create_time.div_euclid(1000),
+    }
+}
+
+/// Candle row of the candle data, the average is the amount per quantity
+pub fn candle_data_to_candle(period: i32, c: CandleData) -> Result<Candle, BotError> {
+    let (base, quote) = EXCHANGE
+        .parse_symbol(&c.symbol)
+        .ok_or_else(|| BotError::Parse(format!("invalid symbol {}", c.symbol)))?;
+    let average = if c.quantity == BigDecimal::from(0) {
+        c.close.clone()
+    } else {
+        (&c.amount / &c.quantity).round(crate::decimal::DECIMAL_SCALE)
+    };
+
+    Ok(Candle {
+        base,
+        quote,
+        period,
+        timestamp: timestamp_ms(c.start_time)?,
+        high: Some(c.high),
+        low: Some(c.low),
+        open: Some(c.open),
+        close: Some(c.close),
+        average: Some(average),
+        volume: Some(c.amount),
+        exchange: EXCHANGE.name().to_string(),
+        source: SOURCE_STREAM.to_string(),
+    })
+}
+
+/// CRC32 (IEEE) of the bytes
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Checksum of the order book, CRC32 of `price:size` of the best
+/// `CHECKSUM_LEVELS` bids and asks interleaved, best prices first, joined
+/// with `:`
+pub fn book_checksum(order_book: &OrderBook) -> u32 {
+    let mut bids = order_book.bids().take(CHECKSUM_LEVELS);
+    let mut asks = order_book.asks().take(CHECKSUM_LEVELS);
+    let mut parts: Vec<String> = vec![];
+
+    loop {
+        let bid = bids.next();
+        let ask = asks.next();
+        if bid.is_none() && ask.is_none() {
+            break;
+        }
+        for level in [bid, ask].into_iter().flatten() {
+            parts.push(format!("{}:{}", level.price, level.size));
+        }
+    }
+
+    crc32(parts.join(":").as_bytes())
+}
+
+/// Order books of the subscribed symbols with the id of their latest update,
+/// kept to validate the updates
+#[derive(Debug, Default)]
+pub struct BookTracker {
+    books: HashMap<String, (OrderBook, i64)>,
+}
+
+impl BookTracker {
+    pub fn new() -> BookTracker {
+        BookTracker::default()
+    }
+
+    /// Applies a snapshot or an update, and returns it as order book events
+    ///
+    /// Fails if an update doesn't follow the latest one or the checksum
+    /// doesn't match, the symbol is then dropped and its updates are skipped
+    /// until the next snapshot.
+    pub fn apply(&mut self, snapshot: bool, data: BookData) -> Result<Vec<BookEvent>, BotError> {
+        let mut order_book = if snapshot {
+            OrderBook::new()
+        } else {
+            match self.books.remove(&data.symbol) {
+                Some((order_book, id)) if id == data.last_id => order_book,
+                Some((_, id)) => {
+                    return Err(BotError::Parse(format!(
+                        "{} update {} doesn't follow {}",
+                        data.symbol, data.last_id, id
+                    )))
+                }
+                None => return Ok(vec![]),
+            }
+        };
+
+        let mut events = vec![];
+        for (order_type, levels) in [(OrderType::Ask, &data.asks), (OrderType::Bid, &data.bids)] {
+            for (price, size) in levels {
+                order_book.update(order_type.clone(), price.clone(), size.clone());
+                events.push(BookEvent::Update {
+                    order_type: order_type.clone(),
+                    price: price.clone(),
+                    size: size.clone(),
+                });
+            }
+        }
+
+        // the checksum may be sent as a signed 32 bit integer
+        let checksum = book_checksum(&order_book);
+        if checksum != data.checksum as u32 {
+            return Err(BotError::Parse(format!(
+                "{} checksum {} doesn't match {}",
+                data.symbol, data.checksum, checksum
+            )));
+        }
+
+        let (base, quote) = EXCHANGE
+            .parse_symbol(&data.symbol)
+            .ok_or_else(|| BotError::Parse(format!("invalid symbol {}", data.symbol)))?;
+        if snapshot {
+            events = vec![BookEvent::Snapshot {
+                currency_pair: Exchange::Poloniex(Api::Legacy).symbol(&base, &quote),
+                order_book: order_book.clone(),
+            }];
+        }
+        self.books.insert(data.symbol, (order_book, data.id));
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn parse_ws_message_test() {
+        assert_eq!(
+            parse_ws_message(
+                r#"{"event": "subscribe", "channel": "trades", "symbols": ["BTC_USDT"]}"#
+            )
+            .unwrap(),
+            WsMessage::Event("subscribe".to_string())
+        );
+        assert_eq!(
+            parse_ws_message(r#"{"event": "error", "message": "Invalid symbol"}"#).unwrap(),
+            WsMessage::Error("Invalid symbol".to_string())
+        );
+
+        let trades = parse_ws_message(
+            r#"{"channel": "trades", "data": [{"symbol": "BTC_USDT", "amount": "70",
+              "takerSide": "sell", "quantity": "0.0035", "createTime": 1674043205123,
+              "price": "20000", "id": 60100, "ts": 1674043205130}]}"#,
+        )
+        .unwrap();
+        let trade = match trades {
+            WsMessage::Trades(mut data) => to_public_trade(data.remove(0)),
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(
+            trade,
+            PublicTrade {
+                id: "60100".to_string(),
+                is_buy: false,
+                price: d("20000"),
+                size: d("0.0035"),
+                timestamp: 1674043205,
+            }
+        );
+
+        let candles = parse_ws_message(
+            r#"{"channel": "candles_minute_5", "data": [{"symbol": "BTC_USDT", "amount": "40",
+              "high": "21", "quantity": "2", "tradeCount": 3, "low": "19",
+              "closeTime": 1674043499999, "startTime": 1674043200000, "close": "20.5",
+              "open": "19.5", "ts": 1674043300000}]}"#,
+        )
+        .unwrap();
+        let candle = match candles {
+            WsMessage::Candles { period, mut data } => {
+                candle_data_to_candle(period, data.remove(0)).unwrap()
+            }
+            other => panic!("unexpected {:?}", other),
+        };
+        assert_eq!(
+            (candle.base.as_str(), candle.quote.as_str()),
+            ("USDT", "BTC")
+        );
+        assert_eq!(candle.period, 300);
+        assert_eq!(candle.timestamp.timestamp(), 1674043200);
+        assert_eq!(candle.average, Some(d("20")));
+
+        assert!(parse_ws_message(r#"{"channel": "unknown", "data": []}"#).is_err());
+    }
+
+    #[test]
+    fn parse_rest_test() {
+        let markets: Vec<V3Market> = serde_json::from_str(
+            r#"[{"symbol": "BTC_USDT", "baseCurrencyName": "BTC", "quoteCurrencyName": "USDT",
+              "displayName": "BTC/USDT", "state": "POST_ONLY", "visibleStartTime": 1659018819512,
+              "tradableStartTime": 1659018819512, "crossMargin": {"supportCrossMargin": true},
+              "symbolTradeLimit": {"symbol": "BTC_USDT", "priceScale": 2, "quantityScale": 6,
+              "amountScale": 2, "minQuantity": "0.000001", "minAmount": "1",
+              "highestBid": "0", "lowestAsk": "0"}}]"#,
+        )
+        .unwrap();
+        let market = to_market(&markets[0]);
+        assert_eq!(
+            (market.base.as_str(), market.quote.as_str()),
+            ("USDT", "BTC")
+        );
+        assert_eq!((market.price_precision, market.amount_precision), (2, 6));
+        assert!(market.post_only && !market.is_frozen);
+
+        let candles: Vec<V3Candle> = serde_json::from_str(
+            r#"[["45218.8", "47590.82", "47009.11", "45516.6", "13337805.8", "286.639111",
+              "2396659.0", "51.919491", 1123, 1639804500000, "46531.7", "MINUTE_15",
+              1639803600000, 1639804499999]]"#,
+        )
+        .unwrap();
+        let request = ChartDataRequest {
+            exchange: EXCHANGE,
+            base: "USDT".to_string(),
+            quote: "BTC".to_string(),
+            period: 900,
+            start: 1639803600,
+            end: 1639804500,
+        };
+        let candle = to_candle(&request, candles[0].clone()).unwrap();
+        assert_eq!(candle.timestamp.timestamp(), 1639803600);
+        assert_eq!(candle.low, Some(d("45218.8")));
+        assert_eq!(candle.high, Some(d("47590.82")));
+        assert_eq!(candle.average, Some(d("46531.7")));
+        assert_eq!(candle.volume, Some(d("13337805.8")));
+        assert_eq!(candle.exchange, "poloniex");
+    }
+
+    #[test]
+    fn book_tracker_test() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+
+        let checksum = |s: &str| crc32(s.as_bytes()) as i64;
+        let mut tracker = BookTracker::new();
+
+        let snapshot = BookData {
+            symbol: "BTC_USDT".to_string(),
+            asks: vec![(d("101"), d("1")), (d("102"), d("2"))],
+            bids: vec![(d("100"), d("3"))],
+            last_id: 0,
+            id: 10,
+            checksum: checksum("100:3:101:1:102:2"),
+        };
+        let events = tracker.apply(true, snapshot).unwrap();
+        assert!(matches!(
+            &events[..],
+            [BookEvent::Snapshot { currency_pair, .. }] if currency_pair == "USDT_BTC"
+        ));
+
+        let update = BookData {
+            symbol: "BTC_USDT".to_string(),
+            asks: vec![(d("101"), d("0"))],
+            bids: vec![(d("99"), d("1"))],
+            last_id: 10,
+            id: 11,
+            checksum: checksum("100:3:102:2:99:1"),
+        };
+        assert_eq!(tracker.apply(false, update.clone()).unwrap().len(), 2);
+
+        // a missed update drops the book until the next snapshot
+        let gap = BookData {
+            last_id: 12,
+            id: 13,
+            ..update.clone()
+        };
+        assert!(tracker.apply(false, gap).is_err());
+        assert!(tracker
+            .apply(
+                false,
+                BookData {
+                    last_id: 13,
+                    id: 14,
+                    ..update
+                }
+            )
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,216 @@
+extern crate diesel;
+
+use super::diesel::prelude::*;
+use super::error::{BotError, Context};
+use super::exchange::Exchange;
+use super::models::Regime;
+use diesel::sql_query;
+
+// market trend is followed from this quote
+pub const TREND_QUOTE: &str = "BTC";
+
+// share of quotes above their long moving average, at or below this the
+// market is falling if the trend quote is also below its long MA
+pub const BEAR_BREADTH: f64 = 0.4;
+
+// share of quotes above their long moving average, at or above this the
+// market is rising if the trend quote is also above its long MA
+pub const BULL_BREADTH: f64 = 0.6;
+
+// maximum number of open trades, new ones are only entered below this, when
+// the market is neither rising nor falling
+pub const NEUTRAL_MAX_ENTRIES: usize = 1;
+
+pub const REGIME_BULL: &str = "bull";
+pub const REGIME_NEUTRAL: &str = "neutral";
+pub const REGIME_BEAR: &str = "bear";
+
+/// Detects the current market regime from the trend quote and the breadth of
+/// quotes above their long moving average, and stores it with its inputs
+///
+/// Indicators must be up to date, see `update_indicators`.
+pub fn update_regime(
+    connection: &mut PgConnection,
+    exchange: Exchange,
+    base: String,
+    period: i32,
+) -> Result<usize, BotError> {
+    println!("updating regime");
+
+    sql_query(format!(
+        "
+      WITH latest AS (
+        SELECT
+          DISTINCT ON (indicators.quote) indicators.quote,
+          candles.average,
+          indicators.ma_long
+        FROM
+          indicators
+          JOIN candles USING (exchange, base, quote, period, timestamp)
+        WHERE
+          indicators.exchange = '{exchange}'
+          AND indicators.base = '{base}'
+          AND indicators.period = {period}
+          -- only quotes with recent data
+          AND indicators.timestamp > (current_timestamp - interval '30 minutes')
+        ORDER BY
+          indicators.quote,
+          indicators.timestamp DESC
+      ),
+      inputs AS (
+        SELECT
+          (SELECT average FROM latest WHERE quote = '{trend_quote}') AS trend_average,
+          (SELECT ma_long FROM latest WHERE quote = '{trend_quote}') AS trend_ma_long,
+          AVG(CASE WHEN average > ma_long THEN 1.0 ELSE 0.0 END) AS breadth,
+          COUNT(*) AS quotes
+        FROM
+          latest
+      )
+      INSERT INTO regimes(exchange, timestamp, trend_average, trend_ma_long, breadth, quotes, regime) (SELECT
+        '{exchange}',
+        NOW(),
+        trend_average,
+        trend_ma_long,
+        breadth,
+        quotes,
+        {regime}
+      FROM
+        inputs
+      );
+    ",
+        exchange = exchange.name(),
+        base = base,
+        period = period,
+        trend_quote = TREND_QUOTE,
+        regime = regime_sql("trend_average", "trend_ma_long", "breadth"),
+    ))
+    .execute(connection)
+    .context("updating regime")
+}
+
+/// Market regime from the trend quote and breadth columns
+fn regime_sql(trend_average: &str, trend_ma_long: &str, breadth: &str) -> String {
+    format!(
+        "CASE
+          WHEN {trend_average} < {trend_ma_long} AND {breadth} <= {bear_breadth} THEN '{bear}'
+          WHEN {trend_average} > {trend_ma_long} AND {breadth} >= {bull_breadth} THEN '{bull}'
+          ELSE '{neutral}'
+        END",
+        trend_average = trend_average,
+        trend_ma_long = trend_ma_long,
+        breadth = breadth,
+        bear_breadth = BEAR_BREADTH,
+        bull_breadth = BULL_BREADTH,
+        bear = REGIME_BEAR,
+        bull = REGIME_BULL,
+        neutral = REGIME_NEUTRAL,
+    )
+}
+
+/// Gets the most recently detected market regime of `exchange_p`
+pub fn get_regime(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+) -> Result<Option<Regime>, BotError> {
+    use super::schema::regimes::dsl::*;
+
+    let row = regimes
+        .filter(exchange.eq(exchange_p.name()))
+        .order(timestamp.desc())
+        .first::<Regime>(connection)
+        .optional()?;
+
+    Ok(row)
+}
+
+/// Maximum number of open trades in the regime, new trades are only entered
+/// below it, `None` for no limit
+pub fn max_entries(regime: &Regime) -> Option<usize> {
+    match regime.regime.as_str() {
+        REGIME_BEAR => Some(0),
+        REGIME_NEUTRAL => Some(NEUTRAL_MAX_ENTRIES),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_connection;
+    use chrono::Utc;
+
+    fn regime(name: &str) -> Regime {
+        Regime {
+            id: 1,
+            timestamp: Utc::now(),
+            trend_average: None,
+            trend_ma_long: None,
+            breadth: None,
+            quotes: 0,
+            regime: name.to_string(),
+            exchange: "poloniex".to_string(),
+        }
+    }
+
+    #[test]
+    fn max_entries_test() {
+        assert_eq!(max_entries(&regime(REGIME_BULL)), None);
+        assert_eq!(
+            max_entries(&regime(REGIME_NEUTRAL)),
+            Some(NEUTRAL_MAX_ENTRIES)
+        );
+        assert_eq!(max_entries(&regime(REGIME_BEAR)), Some(0));
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn regime_sql_test() {
+        let (_lock, mut connection) = test_connection();
+
+        #[derive(QueryableByName)]
+        struct Row {
+            #[diesel(sql_type = diesel::sql_types::Text)]
+            regime: String,
+        }
+
+        let regimes: Vec<String> = sql_query(format!(
+            "SELECT ({}) AS regime
+            FROM (VALUES
+              (1, 90, 100, 0.3),
+              (2, 90, 100, {bear}),
+              (3, 90, 100, 0.5),
+              (4, 110, 100, 0.3),
+              (5, 110, 100, {bull}),
+              (6, 110, 100, 0.7),
+              (7, 90, 100, 0.7),
+              (8, NULL, NULL, 0.3)
+            ) AS t(i, average, ma_long, breadth)
+            ORDER BY i",
+            regime_sql("average", "ma_long", "breadth"),
+            bear = BEAR_BREADTH,
+            bull = BULL_BREADTH,
+        ))
+        .load::<Row>(&mut connection)
+        .unwrap()
+        .into_iter()
+        .map(|r| r.regime)
+        .collect();
+
+        // both the trend quote and the breadth must agree, without the trend
+        // quote the market is neutral
+        assert_eq!(
+            regimes,
+            [
+                REGIME_BEAR,
+                REGIME_BEAR,
+                REGIME_NEUTRAL,
+                REGIME_NEUTRAL,
+                REGIME_BULL,
+                REGIME_BULL,
+                REGIME_NEUTRAL,
+                REGIME_NEUTRAL,
+            ]
+        );
+    }
+}
@@ -0,0 +1,132 @@
+extern crate diesel;
+
+use super::chart_data::SOURCE_RESAMPLED;
+use super::error::{BotError, Context};
+use super::exchange::Exchange;
+use diesel::prelude::*;
+use diesel::sql_query;
+
+/// Period of the exchange candles that `period` can be resampled from, the
+/// longest of `exchange_periods` that divides it
+pub fn resample_source(period: i32, exchange_periods: &[i32]) -> Option<i32> {
+    exchange_periods
+        .iter()
+        .rev()
+        .find(|&&p| p < period && period % p == 0)
+        .copied()
+}
+
+/// Derives candles of `period` from the candles of `source_period` of
+/// `exchange`
+///
+/// Candles from the latest stored one of `period` onwards are recomputed, so
+/// the candle in progress is updated until its period has passed. Buckets
+/// start at multiples of `period` from the Unix epoch, the same as exchange
+/// candles.
+pub fn resample_candles(
+    connection: &mut PgConnection,
+    exchange: Exchange,
+    base: String,
+    source_period: i32,
+    period: i32,
+) -> Result<usize, BotError> {
+    println!("resampling candles {} to {}", source_period, period);
+
+    if period % source_period != 0 {
+        return Err(BotError::Config(format!(
+            "period {} is not a multiple of {}",
+            period, source_period
+        )));
+    }
+
+    sql_query(format!(
+        "
+      WITH latest AS (
+        SELECT
+          quote,
+          MAX(timestamp) AS timestamp
+        FROM
+          candles
+        WHERE
+          exchange = '{exchange}'
+          AND base = '{base}'
+          AND period = {period}
+        GROUP BY
+          quote
+      ),
+      source AS (
+        SELECT
+          candles.*,
+          to_timestamp(
+            floor(extract(epoch FROM candles.timestamp) / {period}) * {period}
+          ) AS bucket
+        FROM
+          candles
+          LEFT JOIN latest ON latest.quote = candles.quote
+        WHERE
+          candles.exchange = '{exchange}'
+          AND candles.base = '{base}'
+          AND candles.period = {source_period}
+          AND (
+            latest.timestamp IS NULL
+            OR candles.timestamp >= latest.timestamp
+          )
+      )
+      INSERT INTO candles(
+        exchange, base, quote, period, timestamp, high, low, open, close, average, volume,
+        source
+      ) (SELECT
+        exchange,
+        base,
+        quote,
+        {period},
+        bucket,
+        MAX(high),
+        MIN(low),
+        (ARRAY_AGG(open ORDER BY timestamp ASC))[1],
+        (ARRAY_AGG(close ORDER BY timestamp DESC))[1],
+        -- volume weighted, plain average if there was no volume
+        COALESCE(SUM(average * volume) / NULLIF(SUM(volume), 0), AVG(average)),
+        SUM(volume),
+        '{resampled}'
+      FROM
+        source
+      GROUP BY
+        exchange,
+        base,
+        quote,
+        bucket
+      )
+      ON CONFLICT (exchange, base, quote, period, timestamp) DO UPDATE SET
+        high = EXCLUDED.high,
+        low = EXCLUDED.low,
+        open = EXCLUDED.open,
+        close = EXCLUDED.close,
+        average = EXCLUDED.average,
+        volume = EXCLUDED.volume,
+        source = EXCLUDED.source;
+    ",
+        exchange = exchange.name(),
+        base = base,
+        period = period,
+        source_period = source_period,
+        resampled = SOURCE_RESAMPLED,
+    ))
+    .execute(connection)
+    .context("resampling candles")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chart_data::EXCHANGE_PERIODS;
+
+    #[test]
+    fn resample_source_test() {
+        assert_eq!(resample_source(3600, EXCHANGE_PERIODS), Some(1800));
+        assert_eq!(resample_source(28800, EXCHANGE_PERIODS), Some(14400));
+        assert_eq!(resample_source(604800, EXCHANGE_PERIODS), Some(86400));
+        assert_eq!(resample_source(900, EXCHANGE_PERIODS), Some(300));
+        assert_eq!(resample_source(60, EXCHANGE_PERIODS), None);
+    }
+}
@@ -0,0 +1,391 @@
+extern crate diesel;
+
+use super::decimal::to_f64;
+use super::diesel::dsl::{count_star, sum};
+use super::diesel::prelude::*;
+use super::error::BotError;
+use super::models::*;
+use super::BASE;
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Datelike, Duration, TimeZone, Utc};
+
+// position size of a new trade in base currency (USDT)
+pub const TRADE_SIZE: f64 = 100.0;
+
+// don't open new trades when this many trades are open
+pub const MAX_OPEN_TRADES: i64 = 5;
+
+// maximum open position size in base currency for a single quote
+pub const MAX_QUOTE_EXPOSURE: f64 = 100.0;
+
+// maximum open position size in base currency for all trades combined
+pub const MAX_TOTAL_EXPOSURE: f64 = 500.0;
+
+// halt new trades for the rest of the day (UTC) when trades closed
+// during the day have lost this much in base currency
+pub const MAX_DAILY_LOSS: f64 = 20.0;
+
+// halt new trades for the rest of the week when trades closed during
+// the week (starting Monday, UTC) have lost this much in base currency
+pub const MAX_WEEKLY_LOSS: f64 = 50.0;
+
+/// Returns the currently active trading halt, if any
+///
+/// A halt is active until it has been reset manually or its end time has
+/// passed.
+pub fn get_active_halt(connection: &mut PgConnection) -> Result<Option<Halt>, BotError> {
+    use super::schema::halts::dsl::*;
+
+    let row = halts
+        .filter(reset_at.is_null())
+        .filter(until.is_null().or(until.gt(Utc::now())))
+        .order(created_at.desc())
+        .first::<Halt>(connection)
+        .optional()?;
+
+    Ok(row)
+}
+
+/// Halts opening new trades until the given time, or until reset if `None`
+pub fn create_halt(
+    connection: &mut PgConnection,
+    reason: String,
+    until: Option<DateTime<Utc>>,
+) -> Result<Halt, BotError> {
+    use super::schema::halts;
+
+    println!("halting trading: {}", reason);
+
+    let new_halt = NewHalt {
+        created_at: Utc::now(),
+        reason,
+        until,
+    };
+
+    let halt = diesel::insert_into(halts::table)
+        .values(&new_halt)
+        .get_result::<Halt>(connection)?;
+
+    Ok(halt)
+}
+
+/// Resets all active halts, returns the number of halts reset
+pub fn reset_halts(connection: &mut PgConnection) -> Result<usize, BotError> {
+    use super::schema::halts::dsl::*;
+
+    let count = diesel::update(halts.filter(reset_at.is_null()))
+        .set(reset_at.eq(Some(Utc::now())))
+        .execute(connection)?;
+
+    Ok(count)
+}
+
+/// Checks whether a halt ending at `until` has been created, including
+/// halts that have been reset already
+fn has_halt_until(connection: &mut PgConnection, until_p: DateTime<Utc>) -> Result<bool, BotError> {
+    use super::schema::halts::dsl::*;
+
+    let count: i64 = halts
+        .filter(until.eq(until_p))
+        .select(count_star())
+        .first(connection)?;
+
+    Ok(count > 0)
+}
+
+/// Sum of realised profit in base currency for trades closed at or after
+/// `since`
+fn get_realised_profit(
+    connection: &mut PgConnection,
+    since: DateTime<Utc>,
+) -> Result<f64, BotError> {
+    use super::schema::trades::dsl::*;
+
+    // profit is a ratio and size a decimal, so they are multiplied here
+    let rows: Vec<(Option<f32>, BigDecimal)> = trades
+        .filter(base.eq(BASE))
+        .filter(close_at.ge(since))
+        .select((profit, size))
+        .load(connection)?;
+
+    Ok(rows
+        .iter()
+        .map(|(p, s)| p.unwrap_or(0.0) as f64 * to_f64(s))
+        .sum())
+}
+
+/// Open position size in base currency, for a single quote if given
+fn get_exposure(connection: &mut PgConnection, quote_p: Option<&str>) -> Result<f64, BotError> {
+    use super::schema::trades::dsl::*;
+
+    let mut query = trades
+        .filter(base.eq(BASE))
+        .filter(close_at.is_null())
+        .select(sum(size * remaining))
+        .into_boxed();
+
+    if let Some(q) = quote_p {
+        query = query.filter(quote.eq(q));
+    }
+
+    let total: Option<BigDecimal> = query.first(connection)?;
+
+    Ok(total.map(|t| to_f64(&t)).unwrap_or(0.0))
+}
+
+fn get_open_trade_count(connection: &mut PgConnection) -> Result<i64, BotError> {
+    use super::schema::trades::dsl::*;
+
+    let count = trades
+        .filter(base.eq(BASE))
+        .filter(close_at.is_null())
+        .select(count_star())
+        .first(connection)?;
+
+    Ok(count)
+}
+
+/// Starts of the day and of the week (starting Monday) containing `now`, in
+/// UTC
+fn period_starts(now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+    let day_start = Utc.from_utc_datetime(&now.date_naive().and_hms_opt(0, 0, 0).unwrap());
+    let week_start = day_start - Duration::days(now.weekday().num_days_from_monday() as i64);
+    (day_start, week_start)
+}
+
+/// Checks the realised losses of the day and the week, and halts trading
+/// until the end of the period if either limit has been exceeded
+///
+/// A period is halted only once, so that a manual reset lets trading
+/// continue.
+fn check_loss_limits(connection: &mut PgConnection) -> Result<Option<Halt>, BotError> {
+    let (day_start, week_start) = period_starts(Utc::now());
+
+    let limits = [
+        ("daily", day_start, Duration::days(1), MAX_DAILY_LOSS),
+        ("weekly", week_start, Duration::weeks(1), MAX_WEEKLY_LOSS),
+    ];
+
+    for (name, start, length, max_loss) in limits {
+        let end = start + length;
+        if has_halt_until(connection, end)? {
+            continue;
+        }
+
+        let loss = -get_realised_profit(connection, start)?;
+        if loss > max_loss {
+            let reason = format!(
+                "{} realised loss {:.2} {} exceeds limit {:.2}",
+                name, loss, BASE, max_loss
+            );
+            return Ok(Some(create_halt(connection, reason, Some(end))?));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Checks whether a new trade can be opened for the shortlist entry
+///
+/// The limits cover the trades of all exchanges, as they are positions in
+/// the same currencies and their losses come from the same capital.
+///
+/// Fails with `BotError::RiskRejected` and the reason when the trade would
+/// break the risk limits. Exceeding the realised loss limits persists a halt
+/// that blocks all new trades.
+pub fn check_risk(connection: &mut PgConnection, shortlist: &Shortlist) -> Result<(), BotError> {
+    if let Some(halt) = get_active_halt(connection)? {
+        return Err(BotError::RiskRejected(format!(
+            "trading halted: {}",
+            halt.reason
+        )));
+    }
+
+    if let Some(halt) = check_loss_limits(connection)? {
+        return Err(BotError::RiskRejected(format!(
+            "trading halted: {}",
+            halt.reason
+        )));
+    }
+
+    let open_trades = get_open_trade_count(connection)?;
+    if open_trades >= MAX_OPEN_TRADES {
+        return Err(BotError::RiskRejected(format!(
+            "{} trades open already",
+            open_trades
+        )));
+    }
+
+    let total_exposure = get_exposure(connection, None)?;
+    if total_exposure + TRADE_SIZE > MAX_TOTAL_EXPOSURE {
+        return Err(BotError::RiskRejected(format!(
+            "total exposure {:.2} too high",
+            total_exposure
+        )));
+    }
+
+    let quote_exposure = get_exposure(connection, Some(&shortlist.quote))?;
+    if quote_exposure + TRADE_SIZE > MAX_QUOTE_EXPOSURE {
+        return Err(BotError::RiskRejected(format!(
+            "exposure {:.2} too high for {}",
+            quote_exposure, shortlist.quote
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decimal::decimal;
+    use crate::test_connection;
+    use crate::trade::create_trade;
+
+    fn shortlist(quote: &str) -> Shortlist {
+        Shortlist {
+            quote: quote.to_string(),
+            timestamp: Utc::now(),
+            average: BigDecimal::from(1),
+            target: BigDecimal::from(1),
+            confidence: 1.0,
+            stop_loss: 0.02,
+            exchange: "poloniex".to_string(),
+        }
+    }
+
+    fn open_trade(connection: &mut PgConnection, quote_p: &str) -> Trade {
+        use crate::schema::trades::dsl::*;
+
+        let trade = create_trade(connection, &shortlist(quote_p)).unwrap();
+        diesel::update(&trade)
+            .set(open.eq(Some(BigDecimal::from(1))))
+            .get_result(connection)
+            .unwrap()
+    }
+
+    fn closed_trade(
+        connection: &mut PgConnection,
+        quote_p: &str,
+        profit_p: f32,
+        close_at_p: DateTime<Utc>,
+    ) {
+        use crate::schema::trades::dsl::*;
+
+        let trade = open_trade(connection, quote_p);
+        diesel::update(&trade)
+            .set((close_at.eq(Some(close_at_p)), profit.eq(Some(profit_p))))
+            .execute(connection)
+            .unwrap();
+    }
+
+    fn rejection(connection: &mut PgConnection, quote: &str) -> Option<String> {
+        match check_risk(connection, &shortlist(quote)) {
+            Ok(()) => None,
+            Err(BotError::RiskRejected(reason)) => Some(reason),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    #[test]
+    fn period_starts_test() {
+        let at = |y, m, d, h, min, s| Utc.with_ymd_and_hms(y, m, d, h, min, s).unwrap();
+
+        // Sunday ends the week
+        assert_eq!(
+            period_starts(at(2023, 3, 12, 23, 59, 59)),
+            (at(2023, 3, 12, 0, 0, 0), at(2023, 3, 6, 0, 0, 0))
+        );
+        // Monday starts both periods
+        assert_eq!(
+            period_starts(at(2023, 3, 13, 0, 0, 0)),
+            (at(2023, 3, 13, 0, 0, 0), at(2023, 3, 13, 0, 0, 0))
+        );
+        assert_eq!(
+            period_starts(at(2023, 3, 15, 12, 30, 0)),
+            (at(2023, 3, 15, 0, 0, 0), at(2023, 3, 13, 0, 0, 0))
+        );
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn exposure_limits_test() {
+        let (_lock, mut connection) = test_connection();
+
+        connection.test_transaction::<_, BotError, _>(|conn| {
+            // a single trade takes the whole quote exposure
+            assert_eq!(rejection(conn, "TEST_RISK_0"), None);
+
+            open_trade(conn, "TEST_RISK_0");
+            let reason = rejection(conn, "TEST_RISK_0").unwrap();
+            assert!(reason.contains("too high for TEST_RISK_0"), "{}", reason);
+            assert_eq!(rejection(conn, "TEST_RISK_1"), None);
+
+            for i in 1..MAX_OPEN_TRADES {
+                open_trade(conn, &format!("TEST_RISK_{}", i));
+            }
+            let reason = rejection(conn, "TEST_RISK_NEW").unwrap();
+            assert!(reason.contains("trades open already"), "{}", reason);
+
+            // a partially exited trade only counts its remaining position
+            diesel::update(crate::schema::trades::table)
+                .set(crate::schema::trades::remaining.eq(decimal(0.5)?))
+                .execute(conn)?;
+            assert_eq!(
+                rejection(conn, "TEST_RISK_0"),
+                Some(format!("{} trades open already", MAX_OPEN_TRADES))
+            );
+            assert_eq!(get_exposure(conn, Some("TEST_RISK_0"))?, TRADE_SIZE / 2.0);
+            assert_eq!(
+                get_exposure(conn, None)?,
+                TRADE_SIZE / 2.0 * MAX_OPEN_TRADES as f64
+            );
+            Ok(())
+        });
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn loss_limits_test() {
+        let (_lock, mut connection) = test_connection();
+
+        connection.test_transaction::<_, BotError, _>(|conn| {
+            let (day_start, week_start) = period_starts(Utc::now());
+            let loss = |base_currency: f64| -(base_currency / TRADE_SIZE) as f32;
+
+            // losses before the week don't count
+            closed_trade(
+                conn,
+                "TEST_RISK_0",
+                loss(MAX_WEEKLY_LOSS * 2.0),
+                week_start - Duration::seconds(1),
+            );
+            assert_eq!(rejection(conn, "TEST_RISK_1"), None);
+
+            closed_trade(conn, "TEST_RISK_0", loss(MAX_DAILY_LOSS + 5.0), day_start);
+            let reason = rejection(conn, "TEST_RISK_1").unwrap();
+            assert!(reason.contains("daily realised loss"), "{}", reason);
+            assert!(get_active_halt(conn)?.is_some());
+
+            // the day is halted once, a manual reset lets trading continue
+            assert_eq!(reset_halts(conn)?, 1);
+            assert_eq!(rejection(conn, "TEST_RISK_1"), None);
+            assert!(get_active_halt(conn)?.is_none());
+
+            closed_trade(
+                conn,
+                "TEST_RISK_0",
+                loss(MAX_WEEKLY_LOSS - MAX_DAILY_LOSS),
+                Utc::now(),
+            );
+            let reason = rejection(conn, "TEST_RISK_1").unwrap();
+            assert!(reason.contains("weekly realised loss"), "{}", reason);
+
+            let halt = get_active_halt(conn)?.unwrap();
+            assert_eq!(halt.until, Some(week_start + Duration::weeks(1)));
+            Ok(())
+        });
+    }
+}
@@ -1,25 +1,143 @@
 table! {
-    candles (base, quote, period, timestamp) {
+    candles (exchange, base, quote, period, timestamp) {
         base -> Varchar,
         quote -> Varchar,
         period -> Int4,
         timestamp -> Timestamptz,
-        high -> Nullable<Float4>,
-        low -> Nullable<Float4>,
-        open -> Nullable<Float4>,
-        close -> Nullable<Float4>,
-        average -> Nullable<Float4>,
-        volume -> Nullable<Float4>,
+        high -> Nullable<Numeric>,
+        low -> Nullable<Numeric>,
+        open -> Nullable<Numeric>,
+        close -> Nullable<Numeric>,
+        average -> Nullable<Numeric>,
+        volume -> Nullable<Numeric>,
+        exchange -> Varchar,
+        source -> Varchar,
     }
 }
 
 table! {
-    shortlist (quote) {
+    cooldowns (exchange, quote) {
         quote -> Varchar,
+        losses -> Int4,
+        until -> Timestamptz,
+        updated_at -> Timestamptz,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    correlations (exchange, quote_a, quote_b) {
+        quote_a -> Varchar,
+        quote_b -> Varchar,
         timestamp -> Timestamptz,
-        average -> Float4,
-        target -> Float4,
+        correlation -> Float4,
+        samples -> Int4,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    executions (id) {
+        id -> Int4,
+        trade_id -> Int4,
+        executed_at -> Timestamptz,
+        price -> Numeric,
+        portion -> Numeric,
+        reason -> Varchar,
+    }
+}
+
+table! {
+    halts (id) {
+        id -> Int4,
+        created_at -> Timestamptz,
+        reason -> Varchar,
+        until -> Nullable<Timestamptz>,
+        reset_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    indicators (exchange, base, quote, period, timestamp) {
+        base -> Varchar,
+        quote -> Varchar,
+        period -> Int4,
+        timestamp -> Timestamptz,
+        ma_short -> Nullable<Numeric>,
+        ma_med -> Nullable<Numeric>,
+        ma_long -> Nullable<Numeric>,
+        base_volume_med -> Nullable<Numeric>,
+        volatility_med -> Nullable<Float4>,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    market_trades (exchange, base, quote, trade_id) {
+        base -> Varchar,
+        quote -> Varchar,
+        trade_id -> Varchar,
+        timestamp -> Timestamptz,
+        is_buy -> Bool,
+        price -> Numeric,
+        size -> Numeric,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    markets (exchange, base, quote) {
+        base -> Varchar,
+        quote -> Varchar,
+        price_precision -> Int4,
+        amount_precision -> Int4,
+        min_order -> Numeric,
+        is_frozen -> Bool,
+        post_only -> Bool,
+        updated_at -> Timestamptz,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    regimes (id) {
+        id -> Int4,
+        timestamp -> Timestamptz,
+        trend_average -> Nullable<Numeric>,
+        trend_ma_long -> Nullable<Numeric>,
+        breadth -> Nullable<Float4>,
+        quotes -> Int4,
+        regime -> Varchar,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    shortlist (exchange, quote) {
+        quote -> Varchar,
+        timestamp -> Timestamptz,
+        average -> Numeric,
+        target -> Numeric,
         confidence -> Float4,
+        stop_loss -> Float4,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    ticker_snapshots (exchange, base, quote, timestamp) {
+        base -> Varchar,
+        quote -> Varchar,
+        timestamp -> Timestamptz,
+        last -> Numeric,
+        lowest_ask -> Numeric,
+        highest_bid -> Numeric,
+        percent_change -> Float4,
+        base_volume -> Numeric,
+        quote_volume -> Numeric,
+        high_24hr -> Numeric,
+        low_24hr -> Numeric,
+        exchange -> Varchar,
     }
 }
 
@@ -31,16 +149,54 @@ table! {
         open_at -> Timestamptz,
         close_at -> Nullable<Timestamptz>,
         updated_at -> Timestamptz,
-        open_average -> Float4,
-        target -> Float4,
-        open -> Nullable<Float4>,
-        close -> Nullable<Float4>,
-        highest_bid -> Nullable<Float4>,
+        open_average -> Numeric,
+        target -> Numeric,
+        open -> Nullable<Numeric>,
+        close -> Nullable<Numeric>,
+        highest_bid -> Nullable<Numeric>,
+        stop_loss -> Float4,
+        remaining -> Numeric,
+        take_profit_level -> Int4,
+        profit -> Nullable<Float4>,
+        close_reason -> Nullable<Varchar>,
+        size -> Numeric,
+        entry_bid -> Nullable<Numeric>,
+        entry_bid_at -> Nullable<Timestamptz>,
+        entry_bids -> Int4,
+        entry_type -> Nullable<Varchar>,
+        exit_reason -> Nullable<Varchar>,
+        exit_started_at -> Nullable<Timestamptz>,
+        exit_slices -> Int4,
+        exchange -> Varchar,
+    }
+}
+
+table! {
+    trend_verdicts (exchange, base, quote, period, trend) {
+        base -> Varchar,
+        quote -> Varchar,
+        period -> Int4,
+        trend -> Varchar,
+        timestamp -> Timestamptz,
+        passed -> Bool,
+        exchange -> Varchar,
     }
 }
 
+joinable!(executions -> trades (trade_id));
+
 allow_tables_to_appear_in_same_query!(
     candles,
+    cooldowns,
+    correlations,
+    executions,
+    halts,
+    indicators,
+    market_trades,
+    markets,
+    regimes,
     shortlist,
+    ticker_snapshots,
     trades,
+    trend_verdicts,
 );
@@ -0,0 +1,68 @@
+use diesel::PgConnection;
+
+use crate::cooldown::get_cooldown;
+use crate::correlation::get_too_correlated;
+use crate::error::BotError;
+use crate::exchange::Exchange;
+use crate::models::Trade;
+use crate::regime::{get_regime, max_entries, TREND_QUOTE};
+use crate::risk::check_risk;
+use crate::shortlist::get_shortlist;
+use crate::trade::{create_trade, get_trades, is_trade_open};
+
+/// Opens trades for the quotes shortlisted in `exchange`, limited by the
+/// market regime (counting the open trades of `exchange`), cooldowns, correlation with `open_quotes` and the risk
+/// limits
+///
+/// Returns the created trades, their processes are started by the caller.
+pub fn select_trades(
+    connection: &mut PgConnection,
+    exchange: Exchange,
+    open_quotes: &[String],
+) -> Result<Vec<Trade>, BotError> {
+    let regime = get_regime(connection, exchange)?;
+    let entries_limit = regime.as_ref().and_then(max_entries);
+    let mut open_trades = get_trades(connection, exchange)?.len();
+    let mut open_quotes = open_quotes.to_vec();
+    let mut trades: Vec<Trade> = vec![];
+
+    if let Some(r) = &regime {
+        println!(
+            "Market regime {}, breadth: {:?}, {}: {:?} / {:?}",
+            r.regime, r.breadth, TREND_QUOTE, r.trend_average, r.trend_ma_long
+        );
+    }
+
+    for s in get_shortlist(connection, exchange)? {
+        if entries_limit.map(|max| open_trades >= max).unwrap_or(false) {
+            println!("{}: not opening trade, market regime", s.quote);
+            continue;
+        }
+        if let Some(until) = get_cooldown(connection, exchange, &s.quote)? {
+            println!("{}: cooling down until {}", s.quote, until);
+            continue;
+        }
+        if !is_trade_open(connection, &s)? {
+            if let Some(c) = get_too_correlated(connection, exchange, &s.quote, &open_quotes)? {
+                println!(
+                    "{}: not opening trade, correlation {:.3} with {}",
+                    s.quote, c.correlation, c.quote_b
+                );
+                continue;
+            }
+            match check_risk(connection, &s) {
+                Err(BotError::RiskRejected(reason)) => {
+                    println!("{}: not opening trade, {}", s.quote, reason);
+                    continue;
+                }
+                result => result?,
+            }
+            let trade = create_trade(connection, &s)?;
+            open_quotes.push(trade.quote.clone());
+            open_trades += 1;
+            trades.push(trade);
+        }
+    }
+
+    Ok(trades)
+}
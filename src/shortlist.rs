@@ -1,23 +1,30 @@
 extern crate diesel;
 
 use super::diesel::prelude::*;
+use super::error::BotError;
+use super::exchange::Exchange;
 use super::models::Shortlist;
 use super::schema::shortlist::dsl::*;
 
-/// Returns the shortlist entry with the highest confidence score.
+/// Returns the shortlist entries of `exchange_p`, the highest confidence
+/// score first, and removes them from the shortlist
 pub fn get_shortlist(
     connection: &mut PgConnection,
-) -> Result<Vec<Shortlist>, Box<dyn std::error::Error>> {
+    exchange_p: Exchange,
+) -> Result<Vec<Shortlist>, BotError> {
     let rows = shortlist
+        .filter(exchange.eq(exchange_p.name()))
         .order(confidence.desc())
-        .load::<Shortlist>(connection)
-        .unwrap();
+        .load::<Shortlist>(connection)?;
 
     let quotes: Vec<String> = rows.clone().iter().map(|row| row.quote.clone()).collect();
 
-    diesel::delete(shortlist.filter(quote.eq_any(quotes)))
-        .execute(connection)
-        .unwrap();
+    diesel::delete(
+        shortlist
+            .filter(exchange.eq(exchange_p.name()))
+            .filter(quote.eq_any(quotes)),
+    )
+    .execute(connection)?;
 
     Ok(rows)
 }
@@ -1,104 +1,293 @@
 extern crate diesel;
 
+use crate::decimal::DECIMAL_SCALE;
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
 use crate::schema::shortlist;
-use crate::trade_logic::{CONSTANT_RISE, STOP_LOSS};
+use crate::trade_logic::{
+    CONSTANT_RISE, MAX_SPREAD, STOP_LOSS_MAX, STOP_LOSS_MIN, STOP_LOSS_MULTIPLIER,
+};
 use diesel::prelude::*;
 use diesel::{delete, sql_query};
 
+// candle period in seconds that indicators and the shortlist are computed at
+pub const PERIOD: i32 = 900;
+
 const MA_SHORT: i32 = 5;
 const MA_MED: i32 = 30;
 const MA_LONG: i32 = 200;
 
-pub fn get_analyze_sql(base: String, period: i32) -> String {
-    format!(
-        "analyzed AS (
+// minimum traded volume in base currency (USDT) during the last 24 hours
+const MIN_BASE_VOLUME: f64 = 20000.0;
+
+// candidates with a bigger %-change within a candle of the medium window are
+// left out
+pub const MAX_VOLATILITY: f64 = 0.02;
+
+// the whole stop loss range is used by the candidates
+const _: () = assert!(MAX_VOLATILITY * STOP_LOSS_MULTIPLIER > STOP_LOSS_MAX);
+
+/// Trend a timeframe must be in for a shortlist candidate to pass it
+pub enum Trend {
+    /// Average above the short, the short above the medium and the medium
+    /// above the long moving average of the indicators
+    MaStack,
+    /// Average above its moving average over this many candles
+    AboveMa(i32),
+}
+
+impl Trend {
+    /// Name of the trend in the trend verdicts
+    pub fn name(&self) -> String {
+        match self {
+            Trend::MaStack => "ma_stack".to_string(),
+            Trend::AboveMa(candles) => format!("above_ma_{}", candles),
+        }
+    }
+}
+
+pub struct Timeframe {
+    pub period: i32,
+    pub trend: Trend,
+}
+
+// trends checked for the shortlist candidates, e.g. a bounce in the MA stack
+// of `PERIOD` candles fails if the 4 hour average is below its 50 candle MA
+pub const TIMEFRAMES: &[Timeframe] = &[
+    Timeframe {
+        period: PERIOD,
+        trend: Trend::MaStack,
+    },
+    Timeframe {
+        period: 14400,
+        trend: Trend::AboveMa(50),
+    },
+];
+
+// whether the shortlist requires the candidates to pass every timeframe in
+// `TIMEFRAMES`, otherwise the verdicts are only stored
+pub const REQUIRE_TIMEFRAMES: bool = true;
+
+/// Periods that need indicators, `PERIOD` and those of the `Trend::MaStack`
+/// timeframes
+pub fn indicator_periods() -> Vec<i32> {
+    let mut periods = vec![PERIOD];
+    for timeframe in TIMEFRAMES {
+        if matches!(timeframe.trend, Trend::MaStack) && !periods.contains(&timeframe.period) {
+            periods.push(timeframe.period);
+        }
+    }
+    periods
+}
+
+/// Computes moving averages, volume and volatility for candles of
+/// `exchange` that don't yet have an indicator row, and stores them in the
+/// indicators table.
+///
+/// The latest stored indicator row of each quote is computed again, since
+/// its candle may have been in progress. Window functions need the preceding
+/// `MA_LONG` candles, so only that much history before it is read.
+pub fn update_indicators(
+    connection: &mut PgConnection,
+    exchange: Exchange,
+    base: String,
+    period: i32,
+) -> Result<usize, BotError> {
+    println!("updating indicators");
+
+    sql_query(format!(
+        "
+      WITH latest AS (
         SELECT
-          DISTINCT ON (quote) quote,
-          timestamp,
-          average,
-          volume,
+          quote,
+          MAX(timestamp) AS timestamp
+        FROM
+          indicators
+        WHERE
+          exchange = '{exchange}'
+          AND base = '{base}'
+          AND period = {period}
+        GROUP BY
+          quote
+      ),
+      bounds AS (
+        SELECT
+          latest.quote,
+          latest.timestamp AS latest,
+          -- oldest candle needed for the long window of the latest stored one
+          (
+            SELECT
+              timestamp
+            FROM
+              candles
+            WHERE
+              exchange = '{exchange}'
+              AND base = '{base}'
+              AND quote = latest.quote
+              AND period = {period}
+              AND timestamp <= latest.timestamp
+            ORDER BY
+              timestamp DESC
+            OFFSET {ma_long}
+            LIMIT 1
+          ) AS window_start
+        FROM
+          latest
+      ),
+      computed AS (
+        SELECT
+          candles.exchange,
+          candles.base,
+          candles.quote,
+          candles.period,
+          candles.timestamp,
           -- short moving average
           AVG(average) OVER(
-            PARTITION BY quote,
-            base,
-            period
+            PARTITION BY candles.quote
             ORDER BY
-              timestamp ROWS BETWEEN {ma_short} PRECEDING
+              candles.timestamp ROWS BETWEEN {ma_short} PRECEDING
               AND CURRENT ROW
           ) AS ma_short,
           -- medium moving average
           AVG(average) OVER(
-            PARTITION BY quote,
-            base,
-            period
+            PARTITION BY candles.quote
             ORDER BY
-              timestamp ROWS BETWEEN {ma_med} PRECEDING
+              candles.timestamp ROWS BETWEEN {ma_med} PRECEDING
               AND CURRENT ROW
           ) AS ma_med,
           -- long moving average
           AVG(average) OVER(
-            PARTITION BY quote,
-            base,
-            period
+            PARTITION BY candles.quote
             ORDER BY
-              timestamp ROWS BETWEEN {ma_long} PRECEDING
+              candles.timestamp ROWS BETWEEN {ma_long} PRECEDING
               AND CURRENT ROW
           ) AS ma_long,
           -- short window volume
           SUM(volume * average) OVER(
-            PARTITION BY quote,
-            base,
-            period
+            PARTITION BY candles.quote
             ORDER BY
-              timestamp ROWS BETWEEN {ma_med} PRECEDING
+              candles.timestamp ROWS BETWEEN {ma_med} PRECEDING
               AND CURRENT ROW
           ) AS base_volume_med,
           MAX((high - low) / low) OVER(
-            PARTITION BY quote,
-            base,
-            period
+            PARTITION BY candles.quote
             ORDER BY
-              timestamp ROWS BETWEEN {ma_med} PRECEDING
+              candles.timestamp ROWS BETWEEN {ma_med} PRECEDING
               AND CURRENT ROW
-          ) AS volatility_med
+          ) AS volatility_med,
+          bounds.latest
         FROM
           candles
+          LEFT JOIN bounds ON bounds.quote = candles.quote
         WHERE
-          base = '{base}'
-          AND period = {period}
-          AND quote IN (SELECT quote FROM filtered_symbols)
-        ORDER BY
-          quote,
-          timestamp DESC
-      )",
+          candles.exchange = '{exchange}'
+          AND candles.base = '{base}'
+          AND candles.period = {period}
+          AND (
+            bounds.window_start IS NULL
+            OR candles.timestamp >= bounds.window_start
+          )
+      )
+      INSERT INTO indicators(
+        exchange, base, quote, period, timestamp, ma_short, ma_med, ma_long, base_volume_med,
+        volatility_med
+      ) (SELECT
+        exchange,
+        base,
+        quote,
+        period,
+        timestamp,
+        ma_short,
+        ma_med,
+        ma_long,
+        base_volume_med,
+        volatility_med
+      FROM
+        computed
+      WHERE
+        latest IS NULL
+        OR timestamp >= latest
+      )
+      ON CONFLICT (exchange, base, quote, period, timestamp) DO UPDATE SET
+        ma_short = EXCLUDED.ma_short,
+        ma_med = EXCLUDED.ma_med,
+        ma_long = EXCLUDED.ma_long,
+        base_volume_med = EXCLUDED.base_volume_med,
+        volatility_med = EXCLUDED.volatility_med;
+    ",
+        exchange = exchange.name(),
         base = base,
         period = period,
         ma_short = MA_SHORT,
         ma_med = MA_MED,
         ma_long = MA_LONG
-    )
+    ))
+    .execute(connection)
+    .context("updating indicators")
 }
 
-pub fn update_shortlist(
-    connection: &mut PgConnection,
-    base: String,
-    period: i32,
-) -> Result<usize, diesel::result::Error> {
-    println!("updating shortlist");
-
-    let max_seconds = period * MA_LONG;
-
-    delete(shortlist::table).execute(connection)?;
+/// Latest indicator values for each quote in `filtered_symbols`
+///
+/// Indicators must be up to date, see `update_indicators`.
+pub fn get_analyze_sql(exchange: Exchange, base: String, period: i32) -> String {
+    format!(
+        "analyzed AS (
+        SELECT
+          DISTINCT ON (indicators.quote) indicators.quote,
+          indicators.timestamp,
+          candles.average,
+          candles.volume,
+          indicators.ma_short,
+          indicators.ma_med,
+          indicators.ma_long,
+          indicators.base_volume_med,
+          indicators.volatility_med
+        FROM
+          indicators
+          JOIN candles USING (exchange, base, quote, period, timestamp)
+        WHERE
+          indicators.exchange = '{exchange}'
+          AND indicators.base = '{base}'
+          AND indicators.period = {period}
+          AND indicators.quote IN (SELECT quote FROM filtered_symbols)
+        ORDER BY
+          indicators.quote,
+          indicators.timestamp DESC
+      )",
+        exchange = exchange.name(),
+        base = base,
+        period = period,
+    )
+}
 
-    sql_query(format!(
-        "
-      WITH filtered_symbols AS (
+/// Quotes that pass the sanity checks for the shortlist, as the
+/// `latest_tickers` and `filtered_symbols` expressions
+pub fn get_candidates_sql(exchange: Exchange, base: &str, period: i32) -> String {
+    format!(
+        "latest_tickers AS (
+        SELECT
+          DISTINCT ON (quote) quote,
+          base_volume,
+          (lowest_ask - highest_bid) / lowest_ask AS spread
+        FROM
+          ticker_snapshots
+        WHERE
+          exchange = '{exchange}'
+          AND base = '{base}'
+          AND timestamp > (current_timestamp - interval '30 minutes')
+          AND lowest_ask > 0
+        ORDER BY
+          quote,
+          timestamp DESC
+      ),
+      filtered_symbols AS (
         SELECT
           quote
         FROM
           candles
         WHERE
-          base = '{base}'
+          exchange = '{exchange}'
+          AND base = '{base}'
           AND period = {period}
           AND timestamp > (current_timestamp - interval '{max_seconds} seconds')
           -- filter out instruments
@@ -106,6 +295,17 @@ pub fn update_shortlist(
           AND quote NOT LIKE '%BEAR'
           -- filter out stablecoins
           AND quote NOT IN ('BUSD', 'DAI', 'GUSD', 'PAX', 'TUSD', 'USDC', 'USDD', 'USDD', 'USDH', 'USDJ', 'USDP', 'USDT')
+          -- filter out frozen markets, and those not in markets at all
+          AND quote IN (
+            SELECT quote FROM markets
+            WHERE exchange = '{exchange}' AND base = '{base}' AND NOT is_frozen
+          )
+          -- filter out those with too small 24h volume in base unit (USDT), or
+          -- too wide spread in the latest ticker
+          AND quote IN (
+            SELECT quote FROM latest_tickers
+            WHERE base_volume > {min_base_volume} AND spread < {max_spread}
+          )
         GROUP BY
           quote,
           base,
@@ -117,43 +317,241 @@ pub fn update_shortlist(
           AND MIN(average) > 1e-6
           -- more than 5 candles missing from the longest MA period
           AND count(*) > ({max_seconds} / {period}) - 5
-      ),
+      )",
+        exchange = exchange.name(),
+        base = base,
+        period = period,
+        max_seconds = period * MA_LONG,
+        min_base_volume = MIN_BASE_VOLUME,
+        max_spread = MAX_SPREAD,
+    )
+}
+
+/// Latest trend of `timeframe` for each quote in `filtered_symbols`, as the
+/// `trend` expression with the columns quote and passed
+///
+/// Quotes without recent candles of the timeframe are left out.
+fn get_trend_sql(exchange: Exchange, base: &str, timeframe: &Timeframe) -> String {
+    let period = timeframe.period;
+    // the latest candle must be from the latest two periods
+    let recent_seconds = 2 * period;
+
+    match timeframe.trend {
+        Trend::MaStack => format!(
+            "trend AS (
+        SELECT
+          DISTINCT ON (indicators.quote) indicators.quote,
+          candles.average > indicators.ma_short
+            AND indicators.ma_short > indicators.ma_med
+            AND indicators.ma_med > indicators.ma_long AS passed
+        FROM
+          indicators
+          JOIN candles USING (exchange, base, quote, period, timestamp)
+        WHERE
+          indicators.exchange = '{exchange}'
+          AND indicators.base = '{base}'
+          AND indicators.period = {period}
+          AND indicators.quote IN (SELECT quote FROM filtered_symbols)
+          AND indicators.timestamp > (current_timestamp - interval '{recent_seconds} seconds')
+        ORDER BY
+          indicators.quote,
+          indicators.timestamp DESC
+      )",
+            exchange = exchange.name(),
+            base = base,
+            period = period,
+            recent_seconds = recent_seconds,
+        ),
+        Trend::AboveMa(candles) => format!(
+            "trend AS (
+        SELECT
+          DISTINCT ON (quote) quote,
+          samples >= {candles} AND average > ma AS passed
+        FROM
+          (
+            SELECT
+              quote,
+              timestamp,
+              average,
+              AVG(average) OVER window_candles AS ma,
+              COUNT(average) OVER window_candles AS samples
+            FROM
+              candles
+            WHERE
+              exchange = '{exchange}'
+              AND base = '{base}'
+              AND period = {period}
+              AND quote IN (SELECT quote FROM filtered_symbols)
+              AND timestamp > (current_timestamp - interval '{window_seconds} seconds')
+            WINDOW window_candles AS (
+              PARTITION BY quote
+              ORDER BY
+                timestamp ROWS BETWEEN {preceding} PRECEDING
+                AND CURRENT ROW
+            )
+          ) AS averaged
+        WHERE
+          timestamp > (current_timestamp - interval '{recent_seconds} seconds')
+        ORDER BY
+          quote,
+          timestamp DESC
+      )",
+            exchange = exchange.name(),
+            base = base,
+            period = period,
+            candles = candles,
+            preceding = candles - 1,
+            window_seconds = (candles + 1) * period,
+            recent_seconds = recent_seconds,
+        ),
+    }
+}
+
+/// Stores the verdict of each of `TIMEFRAMES` for the shortlist candidates,
+/// replacing the previous verdicts
+///
+/// A candidate without recent data of a timeframe fails it. Indicators of
+/// the `Trend::MaStack` timeframes must be up to date, see
+/// `update_indicators`.
+pub fn update_trend_verdicts(
+    connection: &mut PgConnection,
+    exchange: Exchange,
+    base: String,
+    period: i32,
+) -> Result<usize, BotError> {
+    use crate::schema::trend_verdicts;
+
+    println!("updating trend verdicts");
+
+    delete(
+        trend_verdicts::table
+            .filter(trend_verdicts::exchange.eq(exchange.name()))
+            .filter(trend_verdicts::base.eq(&base)),
+    )
+    .execute(connection)
+    .context("deleting trend verdicts")?;
+
+    let mut count = 0;
+    for timeframe in TIMEFRAMES {
+        count += sql_query(format!(
+            "
+      WITH {candidates},
+      {trend}
+      INSERT INTO trend_verdicts(exchange, base, quote, period, trend, timestamp, passed) (SELECT
+        '{exchange}',
+        '{base}',
+        filtered_symbols.quote,
+        {period},
+        '{name}',
+        NOW(),
+        COALESCE(trend.passed, false)
+      FROM
+        filtered_symbols
+        LEFT JOIN trend USING (quote)
+      );
+    ",
+            candidates = get_candidates_sql(exchange, &base, period),
+            trend = get_trend_sql(exchange, &base, timeframe),
+            exchange = exchange.name(),
+            base = base,
+            period = timeframe.period,
+            name = timeframe.trend.name(),
+        ))
+        .execute(connection)
+        .context("updating trend verdicts")?;
+    }
+
+    Ok(count)
+}
+
+pub fn update_shortlist(
+    connection: &mut PgConnection,
+    exchange: Exchange,
+    base: String,
+    period: i32,
+) -> Result<usize, BotError> {
+    update_trend_verdicts(connection, exchange, base.clone(), period)?;
+
+    println!("updating shortlist");
+
+    delete(shortlist::table.filter(shortlist::exchange.eq(exchange.name()))).execute(connection)?;
+
+    // candidates must pass the trend of every timeframe
+    let confirmed = if REQUIRE_TIMEFRAMES {
+        format!(
+            "AND quote NOT IN (
+          SELECT quote FROM trend_verdicts
+          WHERE exchange = '{exchange}' AND base = '{base}' AND NOT passed
+        )",
+            exchange = exchange.name(),
+            base = base
+        )
+    } else {
+        String::new()
+    };
+
+    sql_query(format!(
+        "
+      WITH {candidates},
       {analyzed}
-      INSERT INTO shortlist(quote, timestamp, average, target, confidence) (SELECT
+      INSERT INTO shortlist(exchange, quote, timestamp, average, target, confidence, stop_loss) (SELECT
+        '{exchange}',
         quote,
         NOW(),
         average,
-        average * {stop_loss} as target,
-        average / ma_med as confidence
+        average * (1 - stop_loss::numeric) as target,
+        average / ma_med as confidence,
+        stop_loss
       FROM
-        (SELECT * FROM analyzed) AS analyzed
+        (
+          SELECT
+            *,
+            -- trailing stop distance scaled with recent volatility
+            {stop_loss}::real AS stop_loss
+          FROM
+            analyzed
+        ) AS analyzed
       WHERE
         (
-          -- filter out those with too small volume in base unit (USDT), short window
-          base_volume_med > 6000
           -- actual logic: current value must be above 10-period moving average,
           -- which must be above 30-period MA, which must be above 200-period MA
-          AND average > ma_short
+          average > ma_short
           AND ma_short > ma_med
           AND ma_med > ma_long
-          -- too big %-change in last candle
-          AND volatility_med < 0.02
-        ) is true);
+          -- too big %-change in last candles
+          AND volatility_med < {max_volatility}
+        ) is true
+        {confirmed});
     ",
-        max_seconds = max_seconds,
-        base = base.clone(),
-        period = period,
-        analyzed = get_analyze_sql(base, period),
-        stop_loss = 1.0 - STOP_LOSS,
+        candidates = get_candidates_sql(exchange, &base, period),
+        analyzed = get_analyze_sql(exchange, base, period),
+        exchange = exchange.name(),
+        stop_loss = stop_loss_sql("volatility_med"),
+        max_volatility = MAX_VOLATILITY,
+        confirmed = confirmed,
     ))
     .execute(connection)
+    .context("updating shortlist")
+}
+
+/// Trailing stop distance of a trade from the `volatility` column, see
+/// `STOP_LOSS_MULTIPLIER`
+fn stop_loss_sql(volatility: &str) -> String {
+    format!(
+        "LEAST(GREATEST({volatility} * {multiplier}, {min}), {max})",
+        volatility = volatility,
+        multiplier = STOP_LOSS_MULTIPLIER,
+        min = STOP_LOSS_MIN,
+        max = STOP_LOSS_MAX,
+    )
 }
 
 pub fn update_trades(
     connection: &mut PgConnection,
+    exchange: Exchange,
     base: String,
     period: i32,
-) -> Result<usize, diesel::result::Error> {
+) -> Result<usize, BotError> {
     println!("updating trades");
 
     sql_query(format!(
@@ -164,29 +562,327 @@ pub fn update_trades(
           target
         FROM
           trades
+        WHERE
+          exchange = '{exchange}'
       ),
       {analyzed}
       UPDATE
         trades
       SET
-        target = GREATEST(temp.target, trades.target * {constant_rise})
+        -- rounded so that the scale doesn't grow with each rise
+        target = ROUND(
+          GREATEST(
+            temp.average * (1 - trades.stop_loss::numeric),
+            trades.target * {constant_rise}
+          ),
+          {scale}
+        )
       FROM
         (
           SELECT
             quote,
-            average * {stop_loss}
+            average
           FROM
             analyzed
-        ) as temp(quote, target)
+        ) as temp(quote, average)
       WHERE
+        trades.exchange = '{exchange}' AND
         trades.base = '{base}' AND
         trades.quote = temp.quote AND
         trades.close_at IS NULL
     ",
+        exchange = exchange.name(),
         base = base.clone(),
-        analyzed = get_analyze_sql(base, period),
+        analyzed = get_analyze_sql(exchange, base, period),
         constant_rise = 1.0 + CONSTANT_RISE,
-        stop_loss = 1.0 - STOP_LOSS,
+        scale = DECIMAL_SCALE,
     ))
     .execute(connection)
+    .context("updating trades")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::Api;
+    use crate::chart_data::SOURCE_REST;
+    use crate::decimal::decimal;
+    use crate::models::{Candle, Indicator, Market, TickerSnapshot};
+    use crate::test_connection;
+    use bigdecimal::BigDecimal;
+    use chrono::{DateTime, Duration, TimeZone, Utc};
+
+    const TEST_BASE: &str = "TEST_INDICATORS";
+    const TEST_TIMEFRAMES: &str = "TEST_TIMEFRAMES";
+
+    fn candle(quote: &str, i: i64, average: i64) -> Candle {
+        let price = Some(BigDecimal::from(average));
+        Candle {
+            base: TEST_BASE.to_string(),
+            quote: quote.to_string(),
+            period: PERIOD,
+            timestamp: Utc
+                .timestamp_opt(1674043200 + i * PERIOD as i64, 0)
+                .unwrap(),
+            high: price.clone(),
+            low: price.clone(),
+            open: price.clone(),
+            close: price.clone(),
+            average: price,
+            volume: Some(BigDecimal::from(1)),
+            exchange: Exchange::Poloniex(Api::Legacy).name().to_string(),
+            source: SOURCE_REST.to_string(),
+        }
+    }
+
+    fn insert_candles(connection: &mut PgConnection, rows: &[Candle]) {
+        use crate::schema::candles::dsl::*;
+
+        diesel::insert_into(candles)
+            .values(rows)
+            .on_conflict((exchange, base, quote, period, timestamp))
+            .do_update()
+            .set(average.eq(diesel::upsert::excluded(average)))
+            .execute(connection)
+            .unwrap();
+    }
+
+    fn indicators_of(connection: &mut PgConnection, quote_p: &str) -> Vec<Indicator> {
+        use crate::schema::indicators::dsl::*;
+
+        indicators
+            .filter(base.eq(TEST_BASE))
+            .filter(quote.eq(quote_p))
+            .order(timestamp.asc())
+            .load(connection)
+            .unwrap()
+    }
+
+    fn delete_test_rows(connection: &mut PgConnection) {
+        use crate::schema::{candles, indicators};
+
+        diesel::delete(indicators::table.filter(indicators::base.eq(TEST_BASE)))
+            .execute(connection)
+            .unwrap();
+        diesel::delete(candles::table.filter(candles::base.eq(TEST_BASE)))
+            .execute(connection)
+            .unwrap();
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn stop_loss_sql_test() {
+        let (_lock, mut connection) = test_connection();
+
+        #[derive(QueryableByName)]
+        struct StopLoss {
+            #[diesel(sql_type = diesel::sql_types::Double)]
+            stop_loss: f64,
+        }
+
+        let stop_losses: Vec<f64> = sql_query(format!(
+            "SELECT ({})::float8 AS stop_loss
+            FROM (VALUES (1, 0.001), (2, 0.006), (3, 0.019)) AS t(i, volatility)
+            ORDER BY i",
+            stop_loss_sql("volatility")
+        ))
+        .load::<StopLoss>(&mut connection)
+        .unwrap()
+        .into_iter()
+        .map(|s| s.stop_loss)
+        .collect();
+
+        // clamped to the minimum, scaled, and clamped to the maximum
+        assert_eq!(stop_losses.len(), 3);
+        assert_eq!(stop_losses[0], STOP_LOSS_MIN);
+        assert!((stop_losses[1] - 0.006 * STOP_LOSS_MULTIPLIER).abs() < 1e-9);
+        assert_eq!(stop_losses[2], STOP_LOSS_MAX);
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn update_indicators_test() {
+        let (_lock, mut connection) = test_connection();
+        let connection = &mut connection;
+        let exchange = Exchange::Poloniex(Api::Legacy);
+        delete_test_rows(connection);
+
+        let averages: Vec<i64> = (0..260).map(|i| 100 + (i * 7) % 23).collect();
+        let series = |quote: &str, range: std::ops::Range<usize>| -> Vec<Candle> {
+            range
+                .map(|i| candle(quote, i as i64, averages[i]))
+                .collect()
+        };
+
+        // INC gets its indicators in two steps, FULL all at once
+        insert_candles(connection, &series("INC", 0..250));
+        update_indicators(connection, exchange, TEST_BASE.to_string(), PERIOD).unwrap();
+        insert_candles(connection, &series("INC", 250..260));
+        insert_candles(connection, &series("FULL", 0..260));
+        update_indicators(connection, exchange, TEST_BASE.to_string(), PERIOD).unwrap();
+
+        // the windows of the new rows reach over the rows stored before
+        let incremental = indicators_of(connection, "INC");
+        let full = indicators_of(connection, "FULL");
+        assert_eq!(incremental.len(), 260);
+        for (inc, full) in incremental.iter().zip(full.iter()) {
+            assert_eq!(inc.timestamp, full.timestamp);
+            assert_eq!(inc.ma_short, full.ma_short);
+            assert_eq!(inc.ma_med, full.ma_med);
+            assert_eq!(inc.ma_long, full.ma_long);
+            assert_eq!(inc.base_volume_med, full.base_volume_med);
+        }
+
+        // the latest candle changes while it's in progress, its row follows
+        insert_candles(connection, &[candle("INC", 259, 1000)]);
+        update_indicators(connection, exchange, TEST_BASE.to_string(), PERIOD).unwrap();
+
+        let last_six: i64 = averages[254..259].iter().sum::<i64>() + 1000;
+        let updated = indicators_of(connection, "INC").pop().unwrap();
+        assert_eq!(
+            updated.ma_short.clone().unwrap().round(DECIMAL_SCALE),
+            (BigDecimal::from(last_six) / BigDecimal::from(6)).round(DECIMAL_SCALE)
+        );
+        assert_ne!(updated.ma_short, full.last().unwrap().ma_short);
+
+        delete_test_rows(connection);
+    }
+
+    #[test]
+    fn trend_name_test() {
+        assert_eq!(Trend::MaStack.name(), "ma_stack");
+        assert_eq!(Trend::AboveMa(50).name(), "above_ma_50");
+        assert_eq!(indicator_periods(), vec![PERIOD]);
+    }
+
+    /// Candles of `period` ending at `end` with the given averages
+    fn candles_to(quote: &str, period: i32, end: DateTime<Utc>, averages: &[f64]) -> Vec<Candle> {
+        averages
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                let price = Some(decimal(*a).unwrap());
+                let back = (averages.len() - 1 - i) as i64;
+                Candle {
+                    base: TEST_TIMEFRAMES.to_string(),
+                    quote: quote.to_string(),
+                    period,
+                    timestamp: end - Duration::seconds(back * period as i64),
+                    high: price.clone(),
+                    low: price.clone(),
+                    open: price.clone(),
+                    close: price.clone(),
+                    average: price,
+                    volume: Some(BigDecimal::from(1)),
+                    exchange: Exchange::Poloniex(Api::Legacy).name().to_string(),
+                    source: SOURCE_REST.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    /// Market and ticker that pass the candidate checks
+    fn insert_candidate(connection: &mut PgConnection, quote: &str) -> Result<(), BotError> {
+        use crate::schema::{markets, ticker_snapshots};
+
+        let exchange = Exchange::Poloniex(Api::Legacy).name().to_string();
+        diesel::insert_into(markets::table)
+            .values(Market {
+                base: TEST_TIMEFRAMES.to_string(),
+                quote: quote.to_string(),
+                price_precision: 2,
+                amount_precision: 4,
+                min_order: BigDecimal::from(1),
+                is_frozen: false,
+                post_only: false,
+                updated_at: Utc::now(),
+                exchange: exchange.clone(),
+            })
+            .execute(connection)?;
+        diesel::insert_into(ticker_snapshots::table)
+            .values(TickerSnapshot {
+                base: TEST_TIMEFRAMES.to_string(),
+                quote: quote.to_string(),
+                timestamp: Utc::now(),
+                last: BigDecimal::from(100),
+                lowest_ask: decimal(100.01)?,
+                highest_bid: BigDecimal::from(100),
+                percent_change: 0.0,
+                base_volume: decimal(MIN_BASE_VOLUME * 10.0)?,
+                quote_volume: BigDecimal::from(1000),
+                high_24hr: BigDecimal::from(101),
+                low_24hr: BigDecimal::from(99),
+                exchange,
+            })
+            .execute(connection)?;
+        Ok(())
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn timeframes_test() {
+        use crate::schema::{candles, trend_verdicts};
+
+        let (_lock, mut connection) = test_connection();
+        let exchange = Exchange::Poloniex(Api::Legacy);
+
+        connection.test_transaction::<_, BotError, _>(|conn| {
+            let end = Utc::now();
+            let rising: Vec<f64> = (0..MA_LONG + 10).map(|i| 100.0 + 0.1 * i as f64).collect();
+            let falling: Vec<f64> = (0..50).map(|i| 200.0 - i as f64).collect();
+
+            // both rise in the MA stack of PERIOD, one falls on 14400
+            for (quote, longer) in [("TEST_BOTH", &rising[..50]), ("TEST_SHORT", &falling[..])] {
+                insert_candidate(conn, quote)?;
+                diesel::insert_into(candles::table)
+                    .values(candles_to(quote, PERIOD, end, &rising))
+                    .execute(conn)?;
+                diesel::insert_into(candles::table)
+                    .values(candles_to(quote, 14400, end, longer))
+                    .execute(conn)?;
+            }
+
+            update_indicators(conn, exchange, TEST_TIMEFRAMES.to_string(), PERIOD)?;
+            update_shortlist(conn, exchange, TEST_TIMEFRAMES.to_string(), PERIOD)?;
+
+            let verdicts: Vec<(String, i32, String, bool)> = trend_verdicts::table
+                .filter(trend_verdicts::base.eq(TEST_TIMEFRAMES))
+                .select((
+                    trend_verdicts::quote,
+                    trend_verdicts::period,
+                    trend_verdicts::trend,
+                    trend_verdicts::passed,
+                ))
+                .order((trend_verdicts::quote, trend_verdicts::period))
+                .load(conn)?;
+            let verdict = |quote: &str, period: i32, trend: &str, passed: bool| {
+                (quote.to_string(), period, trend.to_string(), passed)
+            };
+            assert_eq!(
+                verdicts,
+                [
+                    verdict("TEST_BOTH", PERIOD, "ma_stack", true),
+                    verdict("TEST_BOTH", 14400, "above_ma_50", true),
+                    verdict("TEST_SHORT", PERIOD, "ma_stack", true),
+                    verdict("TEST_SHORT", 14400, "above_ma_50", false),
+                ]
+            );
+
+            let shortlisted: Vec<String> = shortlist::table
+                .filter(shortlist::exchange.eq(exchange.name()))
+                .select(shortlist::quote)
+                .order(shortlist::quote)
+                .load(conn)?;
+            let expected: &[&str] = if REQUIRE_TIMEFRAMES {
+                &["TEST_BOTH"]
+            } else {
+                &["TEST_BOTH", "TEST_SHORT"]
+            };
+            assert_eq!(shortlisted, expected);
+            Ok(())
+        });
+    }
 }
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{connect, Message, WebSocket};
+use url::Url;
+
+use crate::api::{legacy_ws_url, Api};
+use crate::binance::{self, DepthTracker};
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
+use crate::fetcher::Fetcher;
+use crate::kraken;
+use crate::message::{parse_message, BookEvent, Command, PushMessage};
+use crate::poloniex_v3::{self, parse_ws_message, BookTracker, WsCommand, WsMessage};
+
+// the v3 API closes connections that haven't sent anything in 30 seconds
+const PING_INTERVAL: Duration = Duration::from_secs(20);
+
+// Binance closes connections that send more than 5 messages a second
+const BINANCE_REQUEST_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Data subscribed from a market
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Channel {
+    /// Order book snapshot and updates, also trades in the legacy API
+    Book,
+    /// Public trades
+    Trades,
+    /// Candles of a period in seconds, only in the Poloniex v3 API
+    Candles(i32),
+}
+
+/// Message read from a market stream
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamMessage {
+    /// Events of a market
+    Events {
+        base: String,
+        quote: String,
+        events: Vec<BookEvent>,
+    },
+    /// Error message from the exchange
+    Error(String),
+    /// Message that couldn't be used, with the reason
+    Skipped(String),
+    /// Heartbeat, subscription or other message without events
+    Other,
+}
+
+/// Websocket connection to the order books, trades and candles of markets,
+/// with the messages of each exchange read as `BookEvent`s of a market
+pub struct MarketStream {
+    exchange: Exchange,
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    // legacy channel ids of the markets, known after the order book snapshot
+    channels: HashMap<u32, (String, String)>,
+    books: BookTracker,
+    depths: DepthTracker,
+    kraken_books: kraken::BookTracker,
+    // Binance order book snapshots are fetched from the REST API
+    fetcher: Option<Fetcher>,
+    pinged_at: Instant,
+    request_id: u32,
+}
+
+impl MarketStream {
+    pub fn connect(exchange: Exchange) -> Result<MarketStream, BotError> {
+        let url = match exchange {
+            Exchange::Poloniex(Api::Legacy) => legacy_ws_url(),
+            Exchange::Poloniex(Api::V3) => poloniex_v3::WS_URL.to_string(),
+            Exchange::Binance => binance::WS_URL.to_string(),
+            Exchange::Kraken => kraken::WS_URL.to_string(),
+        };
+        let url = Url::parse(&url).map_err(|e| BotError::Config(e.to_string()))?;
+        let (socket, _response) = connect(url).context("connecting to websocket")?;
+
+        // reads time out so that pings can be sent while waiting for messages
+        if exchange == Exchange::Poloniex(Api::V3) {
+            let timeout = Some(PING_INTERVAL);
+            match socket.get_ref() {
+                MaybeTlsStream::Plain(s) => s.set_read_timeout(timeout),
+                MaybeTlsStream::NativeTls(s) => s.get_ref().set_read_timeout(timeout),
+                _ => Ok(()),
+            }
+            .map_err(|e| BotError::Network(e.to_string()))?;
+        }
+
+        let fetcher = match exchange {
+            Exchange::Binance => Some(Fetcher::new(exchange)?),
+            _ => None,
+        };
+
+        Ok(MarketStream {
+            exchange,
+            socket,
+            channels: HashMap::new(),
+            books: BookTracker::new(),
+            depths: DepthTracker::new(),
+            kraken_books: kraken::BookTracker::new(),
+            fetcher,
+            pinged_at: Instant::now(),
+            request_id: 0,
+        })
+    }
+
+    fn send(&mut self, text: String) -> Result<(), BotError> {
+        self.socket
+            .write_message(Message::Text(text))
+            .context("sending to websocket")
+    }
+
+    /// Subscribes to the channels of the market `base`_`quote`
+    ///
+    /// In the legacy API trades come with the order book. Candles are only
+    /// available in the v3 API.
+    pub fn subscribe(
+        &mut self,
+        base: &str,
+        quote: &str,
+        channels: &[Channel],
+    ) -> Result<(), BotError> {
+        if self.exchange != Exchange::Poloniex(Api::V3)
+            && channels.iter().any(|c| matches!(c, Channel::Candles(_)))
+        {
+            return Err(BotError::Config(
+                "candle channels need the Poloniex v3 API".to_string(),
+            ));
+        }
+        let symbol = self.exchange.symbol(base, quote);
+
+        match self.exchange {
+            Exchange::Poloniex(Api::Legacy) => {
+                let command = Command {
+                    command: "subscribe".to_string(),
+                    channel: symbol,
+                };
+                self.send(serde_json::to_string(&command)?)
+            }
+            Exchange::Poloniex(Api::V3) => {
+                let names = channels
+                    .iter()
+                    .map(|channel| match channel {
+                        Channel::Book => Ok(poloniex_v3::BOOK_CHANNEL.to_string()),
+                        Channel::Trades => Ok(poloniex_v3::TRADES_CHANNEL.to_string()),
+                        Channel::Candles(period) => poloniex_v3::candles_channel(*period)
+                            .ok_or_else(|| {
+                                BotError::Config(format!("no candle channel for {}", period))
+                            }),
+                    })
+                    .collect::<Result<Vec<String>, BotError>>()?;
+
+                self.send(serde_json::to_string(&WsCommand::new(
+                    "subscribe",
+                    &names,
+                    &[symbol],
+                ))?)
+            }
+            Exchange::Binance => {
+                let params = channels
+                    .iter()
+                    .filter_map(|channel| match channel {
+                        Channel::Book => Some(binance::depth_stream(&symbol)),
+                        Channel::Trades => Some(binance::trade_stream(&symbol)),
+                        Channel::Candles(_) => None,
+                    })
+                    .collect();
+                self.request_id += 1;
+                let request = binance::WsRequest {
+                    method: "SUBSCRIBE".to_string(),
+                    params,
+                    id: self.request_id,
+                };
+
+                thread::sleep(BINANCE_REQUEST_INTERVAL);
+                self.send(serde_json::to_string(&request)?)
+            }
+            Exchange::Kraken => {
+                let pairs = [symbol];
+                for channel in channels {
+                    let name = match channel {
+                        Channel::Book => kraken::BOOK_CHANNEL,
+                        Channel::Trades => kraken::TRADES_CHANNEL,
+                        Channel::Candles(_) => continue,
+                    };
+                    self.send(serde_json::to_string(&kraken::WsRequest::new(
+                        "subscribe",
+                        name,
+                        &pairs,
+                    ))?)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Reads the next message, fails only if the connection fails
+    pub fn read(&mut self) -> Result<StreamMessage, BotError> {
+        if self.exchange == Exchange::Poloniex(Api::V3) && self.pinged_at.elapsed() >= PING_INTERVAL
+        {
+            self.send(serde_json::to_string(&WsCommand::new("ping", &[], &[]))?)?;
+            self.pinged_at = Instant::now();
+        }
+
+        let msg = match self.socket.read_message() {
+            Ok(msg) => msg,
+            Err(tungstenite::Error::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(StreamMessage::Other)
+            }
+            Err(e) => return Err(BotError::from(e).context("reading message")),
+        };
+        // pings are answered by the socket
+        if !msg.is_text() {
+            return Ok(StreamMessage::Other);
+        }
+
+        match self.exchange {
+            Exchange::Poloniex(Api::Legacy) => Ok(self.read_legacy(&msg.to_string())),
+            Exchange::Poloniex(Api::V3) => self.read_v3(&msg.to_string()),
+            Exchange::Binance => Ok(self.read_binance(&msg.to_string())),
+            Exchange::Kraken => self.read_kraken(&msg.to_string()),
+        }
+    }
+
+    /// Events of the market of a symbol, `Other` if the symbol isn't known
+    fn events(&self, symbol: &str, events: Vec<BookEvent>) -> StreamMessage {
+        match self.exchange.parse_symbol(symbol) {
+            Some((base, quote)) => StreamMessage::Events {
+                base,
+                quote,
+                events,
+            },
+            None => StreamMessage::Other,
+        }
+    }
+
+    fn read_legacy(&mut self, text: &str) -> StreamMessage {
+        match parse_message(text) {
+            Ok(PushMessage::Update {
+                channel_id, events, ..
+            }) => {
+                for event in events.iter() {
+                    if let BookEvent::Snapshot { currency_pair, .. } = event {
+                        if let Some(market) = self.exchange.parse_symbol(currency_pair) {
+                            self.channels.insert(channel_id, market);
+                        }
+                    }
+                }
+
+                match self.channels.get(&channel_id) {
+                    Some((base, quote)) => StreamMessage::Events {
+                        base: base.clone(),
+                        quote: quote.clone(),
+                        events,
+                    },
+                    None => StreamMessage::Skipped(format!(
+                        "update before snapshot on channel {}",
+                        channel_id
+                    )),
+                }
+            }
+            Ok(PushMessage::Error(message)) => StreamMessage::Error(message),
+            Ok(_) => StreamMessage::Other,
+            Err(err) => StreamMessage::Skipped(err.to_string()),
+        }
+    }
+
+    fn read_v3(&mut self, text: &str) -> Result<StreamMessage, BotError> {
+        let message = match parse_ws_message(text) {
+            Ok(message) => message,
+            Err(err) => return Ok(StreamMessage::Skipped(err.to_string())),
+        };
+
+        // messages carry lists, in practice of a single symbol
+        let (symbol, events) = match message {
+            WsMessage::Book { snapshot, data } => {
+                let mut symbol = None;
+                let mut events = vec![];
+                for book in data {
+                    symbol = Some(book.symbol.clone());
+                    let book_symbol = book.symbol.clone();
+                    match self.books.apply(snapshot, book) {
+                        Ok(book_events) => events.extend(book_events),
+                        Err(err) => {
+                            // a new snapshot comes after subscribing again
+                            self.resubscribe_book(&book_symbol)?;
+                            return Ok(StreamMessage::Skipped(err.to_string()));
+                        }
+                    }
+                }
+                (symbol, events)
+            }
+            WsMessage::Trades(data) => (
+                data.first().map(|t| t.symbol.clone()),
+                data.into_iter()
+                    .map(|t| BookEvent::Trade(poloniex_v3::to_public_trade(t)))
+                    .collect(),
+            ),
+            WsMessage::Candles { period, data } => {
+                let symbol = data.first().map(|c| c.symbol.clone());
+                let mut events = vec![];
+                for c in data {
+                    match poloniex_v3::candle_data_to_candle(period, c) {
+                        Ok(candle) => events.push(BookEvent::Candle(candle)),
+                        Err(err) => return Ok(StreamMessage::Skipped(err.to_string())),
+                    }
+                }
+                (symbol, events)
+            }
+            WsMessage::Error(message) => return Ok(StreamMessage::Error(message)),
+            WsMessage::Event(_) => return Ok(StreamMessage::Other),
+        };
+
+        match symbol {
+            Some(symbol) => Ok(self.events(&symbol, events)),
+            None => Ok(StreamMessage::Other),
+        }
+    }
+
+    fn read_binance(&mut self, text: &str) -> StreamMessage {
+        let message = match binance::parse_ws_message(text) {
+            Ok(message) => message,
+            Err(err) => return StreamMessage::Skipped(err.to_string()),
+        };
+
+        match message {
+            binance::WsMessage::Depth(update) => {
+                let symbol = update.symbol.clone();
+                let mut events = vec![];
+
+                // the snapshot is fetched once updates are coming, so that
+                // the first update after it isn't missed
+                if !self.depths.has_book(&symbol) {
+                    let snapshot = match self.fetcher.as_ref() {
+                        Some(fetcher) => binance::return_depth(fetcher, &symbol),
+                        None => Err(BotError::Config("no fetcher for snapshots".to_string())),
+                    };
+                    match snapshot.and_then(|s| self.depths.insert_snapshot(&symbol, &s)) {
+                        Ok(event) => events.push(event),
+                        Err(err) => return StreamMessage::Skipped(err.to_string()),
+                    }
+                }
+
+                match self.depths.apply(update) {
+                    Ok(book_events) => events.extend(book_events),
+                    Err(err) => return StreamMessage::Skipped(err.to_string()),
+                }
+                self.events(&symbol, events)
+            }
+            binance::WsMessage::Trade(trade) => {
+                let symbol = trade.symbol.clone();
+                self.events(
+                    &symbol,
+                    vec![BookEvent::Trade(binance::to_public_trade(trade))],
+                )
+            }
+            binance::WsMessage::Error(message) => StreamMessage::Error(message),
+            binance::WsMessage::Response(_) => StreamMessage::Other,
+        }
+    }
+
+    fn read_kraken(&mut self, text: &str) -> Result<StreamMessage, BotError> {
+        let message = match kraken::parse_ws_message(text) {
+            Ok(message) => message,
+            Err(err) => return Ok(StreamMessage::Skipped(err.to_string())),
+        };
+
+        match message {
+            kraken::WsMessage::Book(data) => {
+                let pair = data.pair.clone();
+                match self.kraken_books.apply(data) {
+                    Ok(events) => Ok(self.events(&pair, events)),
+                    Err(err) => {
+                        self.resubscribe_kraken_book(&pair)?;
+                        Ok(StreamMessage::Skipped(err.to_string()))
+                    }
+                }
+            }
+            kraken::WsMessage::Trades { pair, data } => {
+                let mut events = vec![];
+                for t in data {
+                    match kraken::to_public_trade(t) {
+                        Ok(trade) => events.push(BookEvent::Trade(trade)),
+                        Err(err) => return Ok(StreamMessage::Skipped(err.to_string())),
+                    }
+                }
+                Ok(self.events(&pair, events))
+            }
+            kraken::WsMessage::Error(message) => Ok(StreamMessage::Error(message)),
+            kraken::WsMessage::Event(_) => Ok(StreamMessage::Other),
+        }
+    }
+
+    fn resubscribe_book(&mut self, symbol: &str) -> Result<(), BotError> {
+        let channels = [poloniex_v3::BOOK_CHANNEL.to_string()];
+        let symbols = [symbol.to_string()];
+
+        self.send(serde_json::to_string(&WsCommand::new(
+            "unsubscribe",
+            &channels,
+            &symbols,
+        ))?)?;
+        self.send(serde_json::to_string(&WsCommand::new(
+            "subscribe",
+            &channels,
+            &symbols,
+        ))?)
+    }
+
+    fn resubscribe_kraken_book(&mut self, pair: &str) -> Result<(), BotError> {
+        let pairs = [pair.to_string()];
+
+        for event in ["unsubscribe", "subscribe"] {
+            self.send(serde_json::to_string(&kraken::WsRequest::new(
+                event,
+                kraken::BOOK_CHANNEL,
+                &pairs,
+            ))?)?;
+        }
+        Ok(())
+    }
+}
@@ -1,50 +1,218 @@
 extern crate diesel;
 
-use serde::{Deserialize, Serialize};
+use crate::api::{legacy_rest_url, Api};
+use crate::decimal::decimal;
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
+use crate::fetcher::Fetcher;
+use crate::models::{Market, TickerSnapshot};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 
-const API_URL: &str = "https://poloniex.com/public";
+// the legacy API doesn't report precisions or order limits, these are the
+// same for all its markets
+pub const PRICE_PRECISION: i32 = 8;
+pub const AMOUNT_PRECISION: i32 = 8;
 
-#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+// minimum order total in base currency
+pub const MIN_ORDER: f64 = 1.0;
+
+// ticker snapshots are kept for this many seconds
+pub const TICKER_RETENTION: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct PoloniexTicker {
     pub id: i32,
+    pub last: BigDecimal,
+    #[serde(rename = "lowestAsk")]
+    pub lowest_ask: BigDecimal,
+    #[serde(rename = "highestBid")]
+    pub highest_bid: BigDecimal,
+    #[serde(
+        rename = "percentChange",
+        deserialize_with = "deserialize_f32_from_str"
+    )]
+    pub percent_change: f32,
+    #[serde(rename = "baseVolume")]
+    pub base_volume: BigDecimal,
+    #[serde(rename = "quoteVolume")]
+    pub quote_volume: BigDecimal,
+    #[serde(rename = "isFrozen", default, deserialize_with = "deserialize_flag")]
+    pub is_frozen: bool,
+    #[serde(rename = "postOnly", default, deserialize_with = "deserialize_flag")]
+    pub post_only: bool,
+    #[serde(rename = "high24hr")]
+    pub high_24hr: BigDecimal,
+    #[serde(rename = "low24hr")]
+    pub low_24hr: BigDecimal,
+}
+
+fn deserialize_f32_from_str<'de, D>(deserializer: D) -> Result<f32, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse::<f32>().map_err(serde::de::Error::custom)
+}
+
+/// Flags are sent as "0" and "1"
+fn deserialize_flag<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Ok(s == "1")
 }
 
+/// Ticker of a currency pair
 #[derive(Clone, Debug, Serialize, Deserialize)]
-struct Ticker {
+pub struct Ticker {
     pub base: String,
     pub quote: String,
+    pub ticker: PoloniexTicker,
 }
 
-pub fn return_ticker(base: String) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-    let client = reqwest::blocking::Client::new();
-
+/// Fetches the tickers of all markets of `base`
+pub fn return_tickers(fetcher: &Fetcher, base: String) -> Result<Vec<Ticker>, BotError> {
     println!("Fetching tickers");
 
-    let ret: HashMap<String, PoloniexTicker> = client
-        .get(API_URL)
-        .query(&[("command", "returnTicker")])
-        .send()?
-        .json::<HashMap<String, PoloniexTicker>>()?;
+    let ret: HashMap<String, serde_json::Value> = fetcher
+        .get(
+            &legacy_rest_url(),
+            &[("command", "returnTicker")],
+            "fetching tickers",
+        )?
+        .json::<HashMap<String, serde_json::Value>>()
+        .context("reading tickers")?;
 
-    let mut tickers: Vec<Ticker> = vec![];
+    Ok(parse_tickers(ret, &base))
+}
 
-    for key in ret.into_keys() {
-        let mut splitted = key.split("_");
-        let base = splitted.next().unwrap().to_string();
-        let quote = splitted.next().unwrap().to_string();
+/// Tickers of the currency pairs of `base`, a ticker that can't be parsed
+/// is skipped so that it doesn't leave out the other markets
+fn parse_tickers(ret: HashMap<String, serde_json::Value>, base: &str) -> Vec<Ticker> {
+    let mut tickers: Vec<Ticker> = vec![];
 
-        tickers.push(Ticker {
-            base: base,
-            quote: quote,
-        })
+    for (key, value) in ret.into_iter() {
+        // skip keys that aren't currency pairs
+        if let Some((b, quote)) = Exchange::Poloniex(Api::Legacy).parse_symbol(&key) {
+            if b == base {
+                match serde_json::from_value::<PoloniexTicker>(value) {
+                    Ok(ticker) => tickers.push(Ticker {
+                        base: b,
+                        quote,
+                        ticker,
+                    }),
+                    Err(e) => println!("skipping ticker {}: {}", key, e),
+                }
+            }
+        }
     }
 
-    let quotes: Vec<String> = tickers
+    tickers
+}
+
+pub fn return_ticker(fetcher: &Fetcher, base: String) -> Result<Vec<String>, BotError> {
+    let quotes: Vec<String> = return_tickers(fetcher, base)?
         .into_iter()
-        .filter(|t| t.base == base)
         .map(|t| t.quote)
         .collect();
 
     Ok(quotes)
 }
+
+/// Market with the trading status of the ticker
+pub fn ticker_to_market(t: &Ticker) -> Result<Market, BotError> {
+    Ok(Market {
+        base: t.base.clone(),
+        quote: t.quote.clone(),
+        price_precision: PRICE_PRECISION,
+        amount_precision: AMOUNT_PRECISION,
+        min_order: decimal(MIN_ORDER)?,
+        is_frozen: t.ticker.is_frozen,
+        post_only: t.ticker.post_only,
+        updated_at: Utc::now(),
+        exchange: Exchange::Poloniex(Api::Legacy).name().to_string(),
+    })
+}
+
+/// Snapshot of the ticker at `timestamp`
+pub fn ticker_to_snapshot(t: &Ticker, timestamp: DateTime<Utc>) -> TickerSnapshot {
+    TickerSnapshot {
+        base: t.base.clone(),
+        quote: t.quote.clone(),
+        timestamp,
+        last: t.ticker.last.clone(),
+        lowest_ask: t.ticker.lowest_ask.clone(),
+        highest_bid: t.ticker.highest_bid.clone(),
+        percent_change: t.ticker.percent_change,
+        base_volume: t.ticker.base_volume.clone(),
+        quote_volume: t.ticker.quote_volume.clone(),
+        high_24hr: t.ticker.high_24hr.clone(),
+        low_24hr: t.ticker.low_24hr.clone(),
+        exchange: Exchange::Poloniex(Api::Legacy).name().to_string(),
+    }
+}
+
+/// Stores ticker snapshots, and deletes the ones older than
+/// `TICKER_RETENTION`
+pub fn insert_ticker_snapshots(
+    connection: &mut PgConnection,
+    snapshots: &[TickerSnapshot],
+) -> Result<usize, BotError> {
+    use crate::schema::ticker_snapshots::dsl::*;
+
+    println!("inserting ticker snapshots");
+
+    diesel::delete(
+        ticker_snapshots.filter(timestamp.lt(Utc::now() - Duration::seconds(TICKER_RETENTION))),
+    )
+    .execute(connection)
+    .context("deleting old ticker snapshots")?;
+
+    diesel::insert_into(ticker_snapshots)
+        .values(snapshots)
+        .execute(connection)
+        .context("inserting ticker snapshots")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_tickers_test() {
+        let input = r#"{
+          "USDT_LTC": {"id": 123, "last": "64.52000000", "lowestAsk": "64.55000000",
+            "highestBid": "64.51000000", "percentChange": "-0.01234567",
+            "baseVolume": "251234.12345678", "quoteVolume": "3912.87654321",
+            "isFrozen": "0", "postOnly": "1", "high24hr": "66.00000000", "low24hr": "63.90000000"},
+          "BTC_LTC": {"id": 50, "last": "0.00271000", "lowestAsk": "0.00271100",
+            "highestBid": "0.00270900", "percentChange": "0.00100000",
+            "baseVolume": "12.5", "quoteVolume": "4600.0",
+            "isFrozen": "0", "postOnly": "0", "high24hr": "0.0028", "low24hr": "0.0026"},
+          "USDT_XMR": {"id": 114, "last": "150.1", "lowestAsk": "150.2",
+            "highestBid": "150.0", "percentChange": "0.01"}
+        }"#;
+
+        // BTC_LTC is of another base and USDT_XMR misses fields
+        let tickers = parse_tickers(serde_json::from_str(input).unwrap(), "USDT");
+        assert_eq!(tickers.len(), 1);
+
+        let market = ticker_to_market(&tickers[0]).unwrap();
+        assert_eq!(market.quote, "LTC");
+        assert!(!market.is_frozen);
+        assert!(market.post_only);
+
+        let snapshot = ticker_to_snapshot(&tickers[0], Utc::now());
+        assert_eq!(snapshot.percent_change, -0.01234567);
+        assert_eq!(
+            snapshot.base_volume,
+            BigDecimal::from_str("251234.12345678").unwrap()
+        );
+    }
+}
@@ -1,30 +1,38 @@
 extern crate diesel;
 
+use super::decimal::{decimal, to_f64, DECIMAL_SCALE};
 use super::diesel::prelude::*;
+use super::error::{BotError, Context};
+use super::exchange::Exchange;
 use super::models::*;
+use super::risk::TRADE_SIZE;
 use super::BASE;
+use bigdecimal::{BigDecimal, Zero};
 use chrono::Utc;
 
 /// Creates a trade based on shortlist entry
 pub fn create_trade(
     connection: &mut PgConnection,
     shortlist: &Shortlist,
-) -> Result<Trade, Box<dyn std::error::Error>> {
+) -> Result<Trade, BotError> {
     use super::schema::trades;
 
     let new_trade = NewTrade {
         base: BASE.to_string(),
         quote: shortlist.quote.clone(),
-        target: shortlist.target,
-        open_average: shortlist.average,
+        target: shortlist.target.clone(),
+        open_average: shortlist.average.clone(),
         open_at: Utc::now(),
         updated_at: Utc::now(),
+        stop_loss: shortlist.stop_loss,
+        size: decimal(TRADE_SIZE)?,
+        exchange: shortlist.exchange.clone(),
     };
 
     let trade = diesel::insert_into(trades::table)
         .values(&new_trade)
         .get_result::<Trade>(connection)
-        .unwrap();
+        .context("creating trade")?;
 
     Ok(trade)
 }
@@ -33,29 +41,186 @@ pub fn create_trade(
 pub fn is_trade_open(
     connection: &mut PgConnection,
     shortlist: &Shortlist,
-) -> Result<bool, Box<dyn std::error::Error>> {
+) -> Result<bool, BotError> {
     use super::schema::trades::dsl::*;
 
     let rows = trades
+        .filter(exchange.eq(&shortlist.exchange))
         .filter(base.eq(BASE))
         .filter(quote.eq(shortlist.quote.clone()))
         .filter(close_at.is_null())
         .limit(1)
-        .load::<Trade>(connection)
-        .unwrap();
+        .load::<Trade>(connection)?;
 
-    Ok(rows.len() > 0)
+    Ok(!rows.is_empty())
 }
 
-/// Gets all open trades
-pub fn get_trades(connection: &mut PgConnection) -> Result<Vec<Trade>, Box<dyn std::error::Error>> {
+/// Gets all open trades in `exchange_p`
+pub fn get_trades(
+    connection: &mut PgConnection,
+    exchange_p: Exchange,
+) -> Result<Vec<Trade>, BotError> {
     use super::schema::trades::dsl::*;
 
     let rows = trades
+        .filter(exchange.eq(exchange_p.name()))
         .filter(base.eq(BASE))
         .filter(close_at.is_null())
-        .load::<Trade>(connection)
-        .unwrap();
+        .load::<Trade>(connection)?;
+
+    Ok(rows)
+}
+
+/// Records a (partial) exit of the trade position
+///
+/// `portion` is the share of the original position sold in this execution.
+pub fn create_execution(
+    connection: &mut PgConnection,
+    trade: &Trade,
+    price: BigDecimal,
+    portion: BigDecimal,
+    reason: &str,
+) -> Result<Execution, BotError> {
+    use super::schema::executions;
+
+    let new_execution = NewExecution {
+        trade_id: trade.id,
+        executed_at: Utc::now(),
+        price,
+        portion,
+        reason: reason.to_string(),
+    };
+
+    let execution = diesel::insert_into(executions::table)
+        .values(&new_execution)
+        .get_result::<Execution>(connection)
+        .context("creating execution")?;
+
+    Ok(execution)
+}
+
+/// Gets all executions of a trade
+pub fn get_executions(
+    connection: &mut PgConnection,
+    trade: &Trade,
+) -> Result<Vec<Execution>, BotError> {
+    use super::schema::executions::dsl::*;
+
+    let rows = Execution::belonging_to(trade)
+        .order(executed_at.asc())
+        .load::<Execution>(connection)?;
 
     Ok(rows)
 }
+
+/// Average exit price of the executions, weighted by the sold portions
+pub fn get_exit_price(executions: &[Execution]) -> Option<BigDecimal> {
+    let portion: BigDecimal = executions.iter().map(|e| &e.portion).sum();
+    if portion <= BigDecimal::zero() {
+        return None;
+    }
+    let value: BigDecimal = executions.iter().map(|e| &e.price * &e.portion).sum();
+    Some((value / portion).round(DECIMAL_SCALE))
+}
+
+/// Price the trade was opened at, an error for a trade that hasn't started
+pub fn get_open_price(trade: &Trade) -> Result<&BigDecimal, BotError> {
+    trade.open.as_ref().ok_or_else(|| BotError::Database {
+        message: format!("trade {} has no open price", trade.id),
+        retryable: false,
+    })
+}
+
+/// Relative profit of the trade over all its exits, e.g. 0.01 for +1%
+pub fn get_trade_profit(trade: &Trade, executions: &[Execution]) -> Option<f32> {
+    match (&trade.open, get_exit_price(executions)) {
+        (Some(open_price), Some(exit_price)) => {
+            Some((to_f64(&(exit_price / open_price)) - 1.0) as f32)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    pub(crate) fn trade(open: Option<&str>) -> Trade {
+        Trade {
+            id: 1,
+            base: BASE.to_string(),
+            quote: "LTC".to_string(),
+            open_at: Utc::now(),
+            close_at: None,
+            updated_at: Utc::now(),
+            open_average: d("100"),
+            target: d("98"),
+            open: open.map(d),
+            close: None,
+            highest_bid: None,
+            stop_loss: 0.02,
+            remaining: d("1"),
+            take_profit_level: 0,
+            profit: None,
+            close_reason: None,
+            size: d("10"),
+            entry_bid: None,
+            entry_bid_at: None,
+            entry_bids: 0,
+            entry_type: None,
+            exit_reason: None,
+            exit_started_at: None,
+            exit_slices: 0,
+            exchange: "poloniex".to_string(),
+        }
+    }
+
+    fn execution(price: &str, portion: &str) -> Execution {
+        Execution {
+            id: 1,
+            trade_id: 1,
+            executed_at: Utc::now(),
+            price: d(price),
+            portion: d(portion),
+            reason: "take_profit".to_string(),
+        }
+    }
+
+    #[test]
+    fn exit_price_test() {
+        assert_eq!(get_exit_price(&[]), None);
+        assert_eq!(get_exit_price(&[execution("105", "0")]), None);
+        assert_eq!(get_exit_price(&[execution("105", "0.3")]), Some(d("105")));
+
+        // weighted by the portion sold in each execution
+        let executions = [
+            execution("102", "0.3"),
+            execution("104", "0.3"),
+            execution("99", "0.4"),
+        ];
+        assert_eq!(get_exit_price(&executions), Some(d("101.4")));
+
+        // a partially exited trade is priced over what has been sold so far
+        assert_eq!(get_exit_price(&executions[..2]), Some(d("103")));
+    }
+
+    #[test]
+    fn trade_profit_test() {
+        let executions = [execution("102", "0.3"), execution("99", "0.7")];
+
+        let profit = get_trade_profit(&trade(Some("100")), &executions).unwrap();
+        assert!((profit + 0.001).abs() < 1e-6);
+
+        let profit = get_trade_profit(&trade(Some("100")), &executions[..1]).unwrap();
+        assert!((profit - 0.02).abs() < 1e-6);
+
+        assert_eq!(get_trade_profit(&trade(Some("100")), &[]), None);
+        assert_eq!(get_trade_profit(&trade(None), &executions), None);
+        assert!(get_open_price(&trade(None)).is_err());
+    }
+}
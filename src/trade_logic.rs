@@ -1,22 +1,113 @@
-use chrono::Utc;
-use serde_json::Value;
-use tungstenite::{connect, Message};
-use url::Url;
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Duration, Utc};
 
 use crate::diesel::prelude::*;
 use crate::models::*;
 
+use crate::candle_builder::{insert_market_trade, to_market_trade};
+use crate::cooldown::register_trade_result;
+use crate::decimal::{decimal, to_f64, DECIMAL_SCALE};
+use crate::error::{BotError, Context};
+use crate::exchange::Exchange;
+use crate::market::get_market;
+use crate::message::*;
 use crate::order_book::*;
+use crate::stream::{Channel, MarketStream, StreamMessage};
+use crate::trade::{
+    create_execution, get_executions, get_exit_price, get_open_price, get_trade_profit,
+};
 
-const API_URL: &str = "wss://api2.poloniex.com";
-
-// allow trade to drop by this amount before closing
-// also the start decisions are based on this
-pub const STOP_LOSS: f64 = 0.005;
+// allow trade to drop by this amount before closing, the amount is
+// chosen per trade from recent volatility (volatility_med of the candles)
+// multiplied by STOP_LOSS_MULTIPLIER, and kept between STOP_LOSS_MIN and
+// STOP_LOSS_MAX (reached below the MAX_VOLATILITY of the shortlist)
+pub const STOP_LOSS_MULTIPLIER: f64 = 1.5;
+pub const STOP_LOSS_MIN: f64 = 0.005;
+pub const STOP_LOSS_MAX: f64 = 0.02;
 
 // start trade if lowest ask is this much above target at maximum
 pub const START_ABOVE_TARGET: f64 = 0.015;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryMode {
+    // buy immediately at the lowest ask
+    Taker,
+    // post a bid inside the spread and wait for the order book to move
+    // through it, take the lowest ask if it doesn't fill after repricing
+    Maker,
+}
+
+pub const ENTRY_MODE: EntryMode = EntryMode::Maker;
+
+// maker entry bid is placed this far into the spread from the highest bid,
+// 0.0 is at the highest bid and 1.0 at the lowest ask
+pub const ENTRY_IMPROVEMENT: f64 = 0.25;
+
+// reprice the maker entry bid if it hasn't filled in this many seconds
+pub const ENTRY_REPRICE_TIME: i64 = 60;
+
+// take the lowest ask if the entry bid hasn't filled after this many reprices
+pub const ENTRY_MAX_REPRICES: i32 = 3;
+
+// take profit ladder: when highest bid rises this much above the open price,
+// sell this portion of the original position, the rest is left for the
+// trailing stop (portions must add up to less than 1)
+pub const TAKE_PROFIT_LEVELS: &[(f64, f64)] = &[(0.02, 0.3), (0.04, 0.3)];
+
+const _: () = {
+    let mut sum = 0.0;
+    let mut i = 0;
+    while i < TAKE_PROFIT_LEVELS.len() {
+        sum += TAKE_PROFIT_LEVELS[i].1;
+        i += 1;
+    }
+    assert!(sum < 1.0);
+};
+
+// abandon a trade that hasn't started in this many seconds
+pub const MAX_PENDING_TIME: i64 = 30 * 60;
+
+// close a trade after holding it this many seconds
+pub const MAX_HOLDING_TIME: i64 = 48 * 60 * 60;
+
+// close a trade if the expected exit price isn't at least MIN_PROGRESS above
+// the open price after holding it MIN_PROGRESS_TIME seconds
+pub const MIN_PROGRESS_TIME: i64 = 12 * 60 * 60;
+pub const MIN_PROGRESS: f64 = 0.005;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitAlgorithm {
+    // sell the whole position at the highest bid
+    Immediate,
+    // sell to the bid levels from the highest down until MAX_EXIT_IMPACT,
+    // the rest is sold on the next order book updates
+    Book,
+    // split the position to TWAP_SLICES slices sold TWAP_INTERVAL seconds
+    // apart, each slice is sold like in Book
+    Twap,
+}
+
+pub const EXIT_ALGORITHM: ExitAlgorithm = ExitAlgorithm::Book;
+
+// when closing, don't sell to bids more than this below the highest bid
+pub const MAX_EXIT_IMPACT: f64 = 0.005;
+
+// sell to any bid level if closing has taken this many seconds
+pub const MAX_EXIT_TIME: i64 = 10 * 60;
+
+pub const TWAP_SLICES: i32 = 5;
+pub const TWAP_INTERVAL: i64 = 30;
+
+// position portion left after selling is rounded to zero below this
+pub const MIN_REMAINING: f64 = 1e-4;
+
+// order book depth is measured within this range of the middle price
+pub const DEPTH_RANGE: f64 = 0.01;
+
+// don't start a trade unless the asks within DEPTH_RANGE add up to this
+// many times the position size
+pub const MIN_DEPTH: f64 = 3.0;
+
 // when updating trades, increase target at least by this amount
 pub const CONSTANT_RISE: f64 = 0.0025;
 
@@ -24,69 +115,103 @@ pub const CONSTANT_RISE: f64 = 0.0025;
 // spread (higest bid - lowest ask) is less than this
 pub const MAX_SPREAD: f64 = 0.0025;
 
-pub fn do_trade(
-    connection: &mut PgConnection,
-    trade_id: i32,
-) -> Result<(), Box<dyn std::error::Error>> {
-    use crate::schema::trades::dsl::*;
-    let (mut socket, _response) = connect(Url::parse(API_URL).unwrap()).expect("Can't connect");
+pub fn do_trade(connection: &mut PgConnection, trade_id: i32) -> Result<(), BotError> {
+    use crate::schema::trades::dsl::trades;
+    let exchange = Exchange::from_env()?;
+    let mut stream = MarketStream::connect(exchange)?;
 
-    // fetch trade by id
-    let trade: Trade = trades.find(trade_id).first(connection).unwrap();
+    // fetch trade by id, the entry bid state is kept up to date in it until
+    // the trade starts
+    let mut trade: Trade = trades
+        .find(trade_id)
+        .first(connection)
+        .context(&format!("loading trade {}", trade_id))?;
 
-    let subscribe_command = Command {
-        command: "subscribe".to_string(),
-        channel: format!("USDT_{}", trade.quote).to_string(),
-    };
+    // the order book of another exchange would say nothing of the trade
+    if trade.exchange != exchange.name() {
+        return Err(BotError::Config(format!(
+            "trade {} is on {}, not {}",
+            trade.id,
+            trade.exchange,
+            exchange.name()
+        )));
+    }
 
-    socket
-        .write_message(Message::Text(
-            serde_json::to_string(&subscribe_command).unwrap(),
-        ))
-        .unwrap();
+    // market status is read once, select_trade restarts the trade processes
+    // periodically
+    let market: Market =
+        get_market(connection, exchange, &trade.base, &trade.quote)?.ok_or_else(|| {
+            BotError::Config(format!(
+                "market {}_{} not found, fetch markets first",
+                trade.base, trade.quote
+            ))
+        })?;
+
+    stream.subscribe(&trade.base, &trade.quote, &[Channel::Book, Channel::Trades])?;
 
-    let mut channel_id: Option<u32> = None;
     let mut order_book: Option<OrderBook> = None;
-    let mut buy_value: Option<f32> = trade.open;
-    let mut prev_highest_bid: Option<f64> = trade.highest_bid.map(|x| x as f64);
+    let mut buy_value: Option<BigDecimal> = trade.open.clone();
+    let mut prev_highest_bid: Option<BigDecimal> = trade.highest_bid.clone();
     let mut continue_trade: bool = true;
 
     loop {
-        let msg_s = socket.read_message().expect("Error reading message");
-        let parsed = parse_message(msg_s.to_string());
-
-        if channel_id == None && parsed.channel_id != HEARTBEAT_ID {
-            channel_id = Some(parsed.channel_id);
-        }
-
-        if channel_id == Some(parsed.channel_id) {
-            for msg in parsed.messages.into_iter() {
-                let ret = do_message(
-                    connection,
-                    &trade,
-                    msg,
-                    order_book,
-                    buy_value,
-                    prev_highest_bid,
-                )?;
-                continue_trade = ret.0;
-                order_book = ret.1;
-                buy_value = ret.2;
-                prev_highest_bid = ret.3;
+        // unexpected messages are skipped so that they don't end an open trade
+        match stream.read()? {
+            StreamMessage::Events {
+                quote: events_quote,
+                events,
+                ..
+            } => {
+                if events_quote == trade.quote {
+                    for event in events.into_iter() {
+                        let ret = do_message(
+                            connection,
+                            &mut trade,
+                            &market,
+                            event,
+                            order_book,
+                            buy_value,
+                            prev_highest_bid,
+                        )?;
+                        continue_trade = ret.0;
+                        order_book = ret.1;
+                        buy_value = ret.2;
+                        prev_highest_bid = ret.3;
 
-                // don't overwrite continue_trade with possible other messages in the
-                // same batch
-                if !continue_trade {
-                    break;
+                        // don't overwrite continue_trade with possible other messages in the
+                        // same batch
+                        if !continue_trade {
+                            break;
+                        }
+                    }
+                }
+            }
+            StreamMessage::Error(message) => {
+                log_trade(&trade, format!("error from exchange: {}", message))
+            }
+            // heartbeats and read timeouts keep selling the TWAP slices when
+            // the highest bid doesn't change
+            StreamMessage::Other => {
+                if let (Some(ob), Some(_)) = (order_book.as_mut(), &buy_value) {
+                    continue_trade = continue_twap(connection, &trade, &market, ob)?;
                 }
             }
+            StreamMessage::Skipped(reason) => {
+                log_trade(&trade, format!("skipping message: {}", reason))
+            }
+        }
+        // checked on every message including heartbeats, as a trade waiting
+        // for the spread to narrow may not get any order book updates
+        if continue_trade && buy_value.is_none() && is_entry_expired(&trade) {
+            expire_trade(connection, &trade)?;
+            break Ok(());
         }
-        if !continue_trade && order_book == None {
+        if !continue_trade && order_book.is_none() {
             // delete the trade that was never started
             use crate::schema::trades::dsl::*;
             diesel::delete(trades.filter(id.eq(trade.id)))
                 .execute(connection)
-                .unwrap();
+                .context("deleting trade that wasn't started")?;
         }
         if !continue_trade {
             break Ok(());
@@ -98,68 +223,152 @@ pub fn do_trade(
 fn do_buy(
     connection: &mut PgConnection,
     trade: &Trade,
-    lowest_ask: f32,
-) -> Result<Trade, Box<diesel::result::Error>> {
+    price: BigDecimal,
+    entry: &str,
+) -> Result<Trade, BotError> {
     use crate::schema::trades::dsl::*;
 
     // the previous target comes from candles and is not that
     // real-time, set it based on stoploss and start to rise
     // from there
-    let new_target: f32 = lowest_ask * (1.0 - STOP_LOSS as f32);
+    let new_target = &price * (BigDecimal::from(1) - decimal(trade.stop_loss)?);
 
     diesel::update(trade)
         .set((
             open_at.eq(Utc::now()),
-            open.eq(Some(lowest_ask)),
+            open.eq(Some(price)),
             target.eq(new_target),
+            entry_type.eq(Some(entry)),
         ))
         .get_result(connection)
-        .map_err(|e| Box::new(e))
+        .context("starting trade")
 }
 
 fn check_sell(
     connection: &mut PgConnection,
     trade: &Trade,
+    market: &Market,
+    order_book: &mut OrderBook,
     highest_bid_ob: OrderBookEntry,
     lowest_ask_ob: OrderBookEntry,
-) -> Result<bool, Box<dyn std::error::Error>> {
+) -> Result<bool, BotError> {
     use crate::schema::trades::dsl::*;
 
+    // selling to the bids isn't possible when the market doesn't take orders
+    if market.is_frozen || market.post_only {
+        log_trade(trade, "market not taking orders, not selling".to_string());
+        return Ok(true);
+    }
+
     let current_trade: Trade = trades.find(trade.id).first(connection)?;
 
-    let tgt: f32 = current_trade.target;
-    let cur: f32 = highest_bid_ob.price as f32;
-    let spread: f64 = (lowest_ask_ob.price - highest_bid_ob.price) / lowest_ask_ob.price;
+    // keep on selling if closing the trade has been started already
+    if let Some(reason) = current_trade.exit_reason.clone() {
+        return continue_exit(
+            connection,
+            &current_trade,
+            market,
+            order_book,
+            &highest_bid_ob.price,
+            &reason,
+        );
+    }
+
+    let tgt: BigDecimal = current_trade.target.clone();
+    let cur_open: BigDecimal = get_open_price(&current_trade)?.clone();
+    // expected price for selling the whole remaining position, or the
+    // highest bid if the bids aren't deep enough to tell
+    let amount = &current_trade.size * &current_trade.remaining / &cur_open;
+    let cur: BigDecimal = order_book
+        .fill_price(OrderType::Bid, &amount)
+        .unwrap_or_else(|| highest_bid_ob.price.clone());
+    let spread: f64 =
+        to_f64(&((&lowest_ask_ob.price - &highest_bid_ob.price) / &lowest_ask_ob.price));
+
+    // close trade if expected exit price is below target, or if the trade has
+    // been held for too long
+    let close_reason_s = if cur < tgt {
+        Some("stop_loss")
+    } else {
+        check_time_exit(&current_trade, &cur)?
+    };
 
-    // close trade if current bid is below target
-    if cur < tgt {
+    if let Some(reason) = close_reason_s {
         // if the order book has too high spread, don't hurry to sell
         if spread > MAX_SPREAD {
             log_trade(trade, format!("spread too high, not selling, {}", spread));
             return Ok(true);
         }
 
-        let cur_open: f32 = current_trade.open.unwrap();
+        let exiting_trade: Trade = diesel::update(trade)
+            .set((
+                exit_reason.eq(Some(reason)),
+                exit_started_at.eq(Some(Utc::now())),
+            ))
+            .get_result(connection)?;
 
+        return continue_exit(
+            connection,
+            &exiting_trade,
+            market,
+            order_book,
+            &highest_bid_ob.price,
+            reason,
+        );
+    }
+
+    // sell part of the position for each take profit level reached
+    let mut new_level = current_trade.take_profit_level;
+    let mut new_remaining = current_trade.remaining.clone();
+    let position = (&current_trade.size / &cur_open).round(DECIMAL_SCALE);
+
+    while let Some(&(rise, portion)) = TAKE_PROFIT_LEVELS.get(new_level as usize) {
+        if cur < &cur_open * (BigDecimal::from(1) + decimal(rise)?) {
+            break;
+        }
+        if spread > MAX_SPREAD {
+            log_trade(
+                trade,
+                format!("spread too high, not taking profit, {}", spread),
+            );
+            break;
+        }
+
+        let amount = market.round_amount(&(&position * decimal(portion)?));
+        if !market.is_order_valid(&cur, &amount) {
+            log_trade(
+                &current_trade,
+                format!(
+                    "take profit level {} below minimum order, left for the exit",
+                    new_level
+                ),
+            );
+            new_level += 1;
+            continue;
+        }
+
+        let sold = (&amount / &position).round(DECIMAL_SCALE);
+        create_execution(
+            connection,
+            &current_trade,
+            cur.clone(),
+            sold.clone(),
+            "take_profit",
+        )?;
         log_trade(
             &current_trade,
             format!(
-                "closing trade, close: {:?}, open: {:?}: {:.3}%",
-                cur,
-                cur_open,
-                cur / cur_open
+                "taking profit at level {}, sold {} at {}, open: {}",
+                new_level, sold, cur, cur_open
             ),
         );
 
-        diesel::update(trade)
-            .set((close_at.eq(Utc::now()), close.eq(Some(cur))))
-            .execute(connection)?;
-
-        return Ok(false);
+        new_level += 1;
+        new_remaining -= sold;
     }
 
-    // update target if current bid is more than stop loss above target
-    let take_profit_tgt = cur * (1.0 - STOP_LOSS) as f32;
+    // update target if expected exit price is more than stop loss above target
+    let take_profit_tgt = &cur * (BigDecimal::from(1) - decimal(current_trade.stop_loss)?);
     let new_target = if take_profit_tgt > tgt {
         take_profit_tgt
     } else {
@@ -171,21 +380,324 @@ fn check_sell(
     diesel::update(trade)
         .set((
             updated_at.eq(Utc::now()),
-            highest_bid.eq(Some(cur)),
+            highest_bid.eq(Some(highest_bid_ob.price)),
             target.eq(new_target),
+            take_profit_level.eq(new_level),
+            remaining.eq(new_remaining),
         ))
         .execute(connection)?;
 
     Ok(true)
 }
 
-fn check_start(
+/// Sells (part of) the remaining position with `EXIT_ALGORITHM`, and closes
+/// the trade once the position has been sold
+///
+/// Returns whether to continue the trade, i.e. if some of the position is
+/// still left.
+fn continue_exit(
+    connection: &mut PgConnection,
+    trade: &Trade,
+    market: &Market,
+    order_book: &mut OrderBook,
+    highest_bid_p: &BigDecimal,
+    reason: &str,
+) -> Result<bool, BotError> {
+    use crate::schema::trades::dsl::*;
+
+    if EXIT_ALGORITHM == ExitAlgorithm::Immediate {
+        create_execution(
+            connection,
+            trade,
+            highest_bid_p.clone(),
+            trade.remaining.clone(),
+            reason,
+        )?;
+        close_trade(connection, trade, reason)?;
+        return Ok(false);
+    }
+
+    let now = Utc::now();
+    let started = trade.exit_started_at.unwrap_or(now);
+
+    // position size in quote currency
+    let position = (&trade.size / get_open_price(trade)?).round(DECIMAL_SCALE);
+    let unsold = &position * &trade.remaining;
+
+    let amount = if EXIT_ALGORITHM == ExitAlgorithm::Twap && trade.exit_slices < TWAP_SLICES {
+        let due = started + Duration::seconds(TWAP_INTERVAL * trade.exit_slices as i64);
+        if now < due {
+            return Ok(true);
+        }
+        // a slice too small to trade would never be sold, so sell it all
+        let slice =
+            market.round_amount(&(&unsold / BigDecimal::from(TWAP_SLICES - trade.exit_slices)));
+        if slice.is_zero() {
+            market.round_amount(&unsold)
+        } else {
+            slice
+        }
+    } else {
+        market.round_amount(&unsold)
+    };
+
+    let min_price = if now - started > Duration::seconds(MAX_EXIT_TIME) {
+        BigDecimal::zero()
+    } else {
+        highest_bid_p * (BigDecimal::from(1) - decimal(MAX_EXIT_IMPACT)?)
+    };
+
+    let fills = walk_bids(order_book, &amount, &min_price);
+
+    let mut portions: Vec<BigDecimal> = fills
+        .iter()
+        .map(|(_, fill)| (fill / &position).round(DECIMAL_SCALE))
+        .collect();
+    let sold: BigDecimal = portions.iter().sum();
+    let left = &trade.remaining - &sold;
+
+    // sell the rounding leftovers with the last fill
+    let new_remaining = if left < decimal(MIN_REMAINING)? {
+        if let Some(last) = portions.last_mut() {
+            *last += left;
+        }
+        BigDecimal::zero()
+    } else {
+        left
+    };
+
+    for ((price, _), portion) in fills.iter().zip(portions) {
+        create_execution(connection, trade, price.clone(), portion, reason)?;
+    }
+
+    if let Some(avg_price) = get_average_price(&fills) {
+        log_trade(
+            trade,
+            format!(
+                "sold {:.3} of position, average: {}, impact: {:.3}%, remaining: {:.3}",
+                to_f64(&sold),
+                avg_price,
+                (1.0 - to_f64(&(&avg_price / highest_bid_p))) * 100.0,
+                to_f64(&new_remaining)
+            ),
+        );
+    }
+
+    // a slice that sold nothing is tried again rather than counted
+    let new_slices = if fills.is_empty() {
+        trade.exit_slices
+    } else {
+        trade.exit_slices + 1
+    };
+
+    let exiting_trade: Trade = diesel::update(trade)
+        .set((
+            updated_at.eq(now),
+            highest_bid.eq(Some(highest_bid_p.clone())),
+            remaining.eq(new_remaining.clone()),
+            exit_slices.eq(new_slices),
+        ))
+        .get_result(connection)?;
+
+    if new_remaining > BigDecimal::zero() {
+        return Ok(true);
+    }
+
+    close_trade(connection, &exiting_trade, reason)?;
+
+    Ok(false)
+}
+
+/// Sells the TWAP slice that is due, if the trade is being closed, without
+/// an order book update
+///
+/// Returns whether to continue the trade like `continue_exit`.
+fn continue_twap(
+    connection: &mut PgConnection,
+    trade: &Trade,
+    market: &Market,
+    order_book: &mut OrderBook,
+) -> Result<bool, BotError> {
+    use crate::schema::trades::dsl::trades;
+
+    if EXIT_ALGORITHM != ExitAlgorithm::Twap || market.is_frozen || market.post_only {
+        return Ok(true);
+    }
+
+    let current_trade: Trade = trades.find(trade.id).first(connection)?;
+    match (current_trade.exit_reason.clone(), order_book.highest_bid()) {
+        (Some(reason), Some(highest_bid_ob)) => continue_exit(
+            connection,
+            &current_trade,
+            market,
+            order_book,
+            &highest_bid_ob.price,
+            &reason,
+        ),
+        _ => Ok(true),
+    }
+}
+
+/// Closes the trade after the position has been sold
+///
+/// The trade close price and profit are computed over all its executions,
+/// including the earlier take profit exits.
+fn close_trade(connection: &mut PgConnection, trade: &Trade, reason: &str) -> Result<(), BotError> {
+    use crate::schema::trades::dsl::*;
+
+    let executions = get_executions(connection, trade)?;
+    let exit_price = get_exit_price(&executions);
+    let trade_profit = get_trade_profit(trade, &executions);
+
+    log_trade(
+        trade,
+        format!(
+            "closing trade ({}), close: {:?}, open: {:?}, profit: {:.3}%",
+            reason,
+            exit_price.as_ref().map(to_f64),
+            trade.open.as_ref().map(to_f64),
+            trade_profit.unwrap_or(0.0) * 100.0
+        ),
+    );
+
+    let closed_trade: Trade = diesel::update(trade)
+        .set((
+            close_at.eq(Utc::now()),
+            close.eq(exit_price),
+            profit.eq(trade_profit),
+            remaining.eq(BigDecimal::zero()),
+            close_reason.eq(Some(reason)),
+        ))
+        .get_result(connection)?;
+
+    if let Some(cooldown) = register_trade_result(connection, &closed_trade)? {
+        log_trade(
+            &closed_trade,
+            format!(
+                "cooling down until {} after {} losses",
+                cooldown.until, cooldown.losses
+            ),
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks whether an open trade should be closed because of time, returns
+/// the close reason
+///
+/// `exit_price` is the expected price for selling the remaining position.
+fn check_time_exit(
+    trade: &Trade,
+    exit_price: &BigDecimal,
+) -> Result<Option<&'static str>, BotError> {
+    let held = Utc::now() - trade.open_at;
+
+    if held > Duration::seconds(MAX_HOLDING_TIME) {
+        return Ok(Some("max_holding_time"));
+    }
+
+    let progress_tgt = get_open_price(trade)? * (BigDecimal::from(1) + decimal(MIN_PROGRESS)?);
+    if held > Duration::seconds(MIN_PROGRESS_TIME) && *exit_price < progress_tgt {
+        return Ok(Some("no_progress"));
+    }
+
+    Ok(None)
+}
+
+/// Closes a trade that didn't start before its entry expired
+///
+/// The trade is kept with the close reason `entry_expired`, unlike the
+/// trades that are deleted when they don't start, so that expired entries
+/// can be counted. It has no profit and doesn't count for cooldowns.
+fn expire_trade(connection: &mut PgConnection, trade: &Trade) -> Result<(), BotError> {
+    use crate::schema::trades::dsl::*;
+
+    log_trade(trade, "abandoning trade (entry_expired)".to_string());
+
+    diesel::update(trade)
+        .set((
+            close_at.eq(Utc::now()),
+            remaining.eq(BigDecimal::zero()),
+            close_reason.eq(Some("entry_expired")),
+        ))
+        .execute(connection)
+        .context("closing expired trade")?;
+
+    Ok(())
+}
+
+/// Checks whether a trade that hasn't started has been waiting for too long
+///
+/// Trade open time is its creation time until the trade is started.
+fn is_entry_expired(trade: &Trade) -> bool {
+    Utc::now() - trade.open_at > Duration::seconds(MAX_PENDING_TIME)
+}
+
+/// Posts (or reprices) the maker entry bid inside the spread
+///
+/// The bid isn't sent to the exchange, its fill is simulated from the order
+/// book updates in `check_start`.
+fn post_entry_bid(
     connection: &mut PgConnection,
     trade: &Trade,
-    highest_bid: f64,
-    lowest_ask: f64,
-) -> Result<(bool, Option<f64>), Box<dyn std::error::Error>> {
-    let target: f64 = trade.target as f64;
+    market: &Market,
+    highest_bid: &BigDecimal,
+    lowest_ask: &BigDecimal,
+) -> Result<Trade, BotError> {
+    use crate::schema::trades::dsl::{entry_bid, entry_bid_at, entry_bids};
+
+    let bid = market
+        .round_price(&(highest_bid + (lowest_ask - highest_bid) * decimal(ENTRY_IMPROVEMENT)?));
+
+    log_trade(
+        trade,
+        format!(
+            "posting entry bid {}, highest bid: {}, lowest ask: {}",
+            bid, highest_bid, lowest_ask
+        ),
+    );
+
+    diesel::update(trade)
+        .set((
+            entry_bid.eq(Some(bid)),
+            entry_bid_at.eq(Some(Utc::now())),
+            entry_bids.eq(trade.entry_bids + 1),
+        ))
+        .get_result(connection)
+        .context("posting entry bid")
+}
+
+/// Reloads the target and stop loss of `trade`, which `update_trades` keeps
+/// raising, without touching the entry bid state kept in it
+fn refresh_target(connection: &mut PgConnection, trade: &mut Trade) -> Result<(), BotError> {
+    use crate::schema::trades::dsl::{stop_loss, target, trades};
+
+    let (target_p, stop_loss_p) = trades
+        .find(trade.id)
+        .select((target, stop_loss))
+        .first::<(BigDecimal, f32)>(connection)
+        .context("refreshing target")?;
+    trade.target = target_p;
+    trade.stop_loss = stop_loss_p;
+
+    Ok(())
+}
+
+/// Checks whether to start the trade, and posts the maker entry bid
+///
+/// The entry bid state is kept in `trade`, which is replaced with the
+/// stored trade when the bid is posted.
+fn check_start(
+    connection: &mut PgConnection,
+    trade: &mut Trade,
+    market: &Market,
+    order_book: &OrderBook,
+    highest_bid: &BigDecimal,
+    lowest_ask: &BigDecimal,
+) -> Result<(bool, Option<BigDecimal>), BotError> {
+    refresh_target(connection, trade)?;
+    let target: &BigDecimal = &trade.target;
 
     // if highest bid is below the target, don't start trade
     if highest_bid < target {
@@ -195,41 +707,142 @@ fn check_start(
 
     // if highest bid is too high compared to target, don't start trade
     // something strange is happening
-    if highest_bid / target > (1.0 + START_ABOVE_TARGET) {
+    if *highest_bid > target * (BigDecimal::from(1) + decimal(START_ABOVE_TARGET)?) {
         log_trade_hb(trade, "won't start trade (too high)", highest_bid, target);
         return Ok((false, None));
     }
 
-    let spread: f64 = (lowest_ask - highest_bid) / highest_bid;
+    // frozen markets may open again, wait for that until the entry expires
+    if market.is_frozen {
+        log_trade(trade, "won't start trade (market frozen)".to_string());
+        return Ok((true, None));
+    }
+
+    let amount = market.round_amount(&(&trade.size / lowest_ask));
+    if !market.is_order_valid(lowest_ask, &amount) {
+        log_trade(
+            trade,
+            format!("won't start trade (below minimum order), amount {}", amount),
+        );
+        return Ok((false, None));
+    }
+
+    // if the asks are too thin for the position, wait for more liquidity
+    let depth = order_book.depth(OrderType::Ask, DEPTH_RANGE)?;
+    if depth < &amount * decimal(MIN_DEPTH)? {
+        log_trade(
+            trade,
+            format!("won't start trade (too thin), depth {:.4}", to_f64(&depth)),
+        );
+        return Ok((true, None));
+    }
+
+    if ENTRY_MODE == EntryMode::Maker {
+        // lowest ask has come down to the entry bid, so it would have filled
+        if let Some(bid) = &trade.entry_bid {
+            if lowest_ask <= bid {
+                let buy_trade = do_buy(connection, trade, bid.clone(), "maker")?;
+                log_trade_hb(&buy_trade, "starting trade (maker)", highest_bid, target);
+                return Ok((true, Some(highest_bid.clone())));
+            }
+        }
+
+        let is_bid_waiting = trade
+            .entry_bid_at
+            .map(|at| Utc::now() - at < Duration::seconds(ENTRY_REPRICE_TIME))
+            .unwrap_or(false);
+        if is_bid_waiting {
+            return Ok((true, None));
+        }
+
+        // post-only markets don't allow taking, so keep on repricing
+        if trade.entry_bids <= ENTRY_MAX_REPRICES || market.post_only {
+            *trade = post_entry_bid(connection, trade, market, highest_bid, lowest_ask)?;
+            return Ok((true, None));
+        }
+
+        log_trade(trade, "entry bid not filled, taking".to_string());
+    }
+
+    if market.post_only {
+        log_trade(trade, "won't start trade (market post-only)".to_string());
+        return Ok((true, None));
+    }
+
+    // buying the position walks up the asks, so compare the expected fill
+    // price rather than the lowest ask to the highest bid
+    let fill_price = match order_book.fill_price(OrderType::Ask, &amount) {
+        Some(price) => price,
+        None => return Ok((true, None)),
+    };
+    let spread: f64 = to_f64(&((&fill_price - highest_bid) / highest_bid));
     if spread > MAX_SPREAD {
         log_trade(trade, format!("spread too high, not buying, {}", spread));
         return Ok((true, None));
     }
 
-    let buy_trade = do_buy(connection, trade, lowest_ask as f32)?;
+    let buy_trade = do_buy(connection, trade, fill_price.clone(), "taker")?;
 
-    log_trade_hb(&buy_trade, "starting trade", highest_bid, target);
+    log_trade_hb(&buy_trade, "starting trade (taker)", highest_bid, target);
+    log_trade(
+        &buy_trade,
+        format!(
+            "expected fill {}, imbalance {:?}, microprice {:?}",
+            fill_price,
+            order_book.imbalance(DEPTH_RANGE)?,
+            order_book.microprice().as_ref().map(to_f64)
+        ),
+    );
 
-    Ok((true, Some(highest_bid)))
+    Ok((true, Some(highest_bid.clone())))
 }
 
+/// Whether to continue the trade, and the order book, buy value and previous
+/// highest bid carried over to the next message
+type MessageState = (
+    bool,
+    Option<OrderBook>,
+    Option<BigDecimal>,
+    Option<BigDecimal>,
+);
+
 fn do_message(
     connection: &mut PgConnection,
-    trade: &Trade,
-    msg: Value,
+    trade: &mut Trade,
+    market: &Market,
+    event: BookEvent,
     mut order_book: Option<OrderBook>,
-    mut buy_value: Option<f32>,
-    mut prev_highest_bid: Option<f64>,
-) -> Result<(bool, Option<OrderBook>, Option<f32>, Option<f64>), Box<dyn std::error::Error>> {
-    let command: String = serde_json::from_value(msg[0].clone()).unwrap();
-    order_book = match command.as_str() {
+    mut buy_value: Option<BigDecimal>,
+    mut prev_highest_bid: Option<BigDecimal>,
+) -> Result<MessageState, BotError> {
+    match event {
         // update whole order book
-        "i" => parse_orderbook(msg[1].clone()),
-        "o" => update_orderbook(order_book, msg),
-        _ => order_book,
+        BookEvent::Snapshot {
+            order_book: snapshot,
+            ..
+        } => order_book = Some(snapshot),
+        BookEvent::Update {
+            order_type,
+            price,
+            size,
+        } => {
+            if let Some(ob) = order_book.as_mut() {
+                ob.update(order_type, price, size);
+            }
+        }
+        // the candles are built by collect_trades, the trade is stored here too
+        // so that the traded market has its trades even without it
+        BookEvent::Trade(public_trade) => {
+            if let Some(row) =
+                to_market_trade(&trade.exchange, &trade.base, &trade.quote, &public_trade)
+            {
+                insert_market_trade(connection, &row)?;
+            }
+        }
+        BookEvent::Candle(_) => (),
     };
-    match order_book.clone() {
-        Some(ob) => match (find_middle(ob), buy_value, prev_highest_bid) {
+    if let Some(ob) = order_book.as_mut() {
+        match (ob.middle(), buy_value.clone(), prev_highest_bid.clone()) {
             // first loop round
             (
                 OrderBookMiddle {
@@ -239,12 +852,21 @@ fn do_message(
                 None,
                 _,
             ) => {
-                let (ct, phb) =
-                    check_start(connection, trade, highest_bid.price, lowest_ask.price)?;
+                let (ct, phb) = check_start(
+                    connection,
+                    trade,
+                    market,
+                    ob,
+                    &highest_bid.price,
+                    &lowest_ask.price,
+                )?;
+                let started = phb.is_some();
                 prev_highest_bid = phb;
-                buy_value = Some(lowest_ask.price as f32);
-                if phb == None {
-                    return Ok((ct, None, None, None));
+                buy_value = Some(lowest_ask.price);
+                if !started {
+                    // keep the order book if the trade may still start later
+                    let ob = if ct { order_book } else { None };
+                    return Ok((ct, ob, None, None));
                 }
             }
             (
@@ -254,19 +876,18 @@ fn do_message(
                 },
                 Some(buy_value),
                 Some(phb),
-            ) if (phb - highest_bid.price).abs() > F64_EPSILON => {
-                prev_highest_bid = Some(highest_bid.price);
+            ) if phb != highest_bid.price => {
+                prev_highest_bid = Some(highest_bid.price.clone());
 
                 let continue_trade =
-                    check_sell(connection, trade, highest_bid, lowest_ask).unwrap();
+                    check_sell(connection, trade, market, ob, highest_bid, lowest_ask)?;
                 if !continue_trade {
                     return Ok((false, order_book, Some(buy_value), prev_highest_bid));
                 }
             }
             _ => (),
-        },
-        _ => (),
-    };
+        }
+    }
 
     Ok((true, order_book, buy_value, prev_highest_bid))
 }
@@ -275,15 +896,218 @@ pub fn log_trade(trade: &Trade, message: String) {
     println!("TRADE {}, {}: {}", trade.id, trade.quote, message);
 }
 
-pub fn log_trade_hb(trade: &Trade, message: &str, highest_bid: f64, target: f64) {
+pub fn log_trade_hb(trade: &Trade, message: &str, highest_bid: &BigDecimal, target: &BigDecimal) {
     log_trade(
         trade,
         format!(
-            "{}, highest bid: {:?}, target: {:?}: {:.3}%",
+            "{}, highest bid: {}, target: {}: {:.3}%",
             message,
             highest_bid,
             target,
-            highest_bid / target
+            to_f64(&(highest_bid / target))
         ),
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_connection;
+    use crate::trade::create_trade;
+    use crate::trade::tests::trade;
+    use std::str::FromStr;
+
+    fn d(s: &str) -> BigDecimal {
+        BigDecimal::from_str(s).unwrap()
+    }
+
+    fn market() -> Market {
+        Market {
+            base: "USDT".to_string(),
+            quote: "TEST_ENTRY".to_string(),
+            price_precision: 2,
+            amount_precision: 4,
+            min_order: d("1"),
+            is_frozen: false,
+            post_only: false,
+            updated_at: Utc::now(),
+            exchange: "poloniex".to_string(),
+        }
+    }
+
+    fn order_book(highest_bid: &str, lowest_ask: &str) -> OrderBook {
+        let mut order_book = OrderBook::new();
+        order_book.update(OrderType::Bid, d(highest_bid), d("10"));
+        order_book.update(OrderType::Ask, d(lowest_ask), d("10"));
+        order_book
+    }
+
+    fn pending_trade(connection: &mut PgConnection) -> Trade {
+        let shortlist = Shortlist {
+            quote: "TEST_ENTRY".to_string(),
+            timestamp: Utc::now(),
+            average: d("100"),
+            target: d("99.5"),
+            confidence: 1.0,
+            stop_loss: 0.02,
+            exchange: "poloniex".to_string(),
+        };
+        create_trade(connection, &shortlist).unwrap()
+    }
+
+    fn start(
+        connection: &mut PgConnection,
+        trade: &mut Trade,
+        order_book: &OrderBook,
+    ) -> Result<(bool, Option<BigDecimal>), BotError> {
+        let highest_bid = order_book.highest_bid().unwrap().price;
+        let lowest_ask = order_book.lowest_ask().unwrap().price;
+        check_start(
+            connection,
+            trade,
+            &market(),
+            order_book,
+            &highest_bid,
+            &lowest_ask,
+        )
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn entry_bid_test() {
+        use crate::schema::trades::dsl::{target, trades};
+
+        let (_lock, mut connection) = test_connection();
+        assert_eq!(ENTRY_MODE, EntryMode::Maker);
+
+        connection.test_transaction::<_, BotError, _>(|conn| {
+            let book = order_book("100", "100.2");
+            let mut t = pending_trade(conn);
+
+            // the bid is posted inside the spread and waits there
+            assert_eq!(start(conn, &mut t, &book)?, (true, None));
+            assert_eq!(t.entry_bid, Some(d("100.05")));
+            assert_eq!(t.entry_bids, 1);
+
+            // a target raised by update_trades is picked up while waiting
+            diesel::update(trades.find(t.id))
+                .set(target.eq(d("99.8")))
+                .execute(conn)?;
+            assert_eq!(start(conn, &mut t, &book)?, (true, None));
+            assert_eq!(t.target, d("99.8"));
+            assert_eq!(t.entry_bids, 1);
+
+            // repriced every ENTRY_REPRICE_TIME until the reprices run out
+            for bids in 2..=ENTRY_MAX_REPRICES + 1 {
+                t.entry_bid_at = Some(Utc::now() - Duration::seconds(ENTRY_REPRICE_TIME + 1));
+                assert_eq!(start(conn, &mut t, &book)?, (true, None));
+                assert_eq!(t.entry_bids, bids);
+            }
+
+            t.entry_bid_at = Some(Utc::now() - Duration::seconds(ENTRY_REPRICE_TIME + 1));
+            assert_eq!(start(conn, &mut t, &book)?, (true, Some(d("100"))));
+            let started: Trade = trades.find(t.id).first(conn)?;
+            assert_eq!(started.entry_type.as_deref(), Some("taker"));
+            assert_eq!(started.open, Some(d("100.2")));
+
+            // the ask coming down to the bid fills it
+            let mut t = pending_trade(conn);
+            assert_eq!(start(conn, &mut t, &book)?, (true, None));
+            let book = order_book("100", "100.05");
+            assert_eq!(start(conn, &mut t, &book)?, (true, Some(d("100"))));
+            let started: Trade = trades.find(t.id).first(conn)?;
+            assert_eq!(started.entry_type.as_deref(), Some("maker"));
+            assert_eq!(started.open, Some(d("100.05")));
+            assert_eq!(started.entry_bids, 1);
+            Ok(())
+        });
+    }
+
+    fn held(seconds: i64) -> Trade {
+        let mut t = trade(Some("100"));
+        t.open_at = Utc::now() - Duration::seconds(seconds);
+        t
+    }
+
+    // needs TEST_DATABASE_URL, run with cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn exit_slices_test() {
+        use crate::schema::trades::dsl::{exit_reason, open, trades};
+
+        let (_lock, mut connection) = test_connection();
+
+        connection.test_transaction::<_, BotError, _>(|conn| {
+            let pending = pending_trade(conn);
+            let t: Trade = diesel::update(&pending)
+                .set((open.eq(Some(d("100"))), exit_reason.eq(Some("stop_loss"))))
+                .get_result(conn)?;
+
+            // the bids are too far below the highest bid to sell to
+            let mut book = order_book("100", "100.2");
+            book.update(OrderType::Bid, d("100"), d("0"));
+            book.update(OrderType::Bid, d("90"), d("10"));
+            assert!(continue_exit(
+                conn,
+                &t,
+                &market(),
+                &mut book,
+                &d("100"),
+                "stop_loss"
+            )?);
+            let t: Trade = trades.find(t.id).first(conn)?;
+            assert_eq!(t.exit_slices, 0);
+            assert!(get_executions(conn, &t)?.is_empty());
+
+            let mut book = order_book("100", "100.2");
+            book.update(OrderType::Bid, d("100"), d("0.5"));
+            assert!(continue_exit(
+                conn,
+                &t,
+                &market(),
+                &mut book,
+                &d("100"),
+                "stop_loss"
+            )?);
+            let t: Trade = trades.find(t.id).first(conn)?;
+            assert_eq!(t.exit_slices, 1);
+            assert_eq!(t.remaining, d("0.5"));
+            assert_eq!(get_executions(conn, &t)?.len(), 1);
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn time_exit_test() {
+        let below = BigDecimal::from(100);
+        let above = decimal(100.0 * (1.0 + MIN_PROGRESS) + 0.01).unwrap();
+
+        assert_eq!(check_time_exit(&held(60), &below).unwrap(), None);
+
+        // no progress is only checked after MIN_PROGRESS_TIME
+        let t = held(MIN_PROGRESS_TIME + 60);
+        assert_eq!(check_time_exit(&t, &below).unwrap(), Some("no_progress"));
+        assert_eq!(check_time_exit(&t, &above).unwrap(), None);
+
+        let t = held(MAX_HOLDING_TIME + 60);
+        assert_eq!(
+            check_time_exit(&t, &above).unwrap(),
+            Some("max_holding_time")
+        );
+
+        let mut t = held(MIN_PROGRESS_TIME + 60);
+        t.open = None;
+        assert!(check_time_exit(&t, &below).is_err());
+    }
+
+    #[test]
+    fn entry_expired_test() {
+        let mut t = trade(None);
+        t.open_at = Utc::now() - Duration::seconds(MAX_PENDING_TIME - 60);
+        assert!(!is_entry_expired(&t));
+
+        t.open_at = Utc::now() - Duration::seconds(MAX_PENDING_TIME + 60);
+        assert!(is_entry_expired(&t));
+    }
+}